@@ -0,0 +1,27 @@
+use actix_web::{post, web, HttpResponse, Scope};
+use serde_json::json;
+use crate::app::app_state::AppState;
+use crate::errors::auth::RegisterError;
+use crate::errors::validation::EnglishMessages;
+use crate::forms::auth::RegisterForm;
+
+#[post("/register")]
+async fn register(state: web::Data<AppState>, form: web::Json<RegisterForm>) -> HttpResponse {
+    match state.services.auth.register(&form).await {
+        Ok(user) => HttpResponse::Created().json(json!({
+            "email": user.email,
+            "status": format!("{:?}", user.status).to_lowercase(),
+        })),
+        Err(RegisterError::Validation(errors)) => {
+            HttpResponse::UnprocessableEntity().json(errors.to_json(&EnglishMessages))
+        }
+        Err(e) => HttpResponse::BadRequest().json(json!({
+            "errors": [{ "code": e.kind(), "message": e.to_string() }],
+        })),
+    }
+}
+
+pub fn get_scope() -> Scope {
+    web::scope("/api/auth")
+        .service(register)
+}
@@ -0,0 +1,38 @@
+use actix_web::{get, web, HttpResponse};
+use serde_json::{json, Map, Value};
+use crate::app::app_state::AppState;
+
+/// The process is up and able to answer
+#[get("/healthz")]
+async fn healthz() -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "status": "ok" }))
+}
+
+/// The server can handle traffic: no migration is running and every repository answers
+#[get("/readyz")]
+async fn readyz(state: web::Data<AppState>) -> HttpResponse {
+    let readiness = state.services.health.readiness().await;
+
+    let mut components = Map::new();
+    for component in &readiness.components {
+        let value = match component.available {
+            true => json!({ "status": "ok" }),
+            false => json!({ "status": "unavailable" }),
+        };
+        components.insert(component.name.to_string(), value);
+    }
+    components.insert("migrations".to_string(), match readiness.migrating {
+        true => json!({ "status": "running" }),
+        false => json!({ "status": "ok" }),
+    });
+
+    let body = json!({
+        "status": if readiness.is_ready() { "ok" } else { "unavailable" },
+        "components": Value::Object(components),
+    });
+
+    match readiness.is_ready() {
+        true => HttpResponse::Ok().json(body),
+        false => HttpResponse::ServiceUnavailable().json(body),
+    }
+}
@@ -0,0 +1,13 @@
+use actix_web::{get, web, HttpResponse};
+use actix_web::http::header::ContentType;
+use crate::app::app_state::AppState;
+
+#[get("/metrics")]
+async fn metrics(state: web::Data<AppState>) -> HttpResponse {
+    let services = &state.services;
+    services.metrics.active_sessions.set(services.auth.active_sessions().await as i64);
+
+    HttpResponse::Ok()
+        .content_type(ContentType::plaintext())
+        .body(services.metrics.render())
+}
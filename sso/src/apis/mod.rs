@@ -0,0 +1,5 @@
+pub mod auth;
+pub mod health;
+pub mod metrics;
+pub mod oauth;
+pub mod scim;
\ No newline at end of file
@@ -0,0 +1,193 @@
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Scope};
+use actix_web::http::header::{ContentType, AUTHORIZATION, CACHE_CONTROL, LOCATION, WWW_AUTHENTICATE};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use maud::html;
+use serde_json::json;
+use crate::app::app_state::AppState;
+use crate::app::session::Session;
+use crate::errors::oauth::OAuthError;
+use crate::forms::oauth::{AuthorizeQuery, ConsentForm, TokenForm, TokenRequestForm};
+use crate::objects::application::Application;
+use crate::services::oauth::Authorization;
+use crate::views::auth::login_redirect;
+use crate::views::consent::consent_page;
+use crate::views::nav::get_nav;
+
+fn error_response(error: OAuthError) -> HttpResponse {
+    let mut builder = match error {
+        OAuthError::InvalidClient => HttpResponse::Unauthorized(),
+        OAuthError::ServerError => HttpResponse::InternalServerError(),
+        _ => HttpResponse::BadRequest(),
+    };
+    if let OAuthError::InvalidClient = error {
+        builder.insert_header((WWW_AUTHENTICATE, r#"Basic realm="sso""#));
+    }
+    builder.insert_header((CACHE_CONTROL, "no-store"))
+        .json(json!({
+            "error": error.kind(),
+            "error_description": error.to_string(),
+        }))
+}
+
+/// Authenticate the client with basic auth, or with the credentials sent in the form
+async fn client(state: &AppState, req: &HttpRequest, client_id: Option<&str>, client_secret: Option<&str>) -> Result<Application, OAuthError> {
+    let basic = req.headers().get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|value| STANDARD.decode(value.trim()).ok())
+        .and_then(|value| String::from_utf8(value).ok());
+
+    match (&basic, client_id, client_secret) {
+        (Some(basic), _, _) => {
+            let (id, secret) = basic.split_once(':').ok_or(OAuthError::InvalidClient)?;
+            state.services.oauth.authenticate_client(id, secret).await
+        }
+        (None, Some(id), Some(secret)) => state.services.oauth.authenticate_client(id, secret).await,
+        _ => Err(OAuthError::InvalidClient),
+    }
+}
+
+/// Send the browser back to the application, with the code or the error
+fn authorization_redirect(query: &AuthorizeQuery, params: &[(&str, &str)]) -> HttpResponse {
+    let mut params = params.to_vec();
+    if let Some(state) = &query.state {
+        params.push(("state", state));
+    }
+    let separator = if query.redirect_uri.contains('?') { '&' } else { '?' };
+    let location = format!("{}{}{}", query.redirect_uri, separator, serde_urlencoded::to_string(&params).unwrap_or_default());
+    HttpResponse::Found().insert_header((LOCATION, location)).finish()
+}
+
+/// The authorization code flow, the user has to be logged in, allowed to use the application
+/// and to have agreed to share the requested scopes
+async fn authorization(
+    state: &AppState,
+    req: &HttpRequest,
+    session: Option<web::ReqData<Session>>,
+    query: &AuthorizeQuery,
+    consent: Option<&ConsentForm>,
+) -> HttpResponse {
+    // Without a valid redirect uri, the error can only be shown here
+    let client = match state.services.oauth.authorization_client(query).await {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::warn!(?query, error = %e, "authorization request refused");
+            return HttpResponse::BadRequest()
+                .content_type(ContentType::html())
+                .body(html! {
+                    (get_nav(session.as_deref()))
+                    ("Error : ") (e)
+                });
+        }
+    };
+    let Some(session) = session else {
+        return login_redirect(req);
+    };
+    // a consent posted by another site would let it authorize itself on behalf of the user
+    if consent.is_some_and(|consent| consent.csrf_token != session.csrf_token()) {
+        tracing::warn!(client_id = %client.client_id, "consent posted without the session token");
+        return HttpResponse::Forbidden()
+            .content_type(ContentType::html())
+            .body(html! {
+                (get_nav(Some(&session)))
+                "Error : this page expired, start again from the application"
+            });
+    }
+    let consented = match consent {
+        Some(consent) if consent.decision != "allow" => {
+            tracing::info!(client_id = %client.client_id, "consent refused");
+            return authorization_redirect(query, &[("error", "access_denied"), ("error_description", "The user refused to share the requested information")]);
+        }
+        consent => consent.is_some(),
+    };
+    match state.services.oauth.authorize(&session.user, &session.token, &client, query, consented).await {
+        Ok(Authorization::Code(code)) => authorization_redirect(query, &[("code", &code)]),
+        Ok(Authorization::ConsentRequired(scopes)) => HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .body(consent_page(&session, &client, &scopes)),
+        Err(e) => authorization_redirect(query, &[("error", e.kind()), ("error_description", &e.to_string())]),
+    }
+}
+
+#[get("/authorize")]
+async fn authorize(state: web::Data<AppState>, req: HttpRequest, session: Option<web::ReqData<Session>>, query: web::Query<AuthorizeQuery>) -> HttpResponse {
+    authorization(&state, &req, session, &query, None).await
+}
+
+/// The answer of the consent page, posted with the query of the authorization request
+#[post("/authorize")]
+async fn answer_consent(state: web::Data<AppState>, req: HttpRequest, session: Option<web::ReqData<Session>>, query: web::Query<AuthorizeQuery>, form: web::Form<ConsentForm>) -> HttpResponse {
+    authorization(&state, &req, session, &query, Some(&form)).await
+}
+
+#[post("/token")]
+async fn token(state: web::Data<AppState>, req: HttpRequest, form: web::Form<TokenForm>) -> HttpResponse {
+    let client = match client(&state, &req, form.client_id.as_deref(), form.client_secret.as_deref()).await {
+        Ok(client) => client,
+        Err(e) => return error_response(e),
+    };
+    match state.services.oauth.token(&client, &form).await {
+        Ok(token) => {
+            let mut body = json!({
+                "access_token": token.access_token,
+                "token_type": "Bearer",
+                "expires_in": token.expires_in,
+                "scope": token.scope,
+            });
+            if let Some(refresh_token) = token.refresh_token {
+                body["refresh_token"] = json!(refresh_token);
+            }
+            HttpResponse::Ok()
+                .insert_header((CACHE_CONTROL, "no-store"))
+                .json(body)
+        }
+        Err(e) => error_response(e),
+    }
+}
+
+/// RFC 7662, for the resource servers registered as applications
+#[post("/introspect")]
+async fn introspect(state: web::Data<AppState>, req: HttpRequest, form: web::Form<TokenRequestForm>) -> HttpResponse {
+    if let Err(e) = client(&state, &req, form.client_id.as_deref(), form.client_secret.as_deref()).await {
+        return error_response(e);
+    }
+    let body = match state.services.oauth.introspect(&form.token).await {
+        Some(claims) => json!({
+            "active": true,
+            "scope": claims.scope,
+            "client_id": claims.client_id,
+            "token_type": "Bearer",
+            "exp": claims.exp,
+            "iat": claims.iat,
+            "sub": claims.sub,
+            "iss": claims.iss,
+            "jti": claims.jti,
+        }),
+        None => json!({ "active": false }),
+    };
+    HttpResponse::Ok()
+        .insert_header((CACHE_CONTROL, "no-store"))
+        .json(body)
+}
+
+/// RFC 7009, answers the same way whether the token was valid or not
+#[post("/revoke")]
+async fn revoke(state: web::Data<AppState>, req: HttpRequest, form: web::Form<TokenRequestForm>) -> HttpResponse {
+    match client(&state, &req, form.client_id.as_deref(), form.client_secret.as_deref()).await {
+        Ok(client) => {
+            state.services.oauth.revoke(&client, &form.token).await;
+            HttpResponse::Ok().finish()
+        }
+        Err(e) => error_response(e),
+    }
+}
+
+pub fn get_scope() -> Scope {
+    web::scope("/oauth")
+        .service(authorize)
+        .service(answer_consent)
+        .service(token)
+        .service(introspect)
+        .service(revoke)
+}
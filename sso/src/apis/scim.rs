@@ -0,0 +1,188 @@
+use actix_web::{delete, get, patch, post, put, web, Error, HttpMessage, HttpResponse, Scope};
+use actix_web::body::BoxBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{AUTHORIZATION, LOCATION};
+use actix_web::http::StatusCode;
+use actix_web::middleware::{from_fn, Next};
+use serde_json::{json, Value};
+use crate::app::app_state::AppState;
+use crate::errors::scim::ScimError;
+use crate::forms::scim::ScimQuery;
+use crate::services::scim::ERROR_SCHEMA;
+
+const CONTENT_TYPE: &str = "application/scim+json";
+
+/// The provisioning client making the request
+#[derive(Clone)]
+pub struct ScimClient(pub String);
+
+fn resource_response(status: StatusCode, resource: Value) -> HttpResponse {
+    let mut builder = HttpResponse::build(status);
+    builder.content_type(CONTENT_TYPE);
+    if let Some(location) = resource["meta"]["location"].as_str().filter(|_| status == StatusCode::CREATED) {
+        builder.insert_header((LOCATION, location.to_string()));
+    }
+    builder.body(resource.to_string())
+}
+
+fn error_response(error: ScimError) -> HttpResponse {
+    let mut body = json!({
+        "schemas": [ERROR_SCHEMA],
+        "status": error.status().to_string(),
+        "detail": error.to_string(),
+    });
+    if let Some(scim_type) = error.scim_type() {
+        body["scimType"] = json!(scim_type);
+    }
+    HttpResponse::build(StatusCode::from_u16(error.status()).unwrap())
+        .content_type(CONTENT_TYPE)
+        .body(body.to_string())
+}
+
+fn respond(status: StatusCode, result: Result<Value, ScimError>) -> HttpResponse {
+    match result {
+        Ok(resource) => resource_response(status, resource),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Clients send `application/scim+json`, which the json extractor refuses
+fn parse(body: &[u8]) -> Result<Value, ScimError> {
+    serde_json::from_slice(body).map_err(|e| ScimError::InvalidSyntax(e.to_string()))
+}
+
+/// Only provisioning clients with a configured bearer token can use the API
+async fn scim_auth(req: ServiceRequest, next: Next<BoxBody>) -> Result<ServiceResponse<BoxBody>, Error> {
+    let state = req.app_data::<web::Data<AppState>>().unwrap();
+    let client = req.headers().get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .and_then(|token| state.services.scim.authenticate(token.trim()))
+        .map(str::to_string);
+
+    match client {
+        Some(client) => {
+            tracing::Span::current().record("user", client.as_str());
+            req.extensions_mut().insert(ScimClient(client));
+            next.call(req).await
+        }
+        None => {
+            tracing::info!("rejected SCIM request without a valid token");
+            let response = error_response(ScimError::Unauthorized);
+            Ok(ServiceResponse::new(req.request().clone(), response))
+        }
+    }
+}
+
+#[get("/ServiceProviderConfig")]
+async fn service_provider_config(state: web::Data<AppState>) -> HttpResponse {
+    resource_response(StatusCode::OK, state.services.scim.service_provider_config())
+}
+
+#[get("/Users")]
+async fn list_users(state: web::Data<AppState>, query: web::Query<ScimQuery>) -> HttpResponse {
+    respond(StatusCode::OK, state.services.scim.list_users(&query).await)
+}
+
+#[get("/Users/{id}")]
+async fn get_user(state: web::Data<AppState>, id: web::Path<String>, query: web::Query<ScimQuery>) -> HttpResponse {
+    respond(StatusCode::OK, state.services.scim.get_user(&id).await.map(|user| query.project(user)))
+}
+
+#[post("/Users")]
+async fn create_user(state: web::Data<AppState>, client: web::ReqData<ScimClient>, body: web::Bytes) -> HttpResponse {
+    let result = match parse(&body) {
+        Ok(resource) => state.services.scim.create_user(&client.0, &resource).await,
+        Err(e) => Err(e),
+    };
+    respond(StatusCode::CREATED, result)
+}
+
+#[put("/Users/{id}")]
+async fn replace_user(state: web::Data<AppState>, client: web::ReqData<ScimClient>, id: web::Path<String>, body: web::Bytes) -> HttpResponse {
+    let result = match parse(&body) {
+        Ok(resource) => state.services.scim.replace_user(&client.0, &id, &resource).await,
+        Err(e) => Err(e),
+    };
+    respond(StatusCode::OK, result)
+}
+
+#[patch("/Users/{id}")]
+async fn patch_user(state: web::Data<AppState>, client: web::ReqData<ScimClient>, id: web::Path<String>, body: web::Bytes) -> HttpResponse {
+    let result = match parse(&body) {
+        Ok(patch) => state.services.scim.patch_user(&client.0, &id, &patch).await,
+        Err(e) => Err(e),
+    };
+    respond(StatusCode::OK, result)
+}
+
+#[delete("/Users/{id}")]
+async fn delete_user(state: web::Data<AppState>, client: web::ReqData<ScimClient>, id: web::Path<String>) -> HttpResponse {
+    match state.services.scim.delete_user(&client.0, &id).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => error_response(e),
+    }
+}
+
+#[get("/Groups")]
+async fn list_groups(state: web::Data<AppState>, query: web::Query<ScimQuery>) -> HttpResponse {
+    respond(StatusCode::OK, state.services.scim.list_groups(&query).await)
+}
+
+#[get("/Groups/{id}")]
+async fn get_group(state: web::Data<AppState>, id: web::Path<String>, query: web::Query<ScimQuery>) -> HttpResponse {
+    respond(StatusCode::OK, state.services.scim.get_group(&id).await.map(|group| query.project(group)))
+}
+
+#[post("/Groups")]
+async fn create_group(state: web::Data<AppState>, client: web::ReqData<ScimClient>, body: web::Bytes) -> HttpResponse {
+    let result = match parse(&body) {
+        Ok(resource) => state.services.scim.create_group(&client.0, &resource).await,
+        Err(e) => Err(e),
+    };
+    respond(StatusCode::CREATED, result)
+}
+
+#[put("/Groups/{id}")]
+async fn replace_group(state: web::Data<AppState>, client: web::ReqData<ScimClient>, id: web::Path<String>, body: web::Bytes) -> HttpResponse {
+    let result = match parse(&body) {
+        Ok(resource) => state.services.scim.replace_group(&client.0, &id, &resource).await,
+        Err(e) => Err(e),
+    };
+    respond(StatusCode::OK, result)
+}
+
+#[patch("/Groups/{id}")]
+async fn patch_group(state: web::Data<AppState>, client: web::ReqData<ScimClient>, id: web::Path<String>, body: web::Bytes) -> HttpResponse {
+    let result = match parse(&body) {
+        Ok(patch) => state.services.scim.patch_group(&client.0, &id, &patch).await,
+        Err(e) => Err(e),
+    };
+    respond(StatusCode::OK, result)
+}
+
+#[delete("/Groups/{id}")]
+async fn delete_group(state: web::Data<AppState>, client: web::ReqData<ScimClient>, id: web::Path<String>) -> HttpResponse {
+    match state.services.scim.delete_group(&client.0, &id).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => error_response(e),
+    }
+}
+
+pub fn get_scope() -> Scope<impl actix_web::dev::ServiceFactory<ServiceRequest, Config = (), Response = ServiceResponse<BoxBody>, Error = Error, InitError = ()>> {
+    web::scope("/scim/v2")
+        .wrap(from_fn(scim_auth))
+        .service(service_provider_config)
+        .service(list_users)
+        .service(get_user)
+        .service(create_user)
+        .service(replace_user)
+        .service(patch_user)
+        .service(delete_user)
+        .service(list_groups)
+        .service(get_group)
+        .service(create_group)
+        .service(replace_group)
+        .service(patch_group)
+        .service(delete_group)
+}
@@ -1,22 +1,18 @@
+use std::sync::Arc;
 use crate::objects::config::Config;
-use crate::objects::user::User;
+use crate::services::clock::SystemClock;
 use crate::services::factory::Services;
-use std::sync::Mutex;
+use crate::services::metrics::Metrics;
 
+/// State shared by every worker, it must be built once before the server starts
 pub struct AppState {
     pub services: Services,
-    pub user: Mutex<Option<(String, User)>>
 }
 
 impl AppState {
-    pub fn new(config: &Config) -> Self {
+    pub fn new(config: &Config, metrics: Metrics) -> Self {
         Self {
-            services: Services::new(config),
-            user: Mutex::new(None),
+            services: Services::new(config, metrics, Arc::new(SystemClock)),
         }
     }
-
-    pub fn is_connected(&self) -> bool {
-        self.user.lock().unwrap().is_some()
-    }
-}
\ No newline at end of file
+}
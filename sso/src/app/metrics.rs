@@ -0,0 +1,55 @@
+use std::time::Instant;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::http::Method;
+use actix_web::{web, Error};
+use crate::app::app_state::AppState;
+
+/// Record the latency of every request, labelled by the matched route pattern
+/// so that path parameters do not explode the cardinality
+pub async fn track_latency(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let start = Instant::now();
+    let method = method_label(req.method());
+    let state = req.app_data::<web::Data<AppState>>().cloned();
+
+    let res = next.call(req).await?;
+
+    if let Some(state) = state {
+        let route = res.request().match_pattern().unwrap_or_else(|| "unmatched".to_string());
+        state.services.metrics.http_requests
+            .with_label_values(&[method, route.as_str(), res.status().as_str()])
+            .observe(start.elapsed().as_secs_f64());
+    }
+    Ok(res)
+}
+
+/// Extension methods are sent by clients, they share one label so they can not add series
+fn method_label(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::HEAD => "HEAD",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::PATCH => "PATCH",
+        Method::DELETE => "DELETE",
+        Method::OPTIONS => "OPTIONS",
+        _ => "other",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_method_label() {
+        assert_eq!("GET", method_label(&Method::GET));
+        assert_eq!("DELETE", method_label(&Method::DELETE));
+        assert_eq!("other", method_label(&Method::from_bytes(b"PROPFIND").unwrap()));
+        assert_eq!("other", method_label(&Method::from_bytes(b"X-RANDOM-1234").unwrap()));
+    }
+}
@@ -1 +1,4 @@
-pub mod app_state;
\ No newline at end of file
+pub mod app_state;
+pub mod logging;
+pub mod metrics;
+pub mod session;
\ No newline at end of file
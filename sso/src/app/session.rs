@@ -0,0 +1,19 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use sha2::{Digest, Sha256};
+use crate::objects::user::User;
+
+/// The authenticated user of the current request, set by `auth_middleware`
+#[derive(Clone)]
+pub struct Session {
+    pub token: String,
+    pub user: User,
+}
+
+impl Session {
+    /// Put in the forms acting on behalf of the user: another site can not read it to
+    /// forge them, and it does not reveal the session it is derived from
+    pub fn csrf_token(&self) -> String {
+        URL_SAFE_NO_PAD.encode(Sha256::digest(format!("csrf:{}", self.token)))
+    }
+}
@@ -0,0 +1,31 @@
+use std::fmt::{Display, Formatter};
+use crate::errors::validation::ValidationErrors;
+
+pub enum AccountError {
+    Validation(ValidationErrors),
+    WrongPassword,
+    EmailAlreadyExist,
+    TokenNotExist,
+    TokenExpired,
+    UserDeleted,
+    Unavailable,
+    ManagedByProvider,
+    /// The email typed to confirm an action is not the one of the account
+    EmailMismatch,
+}
+
+impl Display for AccountError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AccountError::Validation(e) => write!(f, "{}", e),
+            AccountError::WrongPassword => f.write_str("Invalid current password"),
+            AccountError::EmailAlreadyExist => f.write_str("Email already used"),
+            AccountError::TokenNotExist => f.write_str("Invalid verification link"),
+            AccountError::TokenExpired => f.write_str("Expired verification link"),
+            AccountError::UserDeleted => f.write_str("User does not exist"),
+            AccountError::ManagedByProvider => f.write_str("Your account is managed by your organization directory"),
+            AccountError::EmailMismatch => f.write_str("Type the email address of your account to confirm"),
+            AccountError::Unavailable => f.write_str("Your account could not be saved, try again later"),
+        }
+    }
+}
@@ -0,0 +1,28 @@
+use std::fmt::{Display, Formatter};
+
+pub enum AdminError {
+    UserNotExist,
+    ApplicationNotExist,
+    NotPending,
+    NotDisabled,
+    ReasonRequired,
+    OwnAccount,
+    InviteValidity,
+    Unavailable,
+}
+
+impl Display for AdminError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            AdminError::UserNotExist => "User does not exist",
+            AdminError::ApplicationNotExist => "Application does not exist",
+            AdminError::NotPending => "User is not waiting for an approval",
+            AdminError::NotDisabled => "User is neither disabled nor locked",
+            AdminError::ReasonRequired => "A reason is required",
+            AdminError::OwnAccount => "You can not disable or delete your own account",
+            AdminError::InviteValidity => "An invitation is valid between 1 and 365 days",
+            AdminError::Unavailable => "The user could not be saved, try again later",
+        };
+        f.write_str(str)
+    }
+}
@@ -1,40 +1,116 @@
 use std::fmt::{Display, Formatter};
-use crate::errors::validation::ValidationError;
+use crate::errors::validation::ValidationErrors;
 
 pub enum LoginError {
     EmailNotExist,
     WrongPassword,
+    PendingApproval,
+    AccountDisabled,
+    AccountLocked,
+    TooManyAttempts,
+    Unavailable,
 }
 pub enum RegisterError {
-    Validation(ValidationError),
+    Validation(ValidationErrors),
     EmailAlreadyExist,
     TokenRequired,
     TokenNotExist,
     TokenExpired,
+    TokenUsedUp,
+    TokenNotAllowed,
+    DomainBlocked,
+    Unavailable,
 }
 
 pub enum AuthenticateError {
     TokenNotExist,
     TokenExpired,
     UserDeleted,
+    AccountDisabled,
+    AccountLocked,
+}
+
+impl LoginError {
+    /// Stable identifier used as a metric label
+    pub fn kind(&self) -> &'static str {
+        match self {
+            LoginError::EmailNotExist => "email_not_exist",
+            LoginError::WrongPassword => "wrong_password",
+            LoginError::PendingApproval => "pending_approval",
+            LoginError::AccountDisabled => "account_disabled",
+            LoginError::AccountLocked => "account_locked",
+            LoginError::TooManyAttempts => "too_many_attempts",
+            LoginError::Unavailable => "unavailable",
+        }
+    }
+}
+impl RegisterError {
+    /// Stable identifier used as a metric label
+    pub fn kind(&self) -> &'static str {
+        match self {
+            RegisterError::Validation(_) => "validation",
+            RegisterError::EmailAlreadyExist => "email_already_exist",
+            RegisterError::TokenRequired => "token_required",
+            RegisterError::TokenNotExist => "token_not_exist",
+            RegisterError::TokenExpired => "token_expired",
+            RegisterError::TokenUsedUp => "token_used_up",
+            RegisterError::TokenNotAllowed => "token_not_allowed",
+            RegisterError::DomainBlocked => "domain_blocked",
+            RegisterError::Unavailable => "unavailable",
+        }
+    }
+}
+impl AuthenticateError {
+    /// Stable identifier used as a metric label
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AuthenticateError::TokenNotExist => "token_not_exist",
+            AuthenticateError::TokenExpired => "token_expired",
+            AuthenticateError::UserDeleted => "user_deleted",
+            AuthenticateError::AccountDisabled => "account_disabled",
+            AuthenticateError::AccountLocked => "account_locked",
+        }
+    }
 }
 
 impl Display for LoginError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let str = match self {
             LoginError::EmailNotExist => "Invalid email",
-            LoginError::WrongPassword => "Invalid password"
+            LoginError::WrongPassword => "Invalid password",
+            LoginError::PendingApproval => "Your account is waiting for an administrator approval",
+            LoginError::AccountDisabled => "Your account has been disabled by an administrator",
+            LoginError::AccountLocked => "Your account is locked after too many failed logins, contact an administrator",
+            LoginError::TooManyAttempts => "Too many login attempts, try again in a minute",
+            LoginError::Unavailable => "Login is unavailable, try again later",
         };
         f.write_str(str)?;
         Ok(())
     }
 }
+impl Display for RegisterError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RegisterError::Validation(e) => write!(f, "{}", e),
+            RegisterError::EmailAlreadyExist => f.write_str("Email already used"),
+            RegisterError::TokenRequired => f.write_str("An invitation token is required"),
+            RegisterError::TokenNotExist => f.write_str("Invalid invitation token"),
+            RegisterError::TokenExpired => f.write_str("Expired invitation token"),
+            RegisterError::TokenUsedUp => f.write_str("This invitation has already been used up"),
+            RegisterError::TokenNotAllowed => f.write_str("This invitation is not valid for this email"),
+            RegisterError::DomainBlocked => f.write_str("Registration is not allowed for this email domain"),
+            RegisterError::Unavailable => f.write_str("Registration is unavailable, try again later"),
+        }
+    }
+}
 impl Display for AuthenticateError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let str = match self {
             AuthenticateError::TokenNotExist => "Invalid Token",
             AuthenticateError::TokenExpired => "Expired Token",
             AuthenticateError::UserDeleted => "User does not exist",
+            AuthenticateError::AccountDisabled => "Account disabled",
+            AuthenticateError::AccountLocked => "Account locked",
         };
         f.write_str(str)?;
         Ok(())
@@ -0,0 +1,57 @@
+use std::fmt::{Display, Formatter};
+use crate::errors::auth::LoginError;
+
+pub enum FederationError {
+    UnknownProvider,
+    InvalidState,
+    /// The provider refused the code or sent an unexpected answer
+    Provider(String),
+    EmailMissing,
+    EmailNotVerified,
+    DomainBlocked,
+    RegistrationClosed,
+    /// The email belongs to an admin or to an account of another provider, which must
+    /// not be taken over by whoever the upstream provider vouches for
+    LinkRefused,
+    /// The account was changed by another request
+    Conflict,
+    Unavailable,
+    Login(LoginError),
+}
+
+impl FederationError {
+    /// Stable identifier used as a metric label
+    pub fn kind(&self) -> &'static str {
+        match self {
+            FederationError::UnknownProvider => "unknown_provider",
+            FederationError::InvalidState => "invalid_state",
+            FederationError::Provider(_) => "provider",
+            FederationError::EmailMissing => "email_missing",
+            FederationError::EmailNotVerified => "email_not_verified",
+            FederationError::DomainBlocked => "domain_blocked",
+            FederationError::RegistrationClosed => "registration_closed",
+            FederationError::LinkRefused => "link_refused",
+            FederationError::Conflict => "conflict",
+            FederationError::Unavailable => "unavailable",
+            FederationError::Login(e) => e.kind(),
+        }
+    }
+}
+
+impl Display for FederationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FederationError::UnknownProvider => f.write_str("Unknown identity provider"),
+            FederationError::InvalidState => f.write_str("Invalid or expired login attempt, try again"),
+            FederationError::Provider(reason) => write!(f, "The identity provider failed: {}", reason),
+            FederationError::EmailMissing => f.write_str("The identity provider did not share your email"),
+            FederationError::EmailNotVerified => f.write_str("Your email is not verified by the identity provider"),
+            FederationError::DomainBlocked => f.write_str("Registration is not allowed for this email domain"),
+            FederationError::RegistrationClosed => f.write_str("You need an invitation to create an account"),
+            FederationError::LinkRefused => f.write_str("An account already uses this email, log in with its password"),
+            FederationError::Conflict => f.write_str("Your account was changed at the same time, try again"),
+            FederationError::Unavailable => f.write_str("Login is unavailable, try again later"),
+            FederationError::Login(e) => write!(f, "{}", e),
+        }
+    }
+}
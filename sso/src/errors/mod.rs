@@ -1,2 +1,10 @@
+pub mod account;
+pub mod admin;
 pub mod auth;
+pub mod federation;
+pub mod oauth;
+pub mod provider;
+pub mod repo;
+pub mod saml;
+pub mod scim;
 pub mod validation;
\ No newline at end of file
@@ -0,0 +1,51 @@
+use std::fmt::{Display, Formatter};
+
+/// Errors of the authorization and token endpoints, as in RFC 6749 sections 4.1.2.1 and 5.2
+pub enum OAuthError {
+    InvalidRequest(String),
+    /// Unknown client or wrong secret
+    InvalidClient,
+    /// Unknown, expired, used or revoked code or refresh token
+    InvalidGrant,
+    UnauthorizedClient,
+    /// The user may not use the application
+    AccessDenied,
+    UnsupportedResponseType,
+    UnsupportedGrantType,
+    InvalidScope,
+    /// The token could not be issued with the configured lifetime
+    ServerError,
+}
+
+impl OAuthError {
+    /// The `error` code sent to the client, also used as a metric label
+    pub fn kind(&self) -> &'static str {
+        match self {
+            OAuthError::InvalidRequest(_) => "invalid_request",
+            OAuthError::InvalidClient => "invalid_client",
+            OAuthError::InvalidGrant => "invalid_grant",
+            OAuthError::UnauthorizedClient => "unauthorized_client",
+            OAuthError::AccessDenied => "access_denied",
+            OAuthError::UnsupportedResponseType => "unsupported_response_type",
+            OAuthError::UnsupportedGrantType => "unsupported_grant_type",
+            OAuthError::InvalidScope => "invalid_scope",
+            OAuthError::ServerError => "server_error",
+        }
+    }
+}
+
+impl Display for OAuthError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OAuthError::InvalidRequest(reason) => write!(f, "Invalid request: {}", reason),
+            OAuthError::InvalidClient => f.write_str("Client authentication failed"),
+            OAuthError::InvalidGrant => f.write_str("The grant is invalid, expired or revoked"),
+            OAuthError::UnauthorizedClient => f.write_str("The client can not use this grant type"),
+            OAuthError::AccessDenied => f.write_str("You do not have access to this application"),
+            OAuthError::UnsupportedResponseType => f.write_str("Unsupported response type"),
+            OAuthError::UnsupportedGrantType => f.write_str("Unsupported grant type"),
+            OAuthError::InvalidScope => f.write_str("The client is not allowed to request this scope"),
+            OAuthError::ServerError => f.write_str("The token could not be issued"),
+        }
+    }
+}
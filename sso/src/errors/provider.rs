@@ -0,0 +1,16 @@
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug)]
+pub enum ProviderError {
+    Unavailable(String),
+    InvalidEntry(String),
+}
+
+impl Display for ProviderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProviderError::Unavailable(reason) => write!(f, "Authentication provider unavailable: {}", reason),
+            ProviderError::InvalidEntry(reason) => write!(f, "Invalid directory entry: {}", reason),
+        }
+    }
+}
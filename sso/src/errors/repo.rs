@@ -0,0 +1,18 @@
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug)]
+pub enum RepoError {
+    Unavailable(String),
+    Conflict(String),
+    NotFound(String),
+}
+
+impl Display for RepoError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RepoError::Unavailable(reason) => write!(f, "Repository unavailable: {}", reason),
+            RepoError::Conflict(reason) => write!(f, "Conflicting change: {}", reason),
+            RepoError::NotFound(reason) => write!(f, "Not found: {}", reason),
+        }
+    }
+}
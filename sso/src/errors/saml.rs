@@ -0,0 +1,45 @@
+use std::fmt::{Display, Formatter};
+
+pub enum SamlError {
+    /// No signing key is configured
+    Disabled,
+    InvalidRequest(String),
+    UnknownApplication,
+    AccessDenied,
+    SignatureRequired,
+    InvalidSignature,
+    /// The request asks for an assertion consumer the application did not register
+    AcsMismatch,
+    LogoutUnsupported,
+}
+
+impl SamlError {
+    /// Stable identifier used as a metric label
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SamlError::Disabled => "disabled",
+            SamlError::InvalidRequest(_) => "invalid_request",
+            SamlError::UnknownApplication => "unknown_application",
+            SamlError::AccessDenied => "access_denied",
+            SamlError::SignatureRequired => "signature_required",
+            SamlError::InvalidSignature => "invalid_signature",
+            SamlError::AcsMismatch => "acs_mismatch",
+            SamlError::LogoutUnsupported => "logout_unsupported",
+        }
+    }
+}
+
+impl Display for SamlError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SamlError::Disabled => f.write_str("SAML is not enabled on this server"),
+            SamlError::InvalidRequest(reason) => write!(f, "Invalid SAML request: {}", reason),
+            SamlError::UnknownApplication => f.write_str("Unknown application"),
+            SamlError::AccessDenied => f.write_str("You do not have access to this application"),
+            SamlError::SignatureRequired => f.write_str("The application must sign its requests"),
+            SamlError::InvalidSignature => f.write_str("The request signature is invalid"),
+            SamlError::AcsMismatch => f.write_str("The application asked for an unregistered assertion consumer"),
+            SamlError::LogoutUnsupported => f.write_str("The application does not support single logout"),
+        }
+    }
+}
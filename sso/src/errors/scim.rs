@@ -0,0 +1,56 @@
+use std::fmt::{Display, Formatter};
+
+/// Errors of the SCIM API, with the `scimType` of RFC 7644 section 3.12
+pub enum ScimError {
+    Unauthorized,
+    NotFound,
+    InvalidFilter(String),
+    InvalidSyntax(String),
+    InvalidValue(String),
+    InvalidPath(String),
+    /// The path of a patch operation does not match any value
+    NoTarget(String),
+    /// The userName or displayName is already used
+    Uniqueness,
+    Unavailable,
+}
+
+impl ScimError {
+    pub fn status(&self) -> u16 {
+        match self {
+            ScimError::Unauthorized => 401,
+            ScimError::NotFound => 404,
+            ScimError::Uniqueness => 409,
+            ScimError::Unavailable => 503,
+            _ => 400,
+        }
+    }
+
+    pub fn scim_type(&self) -> Option<&'static str> {
+        match self {
+            ScimError::InvalidFilter(_) => Some("invalidFilter"),
+            ScimError::InvalidSyntax(_) => Some("invalidSyntax"),
+            ScimError::InvalidValue(_) => Some("invalidValue"),
+            ScimError::InvalidPath(_) => Some("invalidPath"),
+            ScimError::NoTarget(_) => Some("noTarget"),
+            ScimError::Uniqueness => Some("uniqueness"),
+            _ => None,
+        }
+    }
+}
+
+impl Display for ScimError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScimError::Unauthorized => f.write_str("A valid bearer token is required"),
+            ScimError::NotFound => f.write_str("Resource not found"),
+            ScimError::InvalidFilter(reason) => write!(f, "Invalid filter: {}", reason),
+            ScimError::InvalidSyntax(reason) => write!(f, "Invalid request: {}", reason),
+            ScimError::InvalidValue(reason) => write!(f, "Invalid value: {}", reason),
+            ScimError::InvalidPath(path) => write!(f, "Invalid path: {}", path),
+            ScimError::NoTarget(path) => write!(f, "No value matches the path {}", path),
+            ScimError::Uniqueness => f.write_str("The name is already used by another resource"),
+            ScimError::Unavailable => f.write_str("Service unavailable, try again later"),
+        }
+    }
+}
@@ -1,9 +1,103 @@
+use std::fmt::{Display, Formatter};
+use serde_json::{json, Value};
+use crate::objects::config::CharacterClass;
+
+#[derive(Debug, PartialEq)]
 pub enum ValidationEnumError {
     Empty,
     Size(i32, i32),
     Regex(String),
+    MissingCharacter(CharacterClass),
+    PersonalInfo,
+    /// The zxcvbn score and the minimum required
+    TooWeak(u8, u8),
+    Breached,
 }
+#[derive(Debug)]
 pub struct ValidationError {
     pub field: String,
     pub error: ValidationEnumError,
-}
\ No newline at end of file
+}
+
+/// Every error found while validating a form
+#[derive(Debug, Default)]
+pub struct ValidationErrors {
+    pub errors: Vec<ValidationError>,
+}
+
+/// Turns validation errors into text, implement it to translate the messages
+pub trait Messages {
+    fn message(&self, error: &ValidationError) -> String;
+}
+
+pub struct EnglishMessages;
+
+impl ValidationEnumError {
+    /// Stable identifier, usable as a translation key
+    pub fn code(&self) -> &'static str {
+        match self {
+            ValidationEnumError::Empty => "empty",
+            ValidationEnumError::Size(_, _) => "size",
+            ValidationEnumError::Regex(_) => "regex",
+            ValidationEnumError::MissingCharacter(_) => "missing_character",
+            ValidationEnumError::PersonalInfo => "personal_info",
+            ValidationEnumError::TooWeak(_, _) => "too_weak",
+            ValidationEnumError::Breached => "breached",
+        }
+    }
+}
+
+impl ValidationErrors {
+    pub fn add(&mut self, field: &str, error: ValidationEnumError) {
+        self.errors.push(ValidationError {
+            field: field.to_string(),
+            error,
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn for_field<'a>(&'a self, field: &'a str) -> impl Iterator<Item = &'a ValidationError> {
+        self.errors.iter().filter(move |e| e.field == field)
+    }
+
+    pub fn to_json(&self, messages: &dyn Messages) -> Value {
+        let errors: Vec<Value> = self.errors.iter()
+            .map(|e| json!({
+                "field": e.field,
+                "code": e.error.code(),
+                "message": messages.message(e),
+            }))
+            .collect();
+        json!({ "errors": errors })
+    }
+}
+
+impl Messages for EnglishMessages {
+    fn message(&self, error: &ValidationError) -> String {
+        error.to_string()
+    }
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.error {
+            ValidationEnumError::Empty => write!(f, "{} is required", self.field),
+            ValidationEnumError::Size(min, max) => write!(f, "{} must be between {} and {} characters", self.field, min, max),
+            ValidationEnumError::Regex(example) => write!(f, "{} is invalid, expected something like {}", self.field, example),
+            ValidationEnumError::MissingCharacter(class) => write!(f, "{} must contain a character of type {:?}", self.field, class),
+            ValidationEnumError::PersonalInfo => write!(f, "{} must not contain your name or email", self.field),
+            ValidationEnumError::TooWeak(score, min) => write!(f, "{} is too easy to guess ({}/4, {} required)", self.field, score, min),
+            ValidationEnumError::Breached => write!(f, "{} appeared in a data breach, choose another one", self.field),
+        }
+    }
+}
+
+impl Display for ValidationErrors {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let messages: Vec<String> = self.errors.iter().map(|e| e.to_string()).collect();
+        f.write_str(&messages.join(", "))
+    }
+}
@@ -0,0 +1,60 @@
+use std::fmt::{Debug, Formatter};
+use serde::Deserialize;
+use crate::forms::REDACTED;
+
+#[derive(Deserialize, Debug)]
+pub struct ProfileForm {
+    pub name: String,
+    pub avatar_url: String,
+    pub timezone: String,
+    pub locale: String,
+}
+
+#[derive(Deserialize)]
+pub struct PasswordForm {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct EmailForm {
+    pub email: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeletionForm {
+    #[serde(default)]
+    pub password: String,
+    /// Typed instead of the password by accounts of a provider, which have none here
+    #[serde(default)]
+    pub email: String,
+}
+
+#[derive(Deserialize)]
+pub struct VerifyEmailQuery {
+    pub token: String,
+}
+
+/// Removes the access given to an application
+#[derive(Deserialize)]
+pub struct RevokeConsentForm {
+    pub client_id: String,
+}
+
+impl Debug for DeletionForm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeletionForm")
+            .field("password", &REDACTED)
+            .field("email", &self.email)
+            .finish()
+    }
+}
+
+impl Debug for PasswordForm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PasswordForm")
+            .field("current_password", &REDACTED)
+            .field("new_password", &REDACTED)
+            .finish()
+    }
+}
@@ -0,0 +1,31 @@
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct InviteForm {
+    pub days_valid: u64,
+    pub max_uses: u32,
+    /// Either an email address or a domain starting with `@`
+    pub restriction: String,
+    /// Comma separated
+    pub groups: String,
+    /// Comma separated client ids
+    pub applications: String,
+}
+
+
+#[derive(Deserialize)]
+pub struct UserActionForm {
+    pub id: String,
+}
+
+#[derive(Deserialize)]
+pub struct DisableForm {
+    pub id: String,
+    pub reason: String,
+}
+
+#[derive(Deserialize)]
+pub struct PreConsentForm {
+    pub client_id: String,
+    pub pre_consented: bool,
+}
\ No newline at end of file
@@ -0,0 +1,171 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use serde::Deserialize;
+use crate::forms::validation::URL_REGEX;
+use crate::objects::application::{Application, Protocol, SamlSettings};
+
+/// An application of the file given by `SSO_APPLICATIONS_FILE`, a JSON array of them
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApplicationEntry {
+    pub name: String,
+    pub url: String,
+    #[serde(default)]
+    pub icon_url: Option<String>,
+    pub client_id: String,
+    pub client_secret: String,
+    #[serde(default)]
+    pub scopes: HashSet<String>,
+    #[serde(default)]
+    pub pre_consented: bool,
+    /// The application uses OIDC without it
+    #[serde(default)]
+    pub saml: Option<SamlEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SamlEntry {
+    pub entity_id: String,
+    pub acs_url: String,
+    #[serde(default)]
+    pub slo_url: Option<String>,
+    /// Path of the PEM certificate, relative to the applications file
+    #[serde(default)]
+    pub signing_cert_file: Option<String>,
+}
+
+/// Read and check the applications registered at startup, the users are given access by invitation
+pub fn read_applications(path: &Path) -> Result<Vec<Application>, String> {
+    let content = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let entries: Vec<ApplicationEntry> = serde_json::from_str(&content)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+
+    let mut client_ids = HashSet::new();
+    let mut entity_ids = HashSet::new();
+    let mut applications = vec![];
+    for entry in entries {
+        let client_id = entry.client_id.clone();
+        let invalid = |reason: &str| format!("application {:?}: {}", client_id, reason);
+        for (field, value) in [("name", &entry.name), ("client_id", &entry.client_id), ("client_secret", &entry.client_secret)] {
+            if value.trim().is_empty() {
+                return Err(invalid(&format!("{} is empty", field)));
+            }
+        }
+        if !client_ids.insert(entry.client_id.clone()) {
+            return Err(invalid("client_id is used twice"));
+        }
+        let wrong_url = [Some(&entry.url), entry.icon_url.as_ref()].into_iter()
+            .chain(entry.saml.iter().flat_map(|saml| [Some(&saml.acs_url), saml.slo_url.as_ref()]))
+            .flatten()
+            .find(|url| !URL_REGEX.is_match(url));
+        if let Some(url) = wrong_url {
+            return Err(invalid(&format!("{} is not an url", url)));
+        }
+
+        let protocol = match entry.saml {
+            None => Protocol::Oidc,
+            Some(saml) => {
+                if saml.entity_id.trim().is_empty() {
+                    return Err(invalid("entity_id is empty"));
+                }
+                if !entity_ids.insert(saml.entity_id.clone()) {
+                    return Err(invalid("entity_id is used twice"));
+                }
+                let signing_cert = saml.signing_cert_file
+                    .map(|file| fs::read_to_string(dir.join(&file)).map_err(|e| invalid(&format!("{}: {}", file, e))))
+                    .transpose()?;
+                Protocol::Saml(SamlSettings {
+                    entity_id: saml.entity_id,
+                    acs_url: saml.acs_url,
+                    slo_url: saml.slo_url,
+                    signing_cert,
+                })
+            }
+        };
+        applications.push(Application {
+            name: entry.name,
+            url: entry.url,
+            icon_url: entry.icon_url,
+            client_id: entry.client_id,
+            client_secret: entry.client_secret,
+            protocol,
+            scopes: entry.scopes,
+            users: HashSet::new(),
+            pre_consented: entry.pre_consented,
+        });
+    }
+    Ok(applications)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use super::*;
+    use crate::objects::config::Config;
+    use crate::services::factory::Repos;
+    use crate::services::metrics::Metrics;
+
+    fn testdata(file: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("testdata").join(file)
+    }
+
+    #[test]
+    fn test_read_applications() {
+        let mut applications = read_applications(&testdata("applications.json")).unwrap();
+        applications.sort_by(|a, b| a.client_id.cmp(&b.client_id));
+        assert_eq!(2, applications.len());
+
+        let payroll = &applications[0];
+        assert_eq!("Payroll", payroll.name);
+        let Protocol::Saml(saml) = &payroll.protocol else { panic!("payroll uses SAML") };
+        assert_eq!("https://payroll.example.com/saml", saml.entity_id);
+        assert!(saml.signing_cert.as_ref().unwrap().starts_with("-----BEGIN CERTIFICATE-----"));
+
+        let wiki = &applications[1];
+        assert!(matches!(wiki.protocol, Protocol::Oidc));
+        assert!(wiki.pre_consented);
+        assert!(wiki.scopes.contains("users:read"));
+        assert!(wiki.users.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_repos_with_applications() {
+        let config = Config { applications_file: Some(testdata("applications.json")), ..Config::default() };
+        let repos = Repos::new(&config, &Metrics::new());
+        assert_eq!(2, repos.application_repo.get_all().await.len());
+        let payroll = repos.application_repo.get_by_entity_id("https://payroll.example.com/saml").await.unwrap();
+        assert_eq!("payroll", payroll.client_id);
+    }
+
+    #[test]
+    fn test_read_invalid_applications() {
+        let dir = std::env::temp_dir().join(format!("sso-applications-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let read = |json: &str| {
+            let path = dir.join("applications.json");
+            fs::write(&path, json).unwrap();
+            read_applications(&path)
+        };
+
+        let wiki = r#"{"name": "Wiki", "url": "https://wiki.example.com", "client_id": "wiki", "client_secret": "secret"}"#;
+        assert!(read(&format!("[{}]", wiki)).is_ok());
+        assert!(read(&format!("[{}, {}]", wiki, wiki)).err().unwrap().contains("client_id is used twice"));
+        assert!(read(&format!("[{}]", wiki.replace("\"secret\"", "\" \""))).err().unwrap().contains("client_secret is empty"));
+        assert!(read(&format!("[{}]", wiki.replace("\"https://wiki.example.com\"", "\"wiki.example.com\""))).err().unwrap().contains("is not an url"));
+        assert!(read(&format!("[{}]", wiki.replace("\"secret\"", "\"secret\", \"scope\": \"users:read\""))).is_err());
+
+        let saml = |client_id: &str, cert: &str| format!(
+            r#"{{"name": "{0}", "url": "https://{0}.example.com", "client_id": "{0}", "client_secret": "secret", "saml": {{"entity_id": "https://sp.example.com", "acs_url": "https://{0}.example.com/acs", "signing_cert_file": "{1}"}}}}"#,
+            client_id, cert,
+        );
+        assert!(read(&format!("[{}, {}]", saml("a", "missing.pem"), saml("b", "missing.pem"))).err().unwrap().contains("missing.pem"));
+        fs::copy(testdata("saml_sp_cert.pem"), dir.join("sp.pem")).unwrap();
+        assert!(read(&format!("[{}]", saml("a", "sp.pem"))).is_ok());
+        assert!(read(&format!("[{}, {}]", saml("a", "sp.pem"), saml("b", "sp.pem"))).err().unwrap().contains("entity_id is used twice"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
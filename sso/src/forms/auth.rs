@@ -1,13 +1,103 @@
-use serde::Deserialize;
+use std::fmt::{Debug, Formatter};
+use serde::{Deserialize, Deserializer, Serialize};
+use crate::forms::REDACTED;
 
 #[derive(Deserialize)]
 pub struct LoginForm {
     pub email: String,
     pub password: String,
 }
+#[derive(Deserialize)]
 pub struct RegisterForm {
     pub email: String,
     pub password: String,
     pub name: String,
+    #[serde(default, deserialize_with = "empty_as_none")]
     pub token: Option<String>
-}
\ No newline at end of file
+}
+
+/// Sent back by the upstream provider, with either a code or an error
+#[derive(Deserialize)]
+pub struct FederationCallbackQuery {
+    pub state: String,
+    pub code: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Page to go back to once logged in
+#[derive(Deserialize, Serialize)]
+pub struct LoginQuery {
+    #[serde(default, deserialize_with = "empty_as_none", skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
+}
+
+impl LoginQuery {
+    /// The page to redirect to, only local paths are followed so the login can not send users away
+    pub fn target(&self) -> &str {
+        match self.next.as_deref() {
+            Some(next) if next.starts_with('/') && !next.starts_with("//") && !next.contains('\\') && !next.chars().any(char::is_control) => next,
+            _ => "/",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RegisterQuery {
+    pub token: Option<String>,
+}
+
+/// Html forms send empty inputs as empty strings
+pub(crate) fn empty_as_none<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    let value = Option::<String>::deserialize(deserializer)?;
+    Ok(value.filter(|value| !value.is_empty()))
+}
+
+impl Debug for LoginForm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LoginForm")
+            .field("email", &self.email)
+            .field("password", &REDACTED)
+            .finish()
+    }
+}
+impl Debug for RegisterForm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RegisterForm")
+            .field("email", &self.email)
+            .field("password", &REDACTED)
+            .field("name", &self.name)
+            .field("token", &self.token.as_ref().map(|_| REDACTED))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debug_redacts_secrets() {
+        let form = RegisterForm {
+            email: "test@example.com".to_string(),
+            password: "hunter22".to_string(),
+            name: "Test".to_string(),
+            token: Some("invite".to_string()),
+        };
+        let output = format!("{:?}", form);
+
+        assert!(output.contains("test@example.com"));
+        assert!(!output.contains("hunter22"));
+        assert!(!output.contains("invite"));
+    }
+
+    #[test]
+    fn test_login_target_is_local() {
+        let target = |next: &str| LoginQuery { next: Some(next.to_string()) }.target().to_string();
+
+        assert_eq!(target("/saml/sso?SAMLRequest=x"), "/saml/sso?SAMLRequest=x");
+        assert_eq!(target("https://evil.example.com"), "/");
+        assert_eq!(target("//evil.example.com"), "/");
+        assert_eq!(target("/\\evil.example.com"), "/");
+        assert_eq!(LoginQuery { next: None }.target(), "/");
+    }
+}
@@ -0,0 +1,18 @@
+use serde::Deserialize;
+use crate::forms::auth::empty_as_none;
+
+/// Search of the launcher, sent as the user types
+#[derive(Deserialize)]
+pub struct LauncherQuery {
+    #[serde(default, deserialize_with = "empty_as_none")]
+    pub search: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct FavoriteForm {
+    pub client_id: String,
+    pub favorite: bool,
+    /// The search shown while the favorite was changed, kept in the new list
+    #[serde(default, deserialize_with = "empty_as_none")]
+    pub search: Option<String>,
+}
@@ -1 +1,11 @@
-pub mod auth;
\ No newline at end of file
+pub mod account;
+pub mod admin;
+pub mod application;
+pub mod auth;
+pub mod launcher;
+pub mod oauth;
+pub mod scim;
+pub mod validation;
+
+/// Shown in place of the secrets, forms are logged so these must never reach the output
+pub const REDACTED: &str = "[redacted]";
@@ -0,0 +1,77 @@
+use std::fmt::{Debug, Formatter};
+use serde::Deserialize;
+use crate::forms::auth::empty_as_none;
+use crate::forms::REDACTED;
+
+/// Sent to the token endpoint, the client can authenticate with these fields or with basic auth
+#[derive(Deserialize)]
+pub struct TokenForm {
+    pub grant_type: String,
+    pub scope: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    /// For the authorization code grant
+    pub code: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub code_verifier: Option<String>,
+    /// For the refresh token grant
+    pub refresh_token: Option<String>,
+}
+
+/// Query of the authorization endpoint, the browser is sent there by the application
+#[derive(Deserialize, Debug)]
+pub struct AuthorizeQuery {
+    pub response_type: String,
+    pub client_id: String,
+    pub redirect_uri: String,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    pub scope: Option<String>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    pub state: Option<String>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    pub code_challenge: Option<String>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    pub code_challenge_method: Option<String>,
+}
+
+/// Sent to the introspection and revocation endpoints
+#[derive(Deserialize)]
+pub struct TokenRequestForm {
+    pub token: String,
+    pub token_type_hint: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+/// Posted by the consent page, `decision` is `allow` or `deny`
+#[derive(Deserialize)]
+pub struct ConsentForm {
+    pub decision: String,
+    #[serde(default)]
+    pub csrf_token: String,
+}
+
+impl Debug for TokenForm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TokenForm")
+            .field("grant_type", &self.grant_type)
+            .field("scope", &self.scope)
+            .field("client_id", &self.client_id)
+            .field("client_secret", &self.client_secret.as_ref().map(|_| REDACTED))
+            .field("code", &self.code.as_ref().map(|_| REDACTED))
+            .field("redirect_uri", &self.redirect_uri)
+            .field("code_verifier", &self.code_verifier.as_ref().map(|_| REDACTED))
+            .field("refresh_token", &self.refresh_token.as_ref().map(|_| REDACTED))
+            .finish()
+    }
+}
+impl Debug for TokenRequestForm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TokenRequestForm")
+            .field("token", &REDACTED)
+            .field("token_type_hint", &self.token_type_hint)
+            .field("client_id", &self.client_id)
+            .field("client_secret", &self.client_secret.as_ref().map(|_| REDACTED))
+            .finish()
+    }
+}
@@ -0,0 +1,39 @@
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Query of the SCIM endpoints, the filter and the paging only apply to lists
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimQuery {
+    pub filter: Option<String>,
+    /// 1-based index of the first result
+    pub start_index: Option<usize>,
+    pub count: Option<usize>,
+    /// Comma separated attributes to return, along with `id`, `schemas` and `meta`
+    pub attributes: Option<String>,
+    /// Comma separated attributes to leave out
+    pub excluded_attributes: Option<String>,
+}
+
+fn names(list: &str) -> Vec<String> {
+    list.split(',').map(|name| name.trim().to_lowercase()).collect()
+}
+
+impl ScimQuery {
+    /// Keep the top level attributes asked for
+    pub fn project(&self, mut resource: Value) -> Value {
+        let Some(object) = resource.as_object_mut() else {
+            return resource;
+        };
+        if let Some(attributes) = &self.attributes {
+            let mut kept = names(attributes);
+            kept.extend(["id", "schemas", "meta"].map(str::to_string));
+            object.retain(|name, _| kept.contains(&name.to_lowercase()));
+        }
+        if let Some(excluded) = &self.excluded_attributes {
+            let excluded = names(excluded);
+            object.retain(|name, _| name == "id" || name == "schemas" || !excluded.contains(&name.to_lowercase()));
+        }
+        resource
+    }
+}
@@ -0,0 +1,122 @@
+use std::sync::LazyLock;
+use regex::Regex;
+use crate::errors::validation::{ValidationEnumError, ValidationErrors};
+
+pub static EMAIL_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^[\w\.-]+@([\w-]+\.)+[\w-]{2,4}$").unwrap()
+});
+
+pub static TIMEZONE_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(UTC|[A-Za-z_]+(/[A-Za-z0-9_+-]+)+)$").unwrap()
+});
+
+pub static LOCALE_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^[a-z]{2,3}(-[A-Z]{2})?$").unwrap()
+});
+
+pub static URL_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^https?://[^\s]+$").unwrap()
+});
+
+/// Collects the errors of every field of a form instead of stopping at the first one
+#[derive(Default)]
+pub struct Validator {
+    errors: ValidationErrors,
+}
+
+/// Rules applied to one field, a missing required value skips the other rules
+pub struct Field<'v> {
+    name: &'v str,
+    value: &'v str,
+    skip: bool,
+    errors: &'v mut ValidationErrors,
+}
+
+impl Validator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn field<'v>(&'v mut self, name: &'v str, value: &'v str) -> Field<'v> {
+        Field {
+            name,
+            value,
+            skip: false,
+            errors: &mut self.errors,
+        }
+    }
+
+    pub fn finish(self) -> Result<(), ValidationErrors> {
+        match self.errors.is_empty() {
+            true => Ok(()),
+            false => Err(self.errors),
+        }
+    }
+}
+
+impl Field<'_> {
+    fn fail(&mut self, error: ValidationEnumError) {
+        self.errors.add(self.name, error);
+    }
+
+    pub fn required(mut self) -> Self {
+        if self.value.trim().is_empty() {
+            self.fail(ValidationEnumError::Empty);
+            self.skip = true;
+        }
+        self
+    }
+
+    pub fn size(mut self, min: i32, max: i32) -> Self {
+        let length = self.value.chars().count() as i32;
+        if !self.skip && (length < min || length > max) {
+            self.fail(ValidationEnumError::Size(min, max));
+        }
+        self
+    }
+
+    /// Skip the remaining rules when the value is empty
+    pub fn optional(mut self) -> Self {
+        if self.value.trim().is_empty() {
+            self.skip = true;
+        }
+        self
+    }
+
+    pub fn regex(mut self, regex: &Regex, example: &str) -> Self {
+        if !self.skip && !regex.is_match(self.value) {
+            self.fail(ValidationEnumError::Regex(example.to_string()));
+        }
+        self
+    }
+
+    /// Run a custom validator returning every problem it found
+    pub fn check(mut self, validator: impl FnOnce(&str) -> Vec<ValidationEnumError>) -> Self {
+        if !self.skip {
+            for error in validator(self.value) {
+                self.fail(error);
+            }
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collects_every_error() {
+        let mut validator = Validator::new();
+        validator.field("name", "").required().size(2, 40);
+        validator.field("email", "nope").required().regex(&EMAIL_REGEX, "abc@example.com");
+        validator.field("password", "abc").check(|value| match value.len() {
+            3 => vec![ValidationEnumError::Breached],
+            _ => vec![],
+        });
+
+        let errors = validator.finish().unwrap_err();
+        let found: Vec<_> = errors.errors.iter().map(|e| (e.field.as_str(), e.error.code())).collect();
+        assert_eq!(vec![("name", "empty"), ("email", "regex"), ("password", "breached")], found);
+    }
+}
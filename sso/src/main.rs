@@ -1,13 +1,13 @@
 use crate::app::app_state::AppState;
+use crate::app::logging;
 use crate::objects::config::Config;
+use crate::services::metrics::Metrics;
 use crate::views::auth::auth_middleware;
-use crate::views::nav::get_nav;
 use actix_web::body::MessageBody;
 use actix_web::middleware::{from_fn, Next};
-use actix_web::{get, web, App, Error, HttpServer};
-use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::{web, App, Error, HttpServer};
+use actix_web::dev::{ServiceFactory, ServiceRequest, ServiceResponse};
 use actix_web::http::header::{HeaderValue, CACHE_CONTROL};
-use maud::{html, Markup};
 
 mod errors;
 mod objects;
@@ -24,36 +24,186 @@ async fn cache(
 ) -> Result<ServiceResponse<impl MessageBody>, Error> {
     // pre-processing
     let mut res = next.call(req).await?;
-    // post-processing
-    res.headers_mut()
-        .insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    // post-processing, token responses already ask for no-store
+    if !res.headers().contains_key(CACHE_CONTROL) {
+        res.headers_mut()
+            .insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    }
     Ok(res)
 }
 
+fn create_app(state: web::Data<AppState>) -> App<
+    impl ServiceFactory<
+        ServiceRequest,
+        Config = (),
+        Response = ServiceResponse<impl MessageBody>,
+        Error = Error,
+        InitError = (),
+    >,
+> {
+    App::new()
+        .app_data(state)
+        .wrap(from_fn(auth_middleware))
+        .wrap(from_fn(cache))
+        .wrap(from_fn(app::metrics::track_latency))
+        .wrap(from_fn(logging::request_id))
+        .service(apis::metrics::metrics)
+        .service(apis::health::healthz)
+        .service(apis::health::readyz)
+        .service(views::home::home)
+        .service(views::home::get_scope())
+        .service(views::auth::get_scope())
+        .service(views::account::get_scope())
+        .service(views::admin::get_scope())
+        .service(views::saml::get_scope())
+        .service(apis::auth::get_scope())
+        .service(apis::scim::get_scope())
+        .service(apis::oauth::get_scope())
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    HttpServer::new(|| {
-        App::new()
-            .app_data(web::Data::new(AppState::new(&Config::default())))
-            .wrap(from_fn(auth_middleware))
-            .wrap(from_fn(cache))
-            .service(hello_world)
-            .service(home)
-            .service(views::auth::get_scope())
-    }).bind(("0.0.0.0", 8080))?
+    let config = Config::from_env();
+    logging::init(&config);
+
+    // Built once so that every worker shares the same repositories
+    let state = web::Data::new(AppState::new(&config, Metrics::new()));
+    state.services.maintenance.spawn();
+
+    let migrating = state.clone();
+    actix_web::rt::spawn(async move {
+        let services = &migrating.services;
+        services.migration.run(&services.health).await;
+    });
+
+    tracing::info!("listening on 0.0.0.0:8080");
+    HttpServer::new(move || create_app(state.clone()))
+        .bind(("0.0.0.0", 8080))?
         .run()
         .await
 }
 
-#[get("/hello")]
-async fn hello_world() -> &'static str {
-    "Hello world"
-}
+#[cfg(test)]
+mod tests {
+    use actix_web::cookie::{Cookie, SameSite};
+    use actix_web::test;
+    use super::*;
+    use crate::objects::config::ProvisioningClient;
+
+    #[actix_web::test]
+    async fn test_workers_share_sessions() {
+        let state = web::Data::new(AppState::new(&Config::default(), Metrics::new()));
+        // One service per simulated worker, built like the HttpServer factory does
+        let workers = [
+            test::init_service(create_app(state.clone())).await,
+            test::init_service(create_app(state.clone())).await,
+            test::init_service(create_app(state.clone())).await,
+        ];
+
+        let req = test::TestRequest::post()
+            .uri("/auth/login")
+            .set_form([("email", "admin@example.com"), ("password", "admin")])
+            .to_request();
+        let res = test::call_service(&workers[0], req).await;
+        let cookie = res.response().cookies()
+            .find(|cookie| cookie.name() == "token")
+            .expect("login should set a token cookie");
+        assert_eq!((Some(true), Some(SameSite::Lax)), (cookie.http_only(), cookie.same_site()));
+        let token = cookie.value().to_string();
+
+        for i in 0..60 {
+            let worker = &workers[i % workers.len()];
+
+            let req = test::TestRequest::get()
+                .uri("/auth/login")
+                .cookie(Cookie::new("token", token.clone()))
+                .to_request();
+            let body = test::call_and_read_body(worker, req).await;
+            assert!(String::from_utf8_lossy(&body).contains("logout"), "worker {} lost the session", i % workers.len());
+
+            // A request without cookie must not see the session of another request
+            let req = test::TestRequest::get().uri("/auth/login").to_request();
+            let body = test::call_and_read_body(worker, req).await;
+            assert!(!String::from_utf8_lossy(&body).contains("logout"));
+        }
 
-#[get("/")]
-async fn home(state: web::Data<AppState>) -> Markup {
-    html! {
-        (get_nav(&state))
-        "Hello world"
+        let req = test::TestRequest::get()
+            .uri("/auth/logout")
+            .cookie(Cookie::new("token", token.clone()))
+            .to_request();
+        test::call_service(&workers[1], req).await;
+
+        let req = test::TestRequest::get()
+            .uri("/auth/logout")
+            .cookie(Cookie::new("token", token.clone()))
+            .to_request();
+        let res = test::call_service(&workers[2], req).await;
+        assert_eq!(401, res.status().as_u16());
+    }
+
+    #[actix_web::test]
+    async fn test_scim_requires_a_token() {
+        let config = Config {
+            provisioning_clients: vec![ProvisioningClient { name: "hr".to_string(), token: "scim-token".to_string() }],
+            ..Config::default()
+        };
+        let state = web::Data::new(AppState::new(&config, Metrics::new()));
+        let app = test::init_service(create_app(state)).await;
+
+        let req = test::TestRequest::get()
+            .uri("/scim/v2/Users")
+            .insert_header(("Authorization", "Bearer wrong"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(401, res.status().as_u16());
+        let body: serde_json::Value = test::read_body_json(res).await;
+        assert_eq!(body["schemas"][0], "urn:ietf:params:scim:api:messages:2.0:Error");
+        assert_eq!(body["status"], "401");
+
+        let req = test::TestRequest::post()
+            .uri("/scim/v2/Users")
+            .insert_header(("Authorization", "Bearer scim-token"))
+            .insert_header(("Content-Type", "application/scim+json"))
+            .set_payload(r#"{"schemas":["urn:ietf:params:scim:schemas:core:2.0:User"],"userName":"bjensen@example.com"}"#)
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(201, res.status().as_u16());
+        assert_eq!(res.headers().get("content-type").unwrap(), "application/scim+json");
+        let location = res.headers().get("location").unwrap().to_str().unwrap().to_string();
+
+        let req = test::TestRequest::get()
+            .uri(location.trim_start_matches("http://localhost:8080"))
+            .insert_header(("Authorization", "Bearer scim-token"))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["userName"], "bjensen@example.com");
     }
-}
\ No newline at end of file
+
+    #[actix_web::test]
+    async fn test_home_lists_applications() {
+        let state = web::Data::new(AppState::new(&Config::default(), Metrics::new()));
+        let app = test::init_service(create_app(state)).await;
+
+        let req = test::TestRequest::get().uri("/").to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        assert!(String::from_utf8_lossy(&body).contains("Log in to see your applications"));
+
+        let req = test::TestRequest::post()
+            .uri("/auth/login")
+            .set_form([("email", "admin@example.com"), ("password", "admin")])
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        let token = res.response().cookies()
+            .find(|cookie| cookie.name() == "token")
+            .unwrap()
+            .value()
+            .to_string();
+
+        let req = test::TestRequest::get()
+            .uri("/launcher/applications?search=wiki")
+            .cookie(Cookie::new("token", token))
+            .to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        assert!(String::from_utf8_lossy(&body).contains("No application matches"));
+    }
+}
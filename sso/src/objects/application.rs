@@ -1,9 +1,50 @@
 use std::collections::HashSet;
 
+/// How an application receives the identity of its users
+#[derive(Clone)]
+pub enum Protocol {
+    Oidc,
+    Saml(SamlSettings),
+}
+
+/// Service provider registered for SAML 2.0
+#[derive(Clone)]
+pub struct SamlSettings {
+    /// Issuer of the authentication requests sent by the application
+    pub entity_id: String,
+    /// Where the assertions are posted, requests asking for another url are refused
+    pub acs_url: String,
+    /// Where logout responses are sent, single logout is refused without it
+    pub slo_url: Option<String>,
+    /// PEM certificate checking signed requests, unsigned requests are refused when set
+    /// and single logout is refused without it
+    pub signing_cert: Option<String>,
+}
+
+#[derive(Clone)]
 pub struct Application {
     pub name: String,
     pub url: String,
+    /// Shown on the launcher, the initials of the name are shown without it
+    pub icon_url: Option<String>,
     pub client_id: String,
     pub client_secret: String,
+    pub protocol: Protocol,
+    /// Scopes the application can ask for with the client credentials grant
+    pub scopes: HashSet<String>,
+    /// Ids of the users allowed to use the application
     pub users: HashSet<String>,
-}
\ No newline at end of file
+    /// First-party application set by an admin, its users are never asked for consent
+    pub pre_consented: bool,
+}
+
+impl Application {
+    /// Shown instead of the icon
+    pub fn initials(&self) -> String {
+        self.name.split_whitespace()
+            .filter_map(|word| word.chars().next())
+            .take(2)
+            .flat_map(char::to_uppercase)
+            .collect()
+    }
+}
@@ -0,0 +1,55 @@
+use chrono::{DateTime, Utc};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AuditAction {
+    Login,
+    ProfileUpdated,
+    PasswordChanged,
+    EmailChanged,
+    AccountApproved,
+    AccountDisabled,
+    AccountLocked,
+    AccountEnabled,
+    DeletionRequested,
+    DeletionCancelled,
+    AccountDeleted,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Login => "login",
+            AuditAction::ProfileUpdated => "profile_updated",
+            AuditAction::PasswordChanged => "password_changed",
+            AuditAction::EmailChanged => "email_changed",
+            AuditAction::AccountApproved => "account_approved",
+            AuditAction::AccountDisabled => "account_disabled",
+            AuditAction::AccountLocked => "account_locked",
+            AuditAction::AccountEnabled => "account_enabled",
+            AuditAction::DeletionRequested => "deletion_requested",
+            AuditAction::DeletionCancelled => "deletion_cancelled",
+            AuditAction::AccountDeleted => "account_deleted",
+        }
+    }
+}
+
+/// Something that happened to an account, `actor` did `action` on the account of `subject`.
+/// Both are user ids, or `system` for automatic actions
+#[derive(Clone, Debug)]
+pub struct AuditEvent {
+    pub date: DateTime<Utc>,
+    pub actor: String,
+    pub action: AuditAction,
+    pub subject: String,
+}
+
+impl AuditEvent {
+    pub fn new(date: DateTime<Utc>, actor: &str, action: AuditAction, subject: &str) -> Self {
+        Self {
+            date,
+            actor: actor.to_string(),
+            action,
+            subject: subject.to_string(),
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use std::fmt::{Debug, Formatter};
+use chrono::{DateTime, Utc};
+
+/// Given to an application by the authorization endpoint, exchanged once for tokens
+#[derive(Clone)]
+pub struct AuthorizationCode {
+    pub value: String,
+    pub client_id: String,
+    /// Id of the user
+    pub user: String,
+    /// The login token of the browser that authorized the application
+    pub session: String,
+    /// Must be sent again with the code
+    pub redirect_uri: String,
+    pub scope: String,
+    /// PKCE S256 challenge, when the application sent one
+    pub code_challenge: Option<String>,
+    pub expiration: DateTime<Utc>,
+}
+
+impl Debug for AuthorizationCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuthorizationCode")
+            .field("value", &"[redacted]")
+            .field("client_id", &self.client_id)
+            .field("user", &self.user)
+            .field("scope", &self.scope)
+            .field("expiration", &self.expiration)
+            .finish()
+    }
+}
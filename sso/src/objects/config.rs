@@ -1,19 +1,407 @@
+use std::collections::HashSet;
+use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
+
 #[derive(Clone)]
 pub enum RepoType {
     Memory,
 }
 
+/// Where sessions are kept, independently of the main repository backend
+#[derive(Clone)]
+pub enum SessionStoreType {
+    Repo,
+    Redis(String),
+}
+
+#[derive(Clone)]
+pub enum LogFormat {
+    Json,
+    Text,
+}
+
+#[derive(Clone, Default)]
+pub struct RegistrationPolicy {
+    /// Domains that can register without an invitation, `*` opens registration to everyone
+    pub open_domains: HashSet<String>,
+    /// Domains that can not register at all, even with an invitation
+    pub blocked_domains: HashSet<String>,
+    /// Accounts registered without an invitation wait for an admin approval
+    pub approval_required: bool,
+}
+
+impl RegistrationPolicy {
+    pub fn is_open(&self, domain: &str) -> bool {
+        self.open_domains.contains("*") || self.open_domains.contains(&domain.to_lowercase())
+    }
+
+    pub fn is_blocked(&self, domain: &str) -> bool {
+        self.blocked_domains.contains(&domain.to_lowercase())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CharacterClass {
+    Lowercase,
+    Uppercase,
+    Digit,
+    Symbol,
+}
+
+impl CharacterClass {
+    pub fn matches(&self, c: char) -> bool {
+        match self {
+            CharacterClass::Lowercase => c.is_lowercase(),
+            CharacterClass::Uppercase => c.is_uppercase(),
+            CharacterClass::Digit => c.is_ascii_digit(),
+            CharacterClass::Symbol => !c.is_alphanumeric() && !c.is_whitespace(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct PasswordPolicy {
+    pub min_length: i32,
+    pub max_length: i32,
+    pub required_classes: Vec<CharacterClass>,
+    /// Refuse passwords containing the email or the name of the user
+    pub disallow_personal_info: bool,
+    /// Minimum zxcvbn score, from 0 to 4
+    pub min_strength: u8,
+    /// Directory of breached password hashes split by prefix, as in the
+    /// Have I Been Pwned range files: `<dir>/<first 5 sha1 hex chars>`
+    /// holding one `SUFFIX:COUNT` per line
+    pub breached_passwords: Option<PathBuf>,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        PasswordPolicy {
+            min_length: 6,
+            max_length: 255,
+            required_classes: vec![],
+            disallow_personal_info: false,
+            min_strength: 0,
+            breached_passwords: None,
+        }
+    }
+}
+
+/// Directory checked on login before the local accounts
+#[derive(Clone)]
+pub struct LdapConfig {
+    pub url: String,
+    /// Service account used to search the users, anonymous if unset
+    pub bind_dn: Option<String>,
+    pub bind_password: Option<String>,
+    pub base_dn: String,
+    /// `{email}` is replaced by the escaped login
+    pub user_filter: String,
+    pub name_attribute: String,
+    pub email_attribute: String,
+    /// Each value is a group, or the DN of a group whose first RDN value is the group name
+    pub group_attribute: String,
+    pub timeout: Duration,
+}
+
+impl Default for LdapConfig {
+    fn default() -> Self {
+        LdapConfig {
+            url: "ldap://localhost:389".to_string(),
+            bind_dn: None,
+            bind_password: None,
+            base_dn: String::new(),
+            user_filter: "(mail={email})".to_string(),
+            name_attribute: "cn".to_string(),
+            email_attribute: "mail".to_string(),
+            group_attribute: "memberOf".to_string(),
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// An external OIDC provider users can log in with
+#[derive(Clone)]
+pub struct UpstreamProvider {
+    /// Used in the redirect uri: `{public_url}/auth/oidc/{id}/callback`
+    pub id: String,
+    /// Shown on the login page
+    pub name: String,
+    pub authorization_url: String,
+    pub token_url: String,
+    pub userinfo_url: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub scopes: String,
+}
+
+/// Key pair signing the SAML assertions, the identity provider is off without it
+#[derive(Clone)]
+pub struct SamlIdpConfig {
+    /// PKCS#8 RSA private key in PEM
+    pub key_file: PathBuf,
+    /// Certificate published in the metadata, in PEM
+    pub cert_file: PathBuf,
+}
+
+/// A system allowed to manage the accounts through SCIM
+#[derive(Clone)]
+pub struct ProvisioningClient {
+    /// Shown in the logs and as the author of the changes
+    pub name: String,
+    /// Sent as a bearer token
+    pub token: String,
+}
+
+fn parse_domains(value: &str) -> HashSet<String> {
+    value.split(',')
+        .map(|domain| domain.trim().trim_start_matches('@').to_lowercase())
+        .filter(|domain| !domain.is_empty())
+        .collect()
+}
+
 #[derive(Clone)]
 pub struct Config {
+    /// Address the users reach the server at, used to build links sent by email
+    pub public_url: String,
     pub repo_type: RepoType,
-    pub restrict_registration: bool,
+    pub session_store: SessionStoreType,
+    pub registration: RegistrationPolicy,
+    pub password_policy: PasswordPolicy,
+    pub log_level: String,
+    pub log_format: LogFormat,
+    pub purge_interval: Duration,
+    /// Time between a deletion request and the removal of the account
+    pub deletion_grace_period: Duration,
+    /// Consecutive wrong passwords that lock an account, 0 never locks
+    pub lockout_threshold: u32,
+    /// Login attempts allowed per email and minute, 0 never limits
+    pub login_rate_limit: u32,
+    pub ldap: Option<LdapConfig>,
+    pub upstream_providers: Vec<UpstreamProvider>,
+    pub saml: Option<SamlIdpConfig>,
+    pub provisioning_clients: Vec<ProvisioningClient>,
+    /// JSON file of the applications registered at startup, see `forms::application`
+    pub applications_file: Option<PathBuf>,
+    /// Validity of the access tokens given to applications
+    pub access_token_lifetime: Duration,
+    /// Key signing the access tokens, a random one is used when unset so the tokens
+    /// do not survive a restart and are not shared between instances
+    pub token_secret: Option<String>,
+    /// Validity of a refresh token, a new one is given on every use
+    pub refresh_token_lifetime: Duration,
+    /// Time after the authorization when refreshing stops working, however often it was done
+    pub refresh_token_max_lifetime: Duration,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Config {
+            public_url: "http://localhost:8080".to_string(),
             repo_type: RepoType::Memory,
-            restrict_registration: true,
+            session_store: SessionStoreType::Repo,
+            registration: RegistrationPolicy::default(),
+            password_policy: PasswordPolicy::default(),
+            log_level: "info".to_string(),
+            log_format: LogFormat::Json,
+            purge_interval: Duration::from_secs(3600),
+            deletion_grace_period: Duration::from_secs(14 * 24 * 3600),
+            lockout_threshold: 0,
+            login_rate_limit: 0,
+            ldap: None,
+            upstream_providers: vec![],
+            saml: None,
+            provisioning_clients: vec![],
+            applications_file: None,
+            access_token_lifetime: Duration::from_secs(15 * 60),
+            token_secret: None,
+            refresh_token_lifetime: Duration::from_secs(7 * 24 * 3600),
+            refresh_token_max_lifetime: Duration::from_secs(30 * 24 * 3600),
+        }
+    }
+}
+
+impl Config {
+    /// Build the config from `SSO_*` environment variables, falling back to the defaults
+    pub fn from_env() -> Self {
+        let mut config = Config::default();
+
+        if let Ok(url) = env::var("SSO_PUBLIC_URL") {
+            config.public_url = url.trim_end_matches('/').to_string();
+        }
+        if let Ok(url) = env::var("SSO_REDIS_URL") {
+            config.session_store = SessionStoreType::Redis(url);
+        }
+        if let Ok(level) = env::var("SSO_LOG_LEVEL") {
+            config.log_level = level;
+        }
+        if let Ok(format) = env::var("SSO_LOG_FORMAT") {
+            config.log_format = match format.as_str() {
+                "text" => LogFormat::Text,
+                _ => LogFormat::Json,
+            };
+        }
+        // A zero interval would make the purge task panic at startup
+        if let Some(seconds) = env::var("SSO_PURGE_INTERVAL").ok().and_then(|s| s.parse().ok()).filter(|seconds| *seconds > 0) {
+            config.purge_interval = Duration::from_secs(seconds);
+        }
+        if let Some(period) = env::var("SSO_DELETION_GRACE_DAYS").ok().and_then(|s| parse_days(&s, 0)) {
+            config.deletion_grace_period = period;
+        }
+        if let Some(threshold) = env::var("SSO_LOCKOUT_THRESHOLD").ok().and_then(|s| s.parse().ok()) {
+            config.lockout_threshold = threshold;
+        }
+        if let Some(limit) = env::var("SSO_LOGIN_RATE_LIMIT").ok().and_then(|s| s.parse().ok()) {
+            config.login_rate_limit = limit;
+        }
+        if let Ok(domains) = env::var("SSO_REGISTRATION_OPEN_DOMAINS") {
+            config.registration.open_domains = parse_domains(&domains);
+        }
+        if let Ok(domains) = env::var("SSO_REGISTRATION_BLOCKED_DOMAINS") {
+            config.registration.blocked_domains = parse_domains(&domains);
+        }
+        if let Ok(approval) = env::var("SSO_REGISTRATION_APPROVAL") {
+            config.registration.approval_required = approval == "true";
+        }
+        if let Some(length) = env::var("SSO_PASSWORD_MIN_LENGTH").ok().and_then(|s| s.parse().ok()) {
+            config.password_policy.min_length = length;
+        }
+        if let Ok(classes) = env::var("SSO_PASSWORD_CLASSES") {
+            config.password_policy.required_classes = classes.split(',')
+                .filter_map(|class| match class.trim() {
+                    "lower" => Some(CharacterClass::Lowercase),
+                    "upper" => Some(CharacterClass::Uppercase),
+                    "digit" => Some(CharacterClass::Digit),
+                    "symbol" => Some(CharacterClass::Symbol),
+                    _ => None,
+                })
+                .collect();
+        }
+        if let Ok(value) = env::var("SSO_PASSWORD_DISALLOW_PERSONAL_INFO") {
+            config.password_policy.disallow_personal_info = value == "true";
+        }
+        if let Some(strength) = env::var("SSO_PASSWORD_MIN_STRENGTH").ok().and_then(|s| s.parse().ok()) {
+            config.password_policy.min_strength = strength;
+        }
+        if let Ok(dir) = env::var("SSO_BREACHED_PASSWORDS_DIR") {
+            config.password_policy.breached_passwords = Some(PathBuf::from(dir));
+        }
+        if let Ok(url) = env::var("SSO_LDAP_URL") {
+            let mut ldap = LdapConfig { url, ..LdapConfig::default() };
+            ldap.bind_dn = env::var("SSO_LDAP_BIND_DN").ok();
+            ldap.bind_password = env::var("SSO_LDAP_BIND_PASSWORD").ok();
+            if let Ok(base_dn) = env::var("SSO_LDAP_BASE_DN") {
+                ldap.base_dn = base_dn;
+            }
+            if let Ok(filter) = env::var("SSO_LDAP_USER_FILTER") {
+                ldap.user_filter = filter;
+            }
+            if let Ok(attribute) = env::var("SSO_LDAP_NAME_ATTRIBUTE") {
+                ldap.name_attribute = attribute;
+            }
+            if let Ok(attribute) = env::var("SSO_LDAP_EMAIL_ATTRIBUTE") {
+                ldap.email_attribute = attribute;
+            }
+            if let Ok(attribute) = env::var("SSO_LDAP_GROUP_ATTRIBUTE") {
+                ldap.group_attribute = attribute;
+            }
+            config.ldap = Some(ldap);
+        }
+        if let Ok(ids) = env::var("SSO_OIDC_PROVIDERS") {
+            config.upstream_providers = ids.split(',')
+                .map(str::trim)
+                .filter(|id| !id.is_empty())
+                .filter_map(upstream_provider_from_env)
+                .collect();
         }
+        if let (Ok(key), Ok(cert)) = (env::var("SSO_SAML_KEY_FILE"), env::var("SSO_SAML_CERT_FILE")) {
+            config.saml = Some(SamlIdpConfig {
+                key_file: PathBuf::from(key),
+                cert_file: PathBuf::from(cert),
+            });
+        }
+        if let Ok(clients) = env::var("SSO_SCIM_TOKENS") {
+            config.provisioning_clients = clients.split(',')
+                .filter_map(|client| client.trim().split_once(':'))
+                .filter(|(_, token)| !token.is_empty())
+                .map(|(name, token)| ProvisioningClient { name: name.to_string(), token: token.to_string() })
+                .collect();
+        }
+        if let Ok(file) = env::var("SSO_APPLICATIONS_FILE") {
+            config.applications_file = Some(PathBuf::from(file));
+        }
+        if let Some(lifetime) = env::var("SSO_ACCESS_TOKEN_SECONDS").ok().and_then(|s| parse_seconds(&s)) {
+            config.access_token_lifetime = lifetime;
+        }
+        config.token_secret = env::var("SSO_TOKEN_SECRET").ok().filter(|secret| !secret.is_empty());
+        if let Some(lifetime) = env::var("SSO_REFRESH_TOKEN_DAYS").ok().and_then(|s| parse_days(&s, 1)) {
+            config.refresh_token_lifetime = lifetime;
+        }
+        if let Some(lifetime) = env::var("SSO_REFRESH_TOKEN_MAX_DAYS").ok().and_then(|s| parse_days(&s, 1)) {
+            config.refresh_token_max_lifetime = lifetime;
+        }
+
+        config
     }
-}
\ No newline at end of file
+}
+
+/// Longest duration accepted in days, longer ones could not be added to a date
+const MAX_DAYS: u64 = 3650;
+
+/// A number of days between `min` and `MAX_DAYS`, anything else is ignored
+fn parse_days(value: &str, min: u64) -> Option<Duration> {
+    let days = value.trim().parse::<u64>().ok().filter(|days| (min..=MAX_DAYS).contains(days))?;
+    days.checked_mul(24 * 3600).map(Duration::from_secs)
+}
+
+/// Longest access token lifetime in seconds
+const MAX_ACCESS_TOKEN_SECONDS: u64 = 24 * 3600;
+
+/// A number of seconds between 1 and `MAX_ACCESS_TOKEN_SECONDS`, anything else is ignored
+fn parse_seconds(value: &str) -> Option<Duration> {
+    let seconds = value.trim().parse::<u64>().ok().filter(|seconds| (1..=MAX_ACCESS_TOKEN_SECONDS).contains(seconds))?;
+    Some(Duration::from_secs(seconds))
+}
+
+/// Read `SSO_OIDC_<ID>_*`, skipping providers with a missing endpoint or client
+fn upstream_provider_from_env(id: &str) -> Option<UpstreamProvider> {
+    let prefix = format!("SSO_OIDC_{}_", id.to_uppercase());
+    let var = |name: &str| env::var(format!("{}{}", prefix, name)).ok();
+
+    let provider = UpstreamProvider {
+        id: id.to_lowercase(),
+        name: var("NAME").unwrap_or_else(|| id.to_string()),
+        authorization_url: var("AUTHORIZATION_URL")?,
+        token_url: var("TOKEN_URL")?,
+        userinfo_url: var("USERINFO_URL")?,
+        client_id: var("CLIENT_ID")?,
+        client_secret: var("CLIENT_SECRET")?,
+        scopes: var("SCOPES").unwrap_or_else(|| "openid email profile".to_string()),
+    };
+    Some(provider)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_days() {
+        assert_eq!(Some(Duration::from_secs(14 * 24 * 3600)), parse_days("14", 0));
+        assert_eq!(Some(Duration::ZERO), parse_days("0", 0));
+        assert_eq!(None, parse_days("0", 1));
+        assert_eq!(None, parse_days("3651", 0));
+        assert_eq!(None, parse_days("18446744073709551615", 0));
+        assert_eq!(None, parse_days("-1", 0));
+    }
+
+    #[test]
+    fn test_parse_seconds() {
+        assert_eq!(Some(Duration::from_secs(900)), parse_seconds("900"));
+        assert_eq!(Some(Duration::from_secs(24 * 3600)), parse_seconds("86400"));
+        assert_eq!(None, parse_seconds("0"));
+        assert_eq!(None, parse_seconds("86401"));
+        assert_eq!(None, parse_seconds("9223372036854775807"));
+    }
+}
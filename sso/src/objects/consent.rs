@@ -0,0 +1,12 @@
+use std::collections::BTreeSet;
+use chrono::{DateTime, Utc};
+
+/// Scopes a user agreed to share with an application, asked again when it wants more
+#[derive(Clone, Debug)]
+pub struct Consent {
+    /// Id of the user
+    pub user: String,
+    pub client_id: String,
+    pub scopes: BTreeSet<String>,
+    pub granted: DateTime<Utc>,
+}
@@ -0,0 +1,11 @@
+use chrono::{DateTime, Utc};
+
+/// A request to change the email of a user, applied once the new address is verified
+#[derive(Clone)]
+pub struct EmailChange {
+    pub value: String,
+    /// Id of the user
+    pub user: String,
+    pub new_email: String,
+    pub expiration: DateTime<Utc>,
+}
@@ -0,0 +1,24 @@
+use std::fmt::{Debug, Formatter};
+use chrono::{DateTime, Utc};
+
+/// A login started on an upstream provider, waiting for the user to come back
+#[derive(Clone)]
+pub struct FederationState {
+    /// Sent as the OAuth `state` parameter
+    pub value: String,
+    pub provider: String,
+    /// PKCE code verifier
+    pub verifier: String,
+    pub expiration: DateTime<Utc>,
+}
+
+impl Debug for FederationState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FederationState")
+            .field("value", &"[redacted]")
+            .field("provider", &self.provider)
+            .field("verifier", &"[redacted]")
+            .field("expiration", &self.expiration)
+            .finish()
+    }
+}
@@ -0,0 +1,23 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// A named group, the members keep the name in `User::groups`
+#[derive(Clone)]
+pub struct Group {
+    /// Stays the same when the group is renamed
+    pub id: String,
+    pub name: String,
+    pub created: DateTime<Utc>,
+}
+
+impl Group {
+    /// A group given by an invitation or a provider, which are only known by name on their members.
+    /// Its id is derived from the name, so that it is the same before and after it is stored
+    pub fn named(name: &str, created: DateTime<Utc>) -> Self {
+        Self {
+            id: Uuid::new_v5(&Uuid::NAMESPACE_OID, format!("group:{}", name).as_bytes()).to_string(),
+            name: name.to_string(),
+            created,
+        }
+    }
+}
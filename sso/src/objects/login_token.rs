@@ -1,8 +1,20 @@
+use std::fmt::{Debug, Formatter};
 use chrono::{DateTime, Utc};
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct LoginToken {
     pub value: String,
+    /// Id of the user
     pub user: String,
     pub expiration: DateTime<Utc>,
-}
\ No newline at end of file
+}
+
+impl Debug for LoginToken {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LoginToken")
+            .field("value", &"[redacted]")
+            .field("user", &self.user)
+            .field("expiration", &self.expiration)
+            .finish()
+    }
+}
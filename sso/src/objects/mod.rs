@@ -2,4 +2,11 @@ pub mod user;
 pub mod application;
 pub mod registration_token;
 pub mod login_token;
-pub mod config;
\ No newline at end of file
+pub mod email_change;
+pub mod audit_event;
+pub mod federation_state;
+pub mod config;
+pub mod group;
+pub mod authorization_code;
+pub mod refresh_token;
+pub mod consent;
\ No newline at end of file
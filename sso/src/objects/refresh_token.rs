@@ -0,0 +1,35 @@
+use std::fmt::{Debug, Formatter};
+use chrono::{DateTime, Utc};
+
+/// A refresh token, replaced by a new one of the same family on every use
+#[derive(Clone)]
+pub struct RefreshToken {
+    pub value: String,
+    /// Shared by the tokens rotated from the same authorization, revoked together
+    pub family: String,
+    pub client_id: String,
+    /// Id of the user
+    pub user: String,
+    /// The login token the application was authorized from, invalidating it revokes the family
+    pub session: String,
+    pub scope: String,
+    /// Set once the token has been exchanged, using it again means it leaked
+    pub used: bool,
+    pub expiration: DateTime<Utc>,
+    /// No token of the family is valid after this date, however often it is rotated
+    pub family_expiration: DateTime<Utc>,
+}
+
+impl Debug for RefreshToken {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RefreshToken")
+            .field("value", &"[redacted]")
+            .field("family", &self.family)
+            .field("client_id", &self.client_id)
+            .field("user", &self.user)
+            .field("scope", &self.scope)
+            .field("used", &self.used)
+            .field("expiration", &self.expiration)
+            .finish()
+    }
+}
@@ -1,7 +1,46 @@
+use std::collections::HashSet;
 use chrono::{DateTime, Utc};
 
+/// Who may use an invitation
+#[derive(Clone)]
+pub enum InviteRestriction {
+    Email(String),
+    Domain(String),
+}
+
+#[derive(Clone)]
+pub struct RegisterTokenUse {
+    pub email: String,
+    pub date: DateTime<Utc>,
+}
+
 #[derive(Clone)]
 pub struct RegisterToken {
     pub value: String,
     pub expiration: DateTime<Utc>,
-}
\ No newline at end of file
+    pub max_uses: u32,
+    pub uses: Vec<RegisterTokenUse>,
+    pub restriction: Option<InviteRestriction>,
+    /// Groups given to the users registering with this token
+    pub groups: HashSet<String>,
+    /// Client ids of the applications the users get access to
+    pub applications: HashSet<String>,
+    /// Id of the admin who created the invitation
+    pub created_by: Option<String>,
+}
+
+impl RegisterToken {
+    pub fn is_exhausted(&self) -> bool {
+        self.uses.len() >= self.max_uses as usize
+    }
+
+    pub fn allows(&self, email: &str) -> bool {
+        match &self.restriction {
+            None => true,
+            Some(InviteRestriction::Email(allowed)) => allowed.eq_ignore_ascii_case(email),
+            Some(InviteRestriction::Domain(domain)) => email
+                .rsplit_once('@')
+                .is_some_and(|(_, email_domain)| email_domain.eq_ignore_ascii_case(domain)),
+        }
+    }
+}
@@ -1,10 +1,64 @@
+use std::collections::{HashMap, HashSet};
 use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum UserStatus {
+    Active,
+    /// Registered, waiting for an admin to approve the account
+    Pending,
+    /// Suspended by an admin, the data is kept until the account is enabled again
+    Disabled,
+    /// Too many failed logins, an admin has to enable the account again
+    Locked,
+}
+
+/// Who took the account out of the active status, and why
+#[derive(Clone, Debug)]
+pub struct StatusChange {
+    /// Id of the admin, or `system` for automatic changes
+    pub by: String,
+    pub reason: String,
+    pub date: DateTime<Utc>,
+}
 
 #[derive(Clone)]
 pub struct User {
+    /// Never changes, unlike the email, and is the `sub` given to applications
+    pub id: String,
     pub name: String,
     pub email: String,
     pub password: String,
     pub admin: bool,
+    pub groups: HashSet<String>,
+    pub status: UserStatus,
+    /// Set while the account is disabled or locked
+    pub status_change: Option<StatusChange>,
+    /// Wrong passwords entered since the last successful login
+    pub failed_logins: u32,
+    /// Name of the authentication provider managing the password, `None` for local accounts
+    pub provider: Option<String>,
+    /// Upstream OIDC provider id to the `sub` of the user there
+    pub federated: HashMap<String, String>,
+    pub avatar_url: Option<String>,
+    pub timezone: String,
+    pub locale: String,
+    /// The account is removed at this date unless the user cancels the deletion
+    pub deletion_scheduled: Option<DateTime<Utc>>,
     pub created: DateTime<Utc>
-}
\ No newline at end of file
+}
+
+impl User {
+    pub fn new_id() -> String {
+        Uuid::new_v4().to_string()
+    }
+
+    /// Shown in place of the avatar when there is none
+    pub fn initials(&self) -> String {
+        self.name.split_whitespace()
+            .filter_map(|word| word.chars().next())
+            .take(2)
+            .flat_map(char::to_uppercase)
+            .collect()
+    }
+}
@@ -1,17 +1,94 @@
-pub trait ApplicationRepo {
+use std::collections::HashMap;
+use std::sync::Arc;
+use async_trait::async_trait;
+use tokio::sync::RwLock;
+use crate::errors::repo::RepoError;
+use crate::objects::application::{Application, Protocol};
 
+#[async_trait]
+pub trait ApplicationRepo: Send + Sync {
+    async fn get_by_client_id(&self, client_id: &str) -> Option<Application>;
+    async fn get_all(&self) -> Vec<Application>;
+    /// Find the SAML application registered with an entity id
+    async fn get_by_entity_id(&self, entity_id: &str) -> Option<Application>;
+    #[cfg(test)]
+    async fn add(&self, application: Application);
+    /// Give a user access to an application, returns false if the application does not exist
+    async fn add_user(&self, client_id: &str, user: &str) -> bool;
+    async fn remove_user(&self, client_id: &str, user: &str);
+    /// Returns false if the application does not exist
+    async fn set_pre_consented(&self, client_id: &str, pre_consented: bool) -> bool;
+    async fn ping(&self) -> Result<(), RepoError>;
 }
 
 pub struct ApplicationRepoMemory {
-
+    applications: Arc<RwLock<HashMap<String, Application>>>,
 }
 
 impl ApplicationRepoMemory {
     pub fn new() -> Self {
-        Self{}
+        Self {
+            applications: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    pub fn with(applications: Vec<Application>) -> Self {
+        Self {
+            applications: Arc::new(RwLock::new(applications.into_iter()
+                .map(|application| (application.client_id.clone(), application))
+                .collect())),
+        }
     }
 }
 
+#[async_trait]
 impl ApplicationRepo for ApplicationRepoMemory {
+    async fn get_by_client_id(&self, client_id: &str) -> Option<Application> {
+        self.applications.read().await.get(client_id).cloned()
+    }
+
+    async fn get_all(&self) -> Vec<Application> {
+        self.applications.read().await.values().cloned().collect()
+    }
+
+    async fn get_by_entity_id(&self, entity_id: &str) -> Option<Application> {
+        self.applications.read().await.values()
+            .find(|application| matches!(&application.protocol, Protocol::Saml(saml) if saml.entity_id == entity_id))
+            .cloned()
+    }
+
+    #[cfg(test)]
+    async fn add(&self, application: Application) {
+        self.applications.write().await.insert(application.client_id.clone(), application);
+    }
+
+    async fn add_user(&self, client_id: &str, user: &str) -> bool {
+        match self.applications.write().await.get_mut(client_id) {
+            None => false,
+            Some(application) => {
+                application.users.insert(user.to_string());
+                true
+            }
+        }
+    }
+
+    async fn remove_user(&self, client_id: &str, user: &str) {
+        if let Some(application) = self.applications.write().await.get_mut(client_id) {
+            application.users.remove(user);
+        }
+    }
 
-}
\ No newline at end of file
+    async fn set_pre_consented(&self, client_id: &str, pre_consented: bool) -> bool {
+        match self.applications.write().await.get_mut(client_id) {
+            None => false,
+            Some(application) => {
+                application.pre_consented = pre_consented;
+                true
+            }
+        }
+    }
+
+    async fn ping(&self) -> Result<(), RepoError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+use tokio::sync::RwLock;
+use crate::errors::repo::RepoError;
+use crate::objects::audit_event::AuditEvent;
+
+#[async_trait]
+pub trait AuditRepo: Send + Sync {
+    async fn add(&self, event: AuditEvent);
+    /// Every event where the user is the actor or the subject, oldest first
+    async fn get_for_user(&self, user: &str) -> Vec<AuditEvent>;
+    /// Rewrite the events of a user under another name, returning how many were changed
+    async fn replace_user(&self, user: &str, replacement: &str) -> usize;
+    async fn ping(&self) -> Result<(), RepoError>;
+}
+
+pub struct AuditRepoMemory {
+    events: Arc<RwLock<Vec<AuditEvent>>>,
+}
+
+impl AuditRepoMemory {
+    pub fn new() -> Self {
+        Self {
+            events: Arc::new(RwLock::new(Vec::new())),
+        }
+    }
+}
+
+#[async_trait]
+impl AuditRepo for AuditRepoMemory {
+    async fn add(&self, event: AuditEvent) {
+        self.events.write().await.push(event);
+    }
+
+    async fn get_for_user(&self, user: &str) -> Vec<AuditEvent> {
+        let mut events: Vec<AuditEvent> = self.events.read().await.iter()
+            .filter(|event| event.actor == user || event.subject == user)
+            .cloned()
+            .collect();
+        events.sort_by_key(|event| event.date);
+        events
+    }
+
+    async fn replace_user(&self, user: &str, replacement: &str) -> usize {
+        let mut changed = 0;
+        for event in self.events.write().await.iter_mut() {
+            let mut touched = false;
+            if event.actor == user {
+                event.actor = replacement.to_string();
+                touched = true;
+            }
+            if event.subject == user {
+                event.subject = replacement.to_string();
+                touched = true;
+            }
+            if touched {
+                changed += 1;
+            }
+        }
+        changed
+    }
+
+    async fn ping(&self) -> Result<(), RepoError> {
+        Ok(())
+    }
+}
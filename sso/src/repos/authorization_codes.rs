@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use tokio::sync::RwLock;
+use crate::errors::repo::RepoError;
+use crate::objects::authorization_code::AuthorizationCode;
+
+#[async_trait]
+pub trait AuthorizationCodeRepo: Send + Sync {
+    async fn add(&self, code: AuthorizationCode);
+    /// Remove the code and return it, so that it can only be exchanged once
+    async fn take(&self, value: &str) -> Option<AuthorizationCode>;
+    /// Remove every code expired at `now`, returning how many were removed
+    async fn delete_expired(&self, now: DateTime<Utc>) -> usize;
+    async fn ping(&self) -> Result<(), RepoError>;
+}
+
+pub struct AuthorizationCodeRepoMemory {
+    codes: Arc<RwLock<HashMap<String, AuthorizationCode>>>,
+}
+
+impl AuthorizationCodeRepoMemory {
+    pub fn new() -> Self {
+        Self {
+            codes: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+}
+
+#[async_trait]
+impl AuthorizationCodeRepo for AuthorizationCodeRepoMemory {
+    async fn add(&self, code: AuthorizationCode) {
+        self.codes.write().await.insert(code.value.clone(), code);
+    }
+
+    async fn take(&self, value: &str) -> Option<AuthorizationCode> {
+        self.codes.write().await.remove(value)
+    }
+
+    async fn delete_expired(&self, now: DateTime<Utc>) -> usize {
+        let mut codes = self.codes.write().await;
+        let before = codes.len();
+        codes.retain(|_, code| code.expiration >= now);
+        before - codes.len()
+    }
+
+    async fn ping(&self) -> Result<(), RepoError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use async_trait::async_trait;
+use tokio::sync::RwLock;
+use crate::errors::repo::RepoError;
+use crate::objects::consent::Consent;
+
+#[async_trait]
+pub trait ConsentRepo: Send + Sync {
+    async fn get(&self, user: &str, client_id: &str) -> Option<Consent>;
+    async fn get_for_user(&self, user: &str) -> Vec<Consent>;
+    /// Add the consent, replacing the previous one of the user for the application
+    async fn add(&self, consent: Consent);
+    /// Returns false if the user had not consented
+    async fn delete(&self, user: &str, client_id: &str) -> bool;
+    /// Remove every consent of a user, returning how many were removed
+    async fn delete_for_user(&self, user: &str) -> usize;
+    async fn ping(&self) -> Result<(), RepoError>;
+}
+
+pub struct ConsentRepoMemory {
+    consents: Arc<RwLock<HashMap<(String, String), Consent>>>,
+}
+
+impl ConsentRepoMemory {
+    pub fn new() -> Self {
+        Self {
+            consents: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+}
+
+#[async_trait]
+impl ConsentRepo for ConsentRepoMemory {
+    async fn get(&self, user: &str, client_id: &str) -> Option<Consent> {
+        self.consents.read().await.get(&(user.to_string(), client_id.to_string())).cloned()
+    }
+
+    async fn get_for_user(&self, user: &str) -> Vec<Consent> {
+        self.consents.read().await.values()
+            .filter(|consent| consent.user == user)
+            .cloned()
+            .collect()
+    }
+
+    async fn add(&self, consent: Consent) {
+        let key = (consent.user.clone(), consent.client_id.clone());
+        self.consents.write().await.insert(key, consent);
+    }
+
+    async fn delete(&self, user: &str, client_id: &str) -> bool {
+        self.consents.write().await.remove(&(user.to_string(), client_id.to_string())).is_some()
+    }
+
+    async fn delete_for_user(&self, user: &str) -> usize {
+        let mut consents = self.consents.write().await;
+        let before = consents.len();
+        consents.retain(|_, consent| consent.user != user);
+        before - consents.len()
+    }
+
+    async fn ping(&self) -> Result<(), RepoError> {
+        Ok(())
+    }
+}
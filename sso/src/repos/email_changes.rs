@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use tokio::sync::RwLock;
+use crate::errors::repo::RepoError;
+use crate::objects::email_change::EmailChange;
+
+#[async_trait]
+pub trait EmailChangeRepo: Send + Sync {
+    async fn get_by_value(&self, value: &str) -> Option<EmailChange>;
+    /// Replace any pending change of the same user
+    async fn add(&self, change: EmailChange);
+    async fn delete(&self, value: &str);
+    /// Remove every change expired at `now`, returning how many were removed
+    async fn delete_expired(&self, now: DateTime<Utc>) -> usize;
+    async fn ping(&self) -> Result<(), RepoError>;
+}
+
+pub struct EmailChangeRepoMemory {
+    changes: Arc<RwLock<HashMap<String, EmailChange>>>,
+}
+
+impl EmailChangeRepoMemory {
+    pub fn new() -> Self {
+        Self {
+            changes: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+}
+
+#[async_trait]
+impl EmailChangeRepo for EmailChangeRepoMemory {
+    async fn get_by_value(&self, value: &str) -> Option<EmailChange> {
+        self.changes.read().await.get(value).cloned()
+    }
+
+    async fn add(&self, change: EmailChange) {
+        let mut changes = self.changes.write().await;
+        changes.retain(|_, existing| existing.user != change.user);
+        changes.insert(change.value.clone(), change);
+    }
+
+    async fn delete(&self, value: &str) {
+        self.changes.write().await.remove(value);
+    }
+
+    async fn delete_expired(&self, now: DateTime<Utc>) -> usize {
+        let mut changes = self.changes.write().await;
+        let before = changes.len();
+        changes.retain(|_, change| change.expiration >= now);
+        before - changes.len()
+    }
+
+    async fn ping(&self) -> Result<(), RepoError> {
+        Ok(())
+    }
+}
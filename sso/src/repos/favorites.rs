@@ -0,0 +1,57 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use async_trait::async_trait;
+use tokio::sync::RwLock;
+use crate::errors::repo::RepoError;
+
+/// Applications each user pinned on the launcher
+#[async_trait]
+pub trait FavoriteRepo: Send + Sync {
+    /// Client ids of the favorites of the user
+    async fn get_for_user(&self, user: &str) -> HashSet<String>;
+    async fn add(&self, user: &str, client_id: &str);
+    async fn remove(&self, user: &str, client_id: &str);
+    /// Remove every favorite of a user, returning how many were removed
+    async fn delete_for_user(&self, user: &str) -> usize;
+    async fn ping(&self) -> Result<(), RepoError>;
+}
+
+pub struct FavoriteRepoMemory {
+    favorites: Arc<RwLock<HashMap<String, HashSet<String>>>>,
+}
+
+impl FavoriteRepoMemory {
+    pub fn new() -> Self {
+        Self {
+            favorites: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+}
+
+#[async_trait]
+impl FavoriteRepo for FavoriteRepoMemory {
+    async fn get_for_user(&self, user: &str) -> HashSet<String> {
+        self.favorites.read().await.get(user).cloned().unwrap_or_default()
+    }
+
+    async fn add(&self, user: &str, client_id: &str) {
+        self.favorites.write().await
+            .entry(user.to_string())
+            .or_default()
+            .insert(client_id.to_string());
+    }
+
+    async fn remove(&self, user: &str, client_id: &str) {
+        if let Some(favorites) = self.favorites.write().await.get_mut(user) {
+            favorites.remove(client_id);
+        }
+    }
+
+    async fn delete_for_user(&self, user: &str) -> usize {
+        self.favorites.write().await.remove(user).map_or(0, |favorites| favorites.len())
+    }
+
+    async fn ping(&self) -> Result<(), RepoError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use tokio::sync::RwLock;
+use crate::errors::repo::RepoError;
+use crate::objects::federation_state::FederationState;
+
+#[async_trait]
+pub trait FederationStateRepo: Send + Sync {
+    async fn add(&self, state: FederationState);
+    /// Remove the state and return it, so that it can only be used once
+    async fn take(&self, value: &str) -> Option<FederationState>;
+    /// Remove every state expired at `now`, returning how many were removed
+    async fn delete_expired(&self, now: DateTime<Utc>) -> usize;
+    async fn ping(&self) -> Result<(), RepoError>;
+}
+
+pub struct FederationStateRepoMemory {
+    states: Arc<RwLock<HashMap<String, FederationState>>>,
+}
+
+impl FederationStateRepoMemory {
+    pub fn new() -> Self {
+        Self {
+            states: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+}
+
+#[async_trait]
+impl FederationStateRepo for FederationStateRepoMemory {
+    async fn add(&self, state: FederationState) {
+        self.states.write().await.insert(state.value.clone(), state);
+    }
+
+    async fn take(&self, value: &str) -> Option<FederationState> {
+        self.states.write().await.remove(value)
+    }
+
+    async fn delete_expired(&self, now: DateTime<Utc>) -> usize {
+        let mut states = self.states.write().await;
+        let before = states.len();
+        states.retain(|_, state| state.expiration >= now);
+        before - states.len()
+    }
+
+    async fn ping(&self) -> Result<(), RepoError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use async_trait::async_trait;
+use tokio::sync::RwLock;
+use crate::errors::repo::RepoError;
+use crate::objects::group::Group;
+
+#[async_trait]
+pub trait GroupRepo: Send + Sync {
+    async fn get_by_id(&self, id: &str) -> Option<Group>;
+    async fn get_by_name(&self, name: &str) -> Option<Group>;
+    async fn get_all(&self) -> Vec<Group>;
+    /// Insert or replace the group with the same id, fails with a conflict
+    /// if another group has the same name
+    async fn add(&self, group: Group) -> Result<(), RepoError>;
+    async fn delete(&self, id: &str);
+    async fn ping(&self) -> Result<(), RepoError>;
+}
+
+pub struct GroupRepoMemory {
+    groups: Arc<RwLock<HashMap<String, Group>>>,
+}
+
+impl GroupRepoMemory {
+    pub fn new() -> Self {
+        Self {
+            groups: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+}
+
+#[async_trait]
+impl GroupRepo for GroupRepoMemory {
+    async fn get_by_id(&self, id: &str) -> Option<Group> {
+        self.groups.read().await.get(id).cloned()
+    }
+
+    async fn get_by_name(&self, name: &str) -> Option<Group> {
+        self.groups.read().await.values()
+            .find(|group| group.name == name)
+            .cloned()
+    }
+
+    async fn get_all(&self) -> Vec<Group> {
+        self.groups.read().await.values().cloned().collect()
+    }
+
+    async fn add(&self, group: Group) -> Result<(), RepoError> {
+        let mut groups = self.groups.write().await;
+        if groups.values().any(|other| other.name == group.name && other.id != group.id) {
+            return Err(RepoError::Conflict(format!("group {} already exists", group.name)));
+        }
+        groups.insert(group.id.clone(), group);
+        Ok(())
+    }
+
+    async fn delete(&self, id: &str) {
+        self.groups.write().await.remove(id);
+    }
+
+    async fn ping(&self) -> Result<(), RepoError> {
+        Ok(())
+    }
+}
@@ -1,40 +1,88 @@
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use tokio::sync::RwLock;
+use crate::errors::repo::RepoError;
 use crate::objects::login_token::LoginToken;
 
-pub trait LoginTokenRepo {
-    fn get_by_value(&self, value: &str) -> Option<LoginToken>;
-    fn get_all(&self) -> Vec<LoginToken>;
-    fn add(&self, token: LoginToken);
-    fn delete(&self, token: &str);
+#[async_trait]
+pub trait LoginTokenRepo: Send + Sync {
+    async fn get_by_value(&self, value: &str) -> Option<LoginToken>;
+    /// Every token still valid at `now`
+    async fn get_all(&self, now: DateTime<Utc>) -> Vec<LoginToken>;
+    /// Every token of a user still valid at `now`
+    async fn get_for_user(&self, user: &str, now: DateTime<Utc>) -> Vec<LoginToken>;
+    async fn add(&self, token: LoginToken);
+    async fn delete(&self, token: &str);
+    /// Remove every token expired at `now`, returning how many were removed
+    async fn delete_expired(&self, now: DateTime<Utc>) -> usize;
+    /// Remove every token of a user but `keep`, returning how many were still valid at `now`
+    async fn delete_for_user(&self, user: &str, keep: Option<&str>, now: DateTime<Utc>) -> usize;
+    async fn ping(&self) -> Result<(), RepoError>;
 }
 
 pub struct LoginTokenRepoMemory {
-    tokens: Arc<Mutex<HashMap<String, LoginToken>>>
+    tokens: Arc<RwLock<HashMap<String, LoginToken>>>
 }
 
 impl LoginTokenRepoMemory {
     pub fn new() -> Self {
         Self {
-            tokens: Arc::new(Mutex::new(HashMap::new()))
+            tokens: Arc::new(RwLock::new(HashMap::new()))
         }
     }
 }
+#[async_trait]
 impl LoginTokenRepo for LoginTokenRepoMemory {
 
-    fn get_by_value(&self, value: &str) -> Option<LoginToken> {
-        self.tokens.lock().unwrap().get(value).cloned()
+    async fn get_by_value(&self, value: &str) -> Option<LoginToken> {
+        self.tokens.read().await.get(value).cloned()
     }
 
-    fn get_all(&self) -> Vec<LoginToken> {
-        self.tokens.lock().unwrap().values().cloned().collect()
+    async fn get_all(&self, now: DateTime<Utc>) -> Vec<LoginToken> {
+        self.tokens.read().await.values()
+            .filter(|token| token.expiration >= now)
+            .cloned()
+            .collect()
     }
 
-    fn add(&self, token: LoginToken) {
-        self.tokens.lock().unwrap().insert(token.value.clone(), token);
+    async fn get_for_user(&self, user: &str, now: DateTime<Utc>) -> Vec<LoginToken> {
+        self.tokens.read().await.values()
+            .filter(|token| token.user == user && token.expiration >= now)
+            .cloned()
+            .collect()
     }
 
-    fn delete(&self, token: &str) {
-        self.tokens.lock().unwrap().remove(token);
+    async fn add(&self, token: LoginToken) {
+        self.tokens.write().await.insert(token.value.clone(), token);
+    }
+
+    async fn delete(&self, token: &str) {
+        self.tokens.write().await.remove(token);
+    }
+
+    async fn delete_expired(&self, now: DateTime<Utc>) -> usize {
+        let mut tokens = self.tokens.write().await;
+        let before = tokens.len();
+        tokens.retain(|_, token| token.expiration >= now);
+        before - tokens.len()
+    }
+
+    async fn delete_for_user(&self, user: &str, keep: Option<&str>, now: DateTime<Utc>) -> usize {
+        let mut tokens = self.tokens.write().await;
+        let mut closed = 0;
+        tokens.retain(|value, token| {
+            let kept = token.user != user || Some(value.as_str()) == keep;
+            if !kept && token.expiration >= now {
+                closed += 1;
+            }
+            kept
+        });
+        closed
+    }
+
+    async fn ping(&self) -> Result<(), RepoError> {
+        Ok(())
     }
 }
\ No newline at end of file
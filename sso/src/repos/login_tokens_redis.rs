@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use redis::RedisResult;
+use crate::errors::repo::RepoError;
+use crate::objects::login_token::LoginToken;
+use crate::repos::login_tokens::LoginTokenRepo;
+use crate::repos::redis_connection::RedisConnection;
+
+const PREFIX: &str = "sso:login_token:";
+/// Sorted set of every token value, scored by expiration
+const INDEX: &str = "sso:login_tokens";
+/// Sorted set of the token values of a user, scored by expiration
+const USER_PREFIX: &str = "sso:login_tokens:user:";
+
+/// Login tokens stored as Redis hashes expiring with the token,
+/// so expired sessions disappear without the maintenance task.
+/// The indexes keep the values of expired tokens until `delete_expired`.
+pub struct LoginTokenRepoRedis {
+    connection: RedisConnection,
+}
+
+impl LoginTokenRepoRedis {
+    pub fn new(connection: RedisConnection) -> Self {
+        Self {
+            connection,
+        }
+    }
+
+    fn key(value: &str) -> String {
+        format!("{}{}", PREFIX, value)
+    }
+
+    fn user_key(user: &str) -> String {
+        format!("{}{}", USER_PREFIX, user)
+    }
+
+    fn parse(value: &str, fields: HashMap<String, String>) -> Option<LoginToken> {
+        let expiration = fields.get("expiration")?.parse::<i64>().ok()?;
+        Some(LoginToken {
+            value: value.to_string(),
+            user: fields.get("user")?.clone(),
+            expiration: DateTime::from_timestamp(expiration, 0)?,
+        })
+    }
+
+    async fn try_get_by_value(&self, value: &str) -> RedisResult<Option<LoginToken>> {
+        let mut connection = self.connection.get().await?;
+        let fields: HashMap<String, String> = redis::cmd("HGETALL")
+            .arg(Self::key(value))
+            .query_async(&mut connection)
+            .await?;
+        Ok(Self::parse(value, fields))
+    }
+
+    /// The tokens of an index still valid at `now`
+    async fn try_get_indexed(&self, index: &str, now: DateTime<Utc>) -> RedisResult<Vec<LoginToken>> {
+        let mut connection = self.connection.get().await?;
+        let values: Vec<String> = redis::cmd("ZRANGEBYSCORE")
+            .arg(index)
+            .arg(now.timestamp()).arg("+inf")
+            .query_async(&mut connection)
+            .await?;
+        if values.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut pipe = redis::pipe();
+        for value in &values {
+            pipe.cmd("HGETALL").arg(Self::key(value));
+        }
+        let hashes: Vec<HashMap<String, String>> = pipe.query_async(&mut connection).await?;
+        Ok(values.iter()
+            .zip(hashes)
+            .filter_map(|(value, fields)| Self::parse(value, fields))
+            .collect())
+    }
+
+    async fn try_add(&self, token: &LoginToken) -> RedisResult<()> {
+        let mut connection = self.connection.get().await?;
+        let key = Self::key(&token.value);
+        let user_key = Self::user_key(&token.user);
+        let expiration = token.expiration.timestamp();
+        let previous: Option<String> = redis::cmd("HGET")
+            .arg(&key).arg("user")
+            .query_async(&mut connection)
+            .await?;
+
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        // a token given to another user leaves the index of the previous one
+        if let Some(previous) = previous.filter(|previous| *previous != token.user) {
+            pipe.cmd("ZREM").arg(Self::user_key(&previous)).arg(&token.value).ignore();
+        }
+        pipe.cmd("HSET").arg(&key)
+            .arg("user").arg(&token.user)
+            .arg("expiration").arg(expiration)
+            .ignore()
+            .cmd("EXPIREAT").arg(&key).arg(expiration)
+            .ignore()
+            .cmd("ZADD").arg(INDEX).arg(expiration).arg(&token.value)
+            .ignore()
+            .cmd("ZADD").arg(&user_key).arg(expiration).arg(&token.value)
+            .ignore()
+            // the index of the user expires with its last token
+            .cmd("EXPIREAT").arg(&user_key).arg(expiration).arg("NX")
+            .ignore()
+            .cmd("EXPIREAT").arg(&user_key).arg(expiration).arg("GT")
+            .ignore()
+            .query_async(&mut connection)
+            .await
+    }
+
+    async fn try_delete(&self, value: &str) -> RedisResult<()> {
+        let mut connection = self.connection.get().await?;
+        let key = Self::key(value);
+        let user: Option<String> = redis::cmd("HGET")
+            .arg(&key).arg("user")
+            .query_async(&mut connection)
+            .await?;
+
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .cmd("DEL").arg(&key).ignore()
+            .cmd("ZREM").arg(INDEX).arg(value).ignore();
+        if let Some(user) = user {
+            pipe.cmd("ZREM").arg(Self::user_key(&user)).arg(value).ignore();
+        }
+        pipe.query_async(&mut connection).await
+    }
+
+    async fn try_delete_expired(&self, now: DateTime<Utc>) -> RedisResult<usize> {
+        let mut connection = self.connection.get().await?;
+        // the tokens themselves already expired, only their index entries are left
+        redis::cmd("ZREMRANGEBYSCORE")
+            .arg(INDEX)
+            .arg("-inf").arg(format!("({}", now.timestamp()))
+            .query_async(&mut connection)
+            .await
+    }
+
+    async fn try_delete_for_user(&self, user: &str, keep: Option<&str>, now: DateTime<Utc>) -> RedisResult<usize> {
+        let mut connection = self.connection.get().await?;
+        let user_key = Self::user_key(user);
+        let now = now.timestamp();
+        let values: Vec<String> = redis::cmd("ZRANGEBYSCORE")
+            .arg(&user_key)
+            .arg(now).arg("+inf")
+            .query_async(&mut connection)
+            .await?;
+        let values: Vec<String> = values.into_iter()
+            .filter(|value| Some(value.as_str()) != keep)
+            .collect();
+
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .cmd("ZREMRANGEBYSCORE").arg(&user_key).arg("-inf").arg(format!("({}", now))
+            .ignore();
+        for value in &values {
+            pipe.cmd("DEL").arg(Self::key(value)).ignore()
+                .cmd("ZREM").arg(INDEX).arg(value).ignore()
+                .cmd("ZREM").arg(&user_key).arg(value).ignore();
+        }
+        pipe.query_async::<()>(&mut connection).await?;
+        Ok(values.len())
+    }
+}
+
+#[async_trait]
+impl LoginTokenRepo for LoginTokenRepoRedis {
+    async fn get_by_value(&self, value: &str) -> Option<LoginToken> {
+        self.try_get_by_value(value).await
+            .inspect_err(|e| tracing::error!(error = %e, "redis: could not read login token"))
+            .ok()
+            .flatten()
+    }
+
+    async fn get_all(&self, now: DateTime<Utc>) -> Vec<LoginToken> {
+        self.try_get_indexed(INDEX, now).await
+            .inspect_err(|e| tracing::error!(error = %e, "redis: could not list login tokens"))
+            .unwrap_or_default()
+    }
+
+    async fn get_for_user(&self, user: &str, now: DateTime<Utc>) -> Vec<LoginToken> {
+        self.try_get_indexed(&Self::user_key(user), now).await
+            .inspect_err(|e| tracing::error!(error = %e, "redis: could not list the login tokens of a user"))
+            .unwrap_or_default()
+    }
+
+    async fn add(&self, token: LoginToken) {
+        if let Err(e) = self.try_add(&token).await {
+            tracing::error!(error = %e, "redis: could not store login token");
+        }
+    }
+
+    async fn delete(&self, token: &str) {
+        if let Err(e) = self.try_delete(token).await {
+            tracing::error!(error = %e, "redis: could not delete login token");
+        }
+    }
+
+    async fn delete_expired(&self, now: DateTime<Utc>) -> usize {
+        self.try_delete_expired(now).await
+            .inspect_err(|e| tracing::error!(error = %e, "redis: could not purge login tokens"))
+            .unwrap_or_default()
+    }
+
+    async fn delete_for_user(&self, user: &str, keep: Option<&str>, now: DateTime<Utc>) -> usize {
+        self.try_delete_for_user(user, keep, now).await
+            .inspect_err(|e| tracing::error!(error = %e, "redis: could not delete the login tokens of a user"))
+            .unwrap_or_default()
+    }
+
+    async fn ping(&self) -> Result<(), RepoError> {
+        self.connection.ping().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+    use super::*;
+
+    // Needs a local Redis: `docker compose up redis` then
+    // `SSO_TEST_REDIS_URL=redis://localhost:6379 cargo test -- --ignored`
+    #[actix_web::test]
+    #[ignore]
+    async fn test_redis_login_tokens() {
+        let url = std::env::var("SSO_TEST_REDIS_URL").unwrap_or("redis://localhost:6379".to_string());
+        let repo = LoginTokenRepoRedis::new(RedisConnection::new(&url).unwrap());
+        assert!(repo.ping().await.is_ok());
+
+        let token = LoginToken {
+            value: "redis-test".to_string(),
+            user: "redis-test-user".to_string(),
+            expiration: Utc::now() + TimeDelta::seconds(2),
+        };
+        repo.add(token.clone()).await;
+
+        let stored = repo.get_by_value("redis-test").await.unwrap();
+        assert_eq!(token.user, stored.user);
+        assert_eq!(token.expiration.timestamp(), stored.expiration.timestamp());
+
+        actix_web::rt::time::sleep(std::time::Duration::from_secs(3)).await;
+        assert!(repo.get_by_value("redis-test").await.is_none());
+        assert!(repo.get_all(Utc::now()).await.iter().all(|token| token.value != "redis-test"));
+        assert!(repo.delete_expired(Utc::now()).await >= 1);
+
+        repo.add(LoginToken { expiration: Utc::now() + TimeDelta::days(1), ..token.clone() }).await;
+        repo.delete("redis-test").await;
+        assert!(repo.get_by_value("redis-test").await.is_none());
+
+        for value in ["redis-test-1", "redis-test-2", "redis-test-3"] {
+            repo.add(LoginToken { value: value.to_string(), expiration: Utc::now() + TimeDelta::days(1), ..token.clone() }).await;
+        }
+        assert_eq!(3, repo.get_for_user("redis-test-user", Utc::now()).await.len());
+        assert_eq!(2, repo.delete_for_user("redis-test-user", Some("redis-test-1"), Utc::now()).await);
+        assert!(repo.get_by_value("redis-test-1").await.is_some());
+        assert!(repo.get_by_value("redis-test-2").await.is_none());
+        assert_eq!(1, repo.delete_for_user("redis-test-user", None, Utc::now()).await);
+    }
+}
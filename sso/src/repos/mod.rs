@@ -1,5 +1,20 @@
 
 pub mod users;
 pub mod applications;
+pub(crate) mod audit;
+pub(crate) mod authorization_codes;
+pub(crate) mod consents;
+pub(crate) mod email_changes;
+pub(crate) mod favorites;
+pub(crate) mod federation_states;
+pub(crate) mod groups;
 pub(crate) mod login_tokens;
+pub(crate) mod login_tokens_redis;
+pub(crate) mod redis_connection;
+pub(crate) mod refresh_tokens;
 pub(crate) mod register_tokens;
+pub(crate) mod revoked_tokens;
+pub(crate) mod throttles;
+pub(crate) mod throttles_redis;
+pub(crate) mod timed;
+pub(crate) mod unit_of_work;
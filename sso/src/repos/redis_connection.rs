@@ -0,0 +1,40 @@
+use std::sync::Arc;
+use redis::aio::ConnectionManager;
+use redis::{Client, RedisResult};
+use tokio::sync::OnceCell;
+use crate::errors::repo::RepoError;
+
+/// A connection shared by the repositories stored in Redis, the clones use the same connection
+#[derive(Clone)]
+pub struct RedisConnection {
+    client: Client,
+    connection: Arc<OnceCell<ConnectionManager>>,
+}
+
+impl RedisConnection {
+    pub fn new(url: &str) -> Result<Self, RepoError> {
+        let client = Client::open(url).map_err(|e| RepoError::Unavailable(e.to_string()))?;
+        Ok(Self {
+            client,
+            connection: Arc::new(OnceCell::new()),
+        })
+    }
+
+    /// The connection is opened on first use and then reconnects by itself
+    pub async fn get(&self) -> RedisResult<ConnectionManager> {
+        self.connection
+            .get_or_try_init(|| ConnectionManager::new(self.client.clone()))
+            .await
+            .cloned()
+    }
+
+    pub async fn ping(&self) -> Result<(), RepoError> {
+        let mut connection = self.get().await
+            .map_err(|e| RepoError::Unavailable(e.to_string()))?;
+        redis::cmd("PING")
+            .query_async::<String>(&mut connection)
+            .await
+            .map(|_| ())
+            .map_err(|e| RepoError::Unavailable(e.to_string()))
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use tokio::sync::RwLock;
+use crate::errors::repo::RepoError;
+use crate::objects::refresh_token::RefreshToken;
+
+#[async_trait]
+pub trait RefreshTokenRepo: Send + Sync {
+    async fn get_by_value(&self, value: &str) -> Option<RefreshToken>;
+    async fn add(&self, token: RefreshToken);
+    /// Mark the token as used and return it as it was before, so that two
+    /// concurrent uses can not both see it unused
+    async fn mark_used(&self, value: &str) -> Option<RefreshToken>;
+    /// Remove every token of the family, returning how many were removed
+    async fn delete_family(&self, family: &str) -> usize;
+    /// Remove every token of a user for an application, returning how many were removed
+    async fn delete_for_client(&self, client_id: &str, user: &str) -> usize;
+    /// Remove every token expired at `now`, returning how many were removed
+    async fn delete_expired(&self, now: DateTime<Utc>) -> usize;
+    async fn ping(&self) -> Result<(), RepoError>;
+}
+
+pub struct RefreshTokenRepoMemory {
+    tokens: Arc<RwLock<HashMap<String, RefreshToken>>>,
+}
+
+impl RefreshTokenRepoMemory {
+    pub fn new() -> Self {
+        Self {
+            tokens: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+}
+
+#[async_trait]
+impl RefreshTokenRepo for RefreshTokenRepoMemory {
+    async fn get_by_value(&self, value: &str) -> Option<RefreshToken> {
+        self.tokens.read().await.get(value).cloned()
+    }
+
+    async fn add(&self, token: RefreshToken) {
+        self.tokens.write().await.insert(token.value.clone(), token);
+    }
+
+    async fn mark_used(&self, value: &str) -> Option<RefreshToken> {
+        let mut tokens = self.tokens.write().await;
+        let token = tokens.get_mut(value)?;
+        let before = token.clone();
+        token.used = true;
+        Some(before)
+    }
+
+    async fn delete_family(&self, family: &str) -> usize {
+        let mut tokens = self.tokens.write().await;
+        let before = tokens.len();
+        tokens.retain(|_, token| token.family != family);
+        before - tokens.len()
+    }
+
+    async fn delete_for_client(&self, client_id: &str, user: &str) -> usize {
+        let mut tokens = self.tokens.write().await;
+        let before = tokens.len();
+        tokens.retain(|_, token| token.client_id != client_id || token.user != user);
+        before - tokens.len()
+    }
+
+    async fn delete_expired(&self, now: DateTime<Utc>) -> usize {
+        let mut tokens = self.tokens.write().await;
+        let before = tokens.len();
+        tokens.retain(|_, token| token.expiration >= now);
+        before - tokens.len()
+    }
+
+    async fn ping(&self) -> Result<(), RepoError> {
+        Ok(())
+    }
+}
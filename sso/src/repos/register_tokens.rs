@@ -1,49 +1,78 @@
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use chrono::{Days, Utc};
-use crate::objects::registration_token::RegisterToken;
-
-pub trait RegisterTokenRepo {
-    fn get_by_value(&self, value: &str) -> Option<RegisterToken>;
-    fn get_all(&self) -> Vec<RegisterToken>;
-    fn add(&self, token: RegisterToken);
-    fn delete(&self, token: &str);
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use async_trait::async_trait;
+use chrono::{DateTime, Days, Utc};
+use tokio::sync::RwLock;
+use crate::errors::repo::RepoError;
+use crate::objects::registration_token::{RegisterToken, RegisterTokenUse};
+
+#[async_trait]
+pub trait RegisterTokenRepo: Send + Sync {
+    async fn get_by_value(&self, value: &str) -> Option<RegisterToken>;
+    async fn get_all(&self) -> Vec<RegisterToken>;
+    async fn add(&self, token: RegisterToken);
+    /// Record a use of the token atomically, returning the token as it was before,
+    /// or `None` if it does not exist or has no use left
+    async fn consume(&self, value: &str, usage: RegisterTokenUse) -> Option<RegisterToken>;
+    /// Remove every token expired at `now`, returning how many were removed
+    async fn delete_expired(&self, now: DateTime<Utc>) -> usize;
+    async fn ping(&self) -> Result<(), RepoError>;
 }
 
 pub struct RegisterTokenRepoMemory {
-    tokens: Arc<Mutex<HashMap<String, RegisterToken>>>
+    tokens: Arc<RwLock<HashMap<String, RegisterToken>>>
 }
 
 
 impl RegisterTokenRepoMemory {
     pub fn new() -> Self {
-        let obj = Self {
-            tokens: Arc::new(Mutex::new(HashMap::new())),
-        };
-
-        obj.add(RegisterToken{
+        let token = RegisterToken{
             value: "token".to_string(),
             expiration: Utc::now() + Days::new(10),
-        });
+            max_uses: 1,
+            uses: vec![],
+            restriction: None,
+            groups: HashSet::new(),
+            applications: HashSet::new(),
+            created_by: None,
+        };
 
-        obj
+        Self {
+            tokens: Arc::new(RwLock::new(HashMap::from([(token.value.clone(), token)]))),
+        }
     }
 }
+#[async_trait]
 impl RegisterTokenRepo for RegisterTokenRepoMemory {
 
-    fn get_by_value(&self, value: &str) -> Option<RegisterToken> {
-        self.tokens.lock().unwrap().get(value).cloned()
+    async fn get_by_value(&self, value: &str) -> Option<RegisterToken> {
+        self.tokens.read().await.get(value).cloned()
+    }
+
+    async fn get_all(&self) -> Vec<RegisterToken> {
+        self.tokens.read().await.values().cloned().collect()
+    }
+
+    async fn add(&self, token: RegisterToken) {
+        self.tokens.write().await.insert(token.value.clone(), token);
     }
 
-    fn get_all(&self) -> Vec<RegisterToken> {
-        self.tokens.lock().unwrap().values().cloned().collect()
+    async fn consume(&self, value: &str, usage: RegisterTokenUse) -> Option<RegisterToken> {
+        let mut tokens = self.tokens.write().await;
+        let token = tokens.get_mut(value).filter(|token| !token.is_exhausted())?;
+        let previous = token.clone();
+        token.uses.push(usage);
+        Some(previous)
     }
 
-    fn add(&self, token: RegisterToken) {
-        self.tokens.lock().unwrap().insert(token.value.clone(), token);
+    async fn delete_expired(&self, now: DateTime<Utc>) -> usize {
+        let mut tokens = self.tokens.write().await;
+        let before = tokens.len();
+        tokens.retain(|_, token| token.expiration >= now);
+        before - tokens.len()
     }
 
-    fn delete(&self, token: &str) {
-        self.tokens.lock().unwrap().remove(token);
+    async fn ping(&self) -> Result<(), RepoError> {
+        Ok(())
     }
 }
\ No newline at end of file
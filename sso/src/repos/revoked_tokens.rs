@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use tokio::sync::RwLock;
+use crate::errors::repo::RepoError;
+
+/// Identifiers of the access tokens revoked before their expiration
+#[async_trait]
+pub trait RevokedTokenRepo: Send + Sync {
+    /// Kept until `expiration`, the token is refused anyway after that
+    async fn add(&self, id: &str, expiration: DateTime<Utc>);
+    async fn is_revoked(&self, id: &str) -> bool;
+    /// Forget every token expired at `now`, returning how many were removed
+    async fn delete_expired(&self, now: DateTime<Utc>) -> usize;
+    async fn ping(&self) -> Result<(), RepoError>;
+}
+
+pub struct RevokedTokenRepoMemory {
+    tokens: Arc<RwLock<HashMap<String, DateTime<Utc>>>>,
+}
+
+impl RevokedTokenRepoMemory {
+    pub fn new() -> Self {
+        Self {
+            tokens: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+}
+
+#[async_trait]
+impl RevokedTokenRepo for RevokedTokenRepoMemory {
+    async fn add(&self, id: &str, expiration: DateTime<Utc>) {
+        self.tokens.write().await.insert(id.to_string(), expiration);
+    }
+
+    async fn is_revoked(&self, id: &str) -> bool {
+        self.tokens.read().await.contains_key(id)
+    }
+
+    async fn delete_expired(&self, now: DateTime<Utc>) -> usize {
+        let mut tokens = self.tokens.write().await;
+        let before = tokens.len();
+        tokens.retain(|_, expiration| *expiration >= now);
+        before - tokens.len()
+    }
+
+    async fn ping(&self) -> Result<(), RepoError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use async_trait::async_trait;
+use chrono::{DateTime, TimeDelta, Utc};
+use tokio::sync::RwLock;
+use crate::errors::repo::RepoError;
+
+/// Attempts counted over fixed windows, for rate limiting
+#[async_trait]
+pub trait ThrottleRepo: Send + Sync {
+    /// Count an attempt for `key`, returning the attempts counted since the window started.
+    /// A window starts with the first attempt and lasts `window`
+    async fn hit(&self, key: &str, now: DateTime<Utc>, window: TimeDelta) -> u32;
+    /// Remove every counter whose window ended at `now`, returning how many were removed
+    async fn delete_expired(&self, now: DateTime<Utc>) -> usize;
+    async fn ping(&self) -> Result<(), RepoError>;
+}
+
+struct Counter {
+    attempts: u32,
+    end: DateTime<Utc>,
+}
+
+pub struct ThrottleRepoMemory {
+    counters: Arc<RwLock<HashMap<String, Counter>>>,
+}
+
+impl ThrottleRepoMemory {
+    pub fn new() -> Self {
+        Self {
+            counters: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+}
+
+#[async_trait]
+impl ThrottleRepo for ThrottleRepoMemory {
+    async fn hit(&self, key: &str, now: DateTime<Utc>, window: TimeDelta) -> u32 {
+        let mut counters = self.counters.write().await;
+        let counter = counters.entry(key.to_string())
+            .and_modify(|counter| if counter.end <= now {
+                *counter = Counter { attempts: 0, end: now + window };
+            })
+            .or_insert_with(|| Counter { attempts: 0, end: now + window });
+        counter.attempts += 1;
+        counter.attempts
+    }
+
+    async fn delete_expired(&self, now: DateTime<Utc>) -> usize {
+        let mut counters = self.counters.write().await;
+        let before = counters.len();
+        counters.retain(|_, counter| counter.end > now);
+        before - counters.len()
+    }
+
+    async fn ping(&self) -> Result<(), RepoError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,75 @@
+use async_trait::async_trait;
+use chrono::{DateTime, TimeDelta, Utc};
+use redis::RedisResult;
+use crate::errors::repo::RepoError;
+use crate::repos::redis_connection::RedisConnection;
+use crate::repos::throttles::ThrottleRepo;
+
+const PREFIX: &str = "sso:throttle:";
+
+/// Counters stored as Redis integers expiring with their window,
+/// so that every instance counts the same attempts
+pub struct ThrottleRepoRedis {
+    connection: RedisConnection,
+}
+
+impl ThrottleRepoRedis {
+    pub fn new(connection: RedisConnection) -> Self {
+        Self {
+            connection,
+        }
+    }
+
+    async fn try_hit(&self, key: &str, now: DateTime<Utc>, window: TimeDelta) -> RedisResult<u32> {
+        let mut connection = self.connection.get().await?;
+        let key = format!("{}{}", PREFIX, key);
+        let (attempts,): (u32,) = redis::pipe()
+            .atomic()
+            .cmd("INCR").arg(&key)
+            // only the first attempt of a window sets when it ends
+            .cmd("EXPIREAT").arg(&key).arg((now + window).timestamp()).arg("NX")
+            .ignore()
+            .query_async(&mut connection)
+            .await?;
+        Ok(attempts)
+    }
+}
+
+#[async_trait]
+impl ThrottleRepo for ThrottleRepoRedis {
+    async fn hit(&self, key: &str, now: DateTime<Utc>, window: TimeDelta) -> u32 {
+        // an unreachable Redis does not block the logins
+        self.try_hit(key, now, window).await
+            .inspect_err(|e| tracing::error!(error = %e, "redis: could not count an attempt"))
+            .unwrap_or_default()
+    }
+
+    async fn delete_expired(&self, _now: DateTime<Utc>) -> usize {
+        // the counters expire with their window, nothing is left behind
+        0
+    }
+
+    async fn ping(&self) -> Result<(), RepoError> {
+        self.connection.ping().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Needs a local Redis, see `test_redis_login_tokens`
+    #[actix_web::test]
+    #[ignore]
+    async fn test_redis_throttles() {
+        let url = std::env::var("SSO_TEST_REDIS_URL").unwrap_or("redis://localhost:6379".to_string());
+        let repo = ThrottleRepoRedis::new(RedisConnection::new(&url).unwrap());
+        let key = format!("test:{}", Utc::now().timestamp_millis());
+
+        assert_eq!(1, repo.hit(&key, Utc::now(), TimeDelta::seconds(2)).await);
+        assert_eq!(2, repo.hit(&key, Utc::now(), TimeDelta::seconds(2)).await);
+
+        actix_web::rt::time::sleep(std::time::Duration::from_secs(3)).await;
+        assert_eq!(1, repo.hit(&key, Utc::now(), TimeDelta::seconds(2)).await);
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use async_trait::async_trait;
+use chrono::{DateTime, TimeDelta, Utc};
+use crate::errors::repo::RepoError;
+use crate::objects::application::Application;
+use crate::objects::audit_event::AuditEvent;
+use crate::objects::authorization_code::AuthorizationCode;
+use crate::objects::consent::Consent;
+use crate::objects::email_change::EmailChange;
+use crate::objects::federation_state::FederationState;
+use crate::objects::group::Group;
+use crate::objects::login_token::LoginToken;
+use crate::objects::refresh_token::RefreshToken;
+use crate::objects::registration_token::{RegisterToken, RegisterTokenUse};
+use crate::objects::user::User;
+use crate::repos::applications::ApplicationRepo;
+use crate::repos::audit::AuditRepo;
+use crate::repos::authorization_codes::AuthorizationCodeRepo;
+use crate::repos::consents::ConsentRepo;
+use crate::repos::email_changes::EmailChangeRepo;
+use crate::repos::favorites::FavoriteRepo;
+use crate::repos::federation_states::FederationStateRepo;
+use crate::repos::groups::GroupRepo;
+use crate::repos::login_tokens::LoginTokenRepo;
+use crate::repos::refresh_tokens::RefreshTokenRepo;
+use crate::repos::register_tokens::RegisterTokenRepo;
+use crate::repos::revoked_tokens::RevokedTokenRepo;
+use crate::repos::throttles::ThrottleRepo;
+use crate::repos::users::UserRepo;
+use crate::services::metrics::Metrics;
+
+/// Records the latency of every call to the wrapped repository,
+/// labelled by the repository name and the method called
+pub struct Timed<R: ?Sized> {
+    name: &'static str,
+    inner: Arc<R>,
+    metrics: Metrics,
+}
+
+impl<R: ?Sized> Timed<R> {
+    pub fn new(name: &'static str, inner: Arc<R>, metrics: &Metrics) -> Arc<Self> {
+        Arc::new(Self {
+            name,
+            inner,
+            metrics: metrics.clone(),
+        })
+    }
+}
+
+/// Implement a repository trait for `Timed` by forwarding every method to the inner repository
+macro_rules! timed {
+    ($repo:ident { $($(#[$attr:meta])* fn $method:ident(&self $(, $arg:ident: $ty:ty)*) $(-> $ret:ty)?;)* }) => {
+        #[async_trait]
+        impl $repo for Timed<dyn $repo> {
+            $(
+                $(#[$attr])*
+                async fn $method(&self $(, $arg: $ty)*) $(-> $ret)? {
+                    let _timer = self.metrics.time_repo(self.name, stringify!($method));
+                    self.inner.$method($($arg),*).await
+                }
+            )*
+        }
+    };
+}
+
+timed!(ApplicationRepo {
+    fn get_by_client_id(&self, client_id: &str) -> Option<Application>;
+    fn get_all(&self) -> Vec<Application>;
+    fn get_by_entity_id(&self, entity_id: &str) -> Option<Application>;
+    #[cfg(test)]
+    fn add(&self, application: Application);
+    fn add_user(&self, client_id: &str, user: &str) -> bool;
+    fn remove_user(&self, client_id: &str, user: &str);
+    fn set_pre_consented(&self, client_id: &str, pre_consented: bool) -> bool;
+    fn ping(&self) -> Result<(), RepoError>;
+});
+
+timed!(AuditRepo {
+    fn add(&self, event: AuditEvent);
+    fn get_for_user(&self, user: &str) -> Vec<AuditEvent>;
+    fn replace_user(&self, user: &str, replacement: &str) -> usize;
+    fn ping(&self) -> Result<(), RepoError>;
+});
+
+timed!(AuthorizationCodeRepo {
+    fn add(&self, code: AuthorizationCode);
+    fn take(&self, value: &str) -> Option<AuthorizationCode>;
+    fn delete_expired(&self, now: DateTime<Utc>) -> usize;
+    fn ping(&self) -> Result<(), RepoError>;
+});
+
+timed!(ConsentRepo {
+    fn get(&self, user: &str, client_id: &str) -> Option<Consent>;
+    fn get_for_user(&self, user: &str) -> Vec<Consent>;
+    fn add(&self, consent: Consent);
+    fn delete(&self, user: &str, client_id: &str) -> bool;
+    fn delete_for_user(&self, user: &str) -> usize;
+    fn ping(&self) -> Result<(), RepoError>;
+});
+
+timed!(EmailChangeRepo {
+    fn get_by_value(&self, value: &str) -> Option<EmailChange>;
+    fn add(&self, change: EmailChange);
+    fn delete(&self, value: &str);
+    fn delete_expired(&self, now: DateTime<Utc>) -> usize;
+    fn ping(&self) -> Result<(), RepoError>;
+});
+
+timed!(FavoriteRepo {
+    fn get_for_user(&self, user: &str) -> HashSet<String>;
+    fn add(&self, user: &str, client_id: &str);
+    fn remove(&self, user: &str, client_id: &str);
+    fn delete_for_user(&self, user: &str) -> usize;
+    fn ping(&self) -> Result<(), RepoError>;
+});
+
+timed!(FederationStateRepo {
+    fn add(&self, state: FederationState);
+    fn take(&self, value: &str) -> Option<FederationState>;
+    fn delete_expired(&self, now: DateTime<Utc>) -> usize;
+    fn ping(&self) -> Result<(), RepoError>;
+});
+
+timed!(GroupRepo {
+    fn get_by_id(&self, id: &str) -> Option<Group>;
+    fn get_by_name(&self, name: &str) -> Option<Group>;
+    fn get_all(&self) -> Vec<Group>;
+    fn add(&self, group: Group) -> Result<(), RepoError>;
+    fn delete(&self, id: &str);
+    fn ping(&self) -> Result<(), RepoError>;
+});
+
+timed!(LoginTokenRepo {
+    fn get_by_value(&self, value: &str) -> Option<LoginToken>;
+    fn get_all(&self, now: DateTime<Utc>) -> Vec<LoginToken>;
+    fn get_for_user(&self, user: &str, now: DateTime<Utc>) -> Vec<LoginToken>;
+    fn add(&self, token: LoginToken);
+    fn delete(&self, token: &str);
+    fn delete_expired(&self, now: DateTime<Utc>) -> usize;
+    fn delete_for_user(&self, user: &str, keep: Option<&str>, now: DateTime<Utc>) -> usize;
+    fn ping(&self) -> Result<(), RepoError>;
+});
+
+timed!(RefreshTokenRepo {
+    fn get_by_value(&self, value: &str) -> Option<RefreshToken>;
+    fn add(&self, token: RefreshToken);
+    fn mark_used(&self, value: &str) -> Option<RefreshToken>;
+    fn delete_family(&self, family: &str) -> usize;
+    fn delete_for_client(&self, client_id: &str, user: &str) -> usize;
+    fn delete_expired(&self, now: DateTime<Utc>) -> usize;
+    fn ping(&self) -> Result<(), RepoError>;
+});
+
+timed!(RegisterTokenRepo {
+    fn get_by_value(&self, value: &str) -> Option<RegisterToken>;
+    fn get_all(&self) -> Vec<RegisterToken>;
+    fn add(&self, token: RegisterToken);
+    fn consume(&self, value: &str, usage: RegisterTokenUse) -> Option<RegisterToken>;
+    fn delete_expired(&self, now: DateTime<Utc>) -> usize;
+    fn ping(&self) -> Result<(), RepoError>;
+});
+
+timed!(RevokedTokenRepo {
+    fn add(&self, id: &str, expiration: DateTime<Utc>);
+    fn is_revoked(&self, id: &str) -> bool;
+    fn delete_expired(&self, now: DateTime<Utc>) -> usize;
+    fn ping(&self) -> Result<(), RepoError>;
+});
+
+timed!(ThrottleRepo {
+    fn hit(&self, key: &str, now: DateTime<Utc>, window: TimeDelta) -> u32;
+    fn delete_expired(&self, now: DateTime<Utc>) -> usize;
+    fn ping(&self) -> Result<(), RepoError>;
+});
+
+timed!(UserRepo {
+    fn get_by_id(&self, id: &str) -> Option<User>;
+    fn get_by_email(&self, email: &str) -> Option<User>;
+    fn get_all(&self) -> Vec<User>;
+    fn get_by_federated(&self, provider: &str, subject: &str) -> Option<User>;
+    fn add(&self, user: User) -> Result<(), RepoError>;
+    fn insert(&self, user: User) -> Result<(), RepoError>;
+    fn delete(&self, id: &str);
+    fn ping(&self) -> Result<(), RepoError>;
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::config::Config;
+    use crate::services::factory::Repos;
+
+    #[actix_web::test]
+    async fn test_calls_are_timed() {
+        let metrics = Metrics::new();
+        let repos = Repos::new(&Config::default(), &metrics);
+        let timer = |repo, operation| metrics.repo_operations.with_label_values(&[repo, operation]).get_sample_count();
+
+        assert!(repos.user_repo.get_by_email("admin@example.com").await.is_some());
+        repos.favorite_repo.add("user", "wiki").await;
+        assert_eq!(1, timer("users", "get_by_email"));
+        assert_eq!(1, timer("favorites", "add"));
+        assert_eq!(0, timer("users", "get_all"));
+    }
+}
@@ -0,0 +1,90 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+use tokio::sync::OwnedMutexGuard;
+use crate::errors::repo::RepoError;
+use crate::objects::registration_token::{RegisterToken, RegisterTokenUse};
+use crate::objects::user::User;
+use crate::repos::applications::ApplicationRepo;
+use crate::repos::register_tokens::RegisterTokenRepo;
+use crate::repos::users::UserRepo;
+
+/// Changes spanning several repositories that must be applied together.
+/// Every unit of work has to end with `commit` or `rollback`.
+#[async_trait]
+pub trait UnitOfWork: Send {
+    async fn consume_register_token(&mut self, value: &str, usage: RegisterTokenUse) -> Option<RegisterToken>;
+    /// Fails with a conflict if the id or the email is already used
+    async fn add_user(&mut self, user: User) -> Result<(), RepoError>;
+    async fn grant_application(&mut self, client_id: &str, user: &str) -> Result<(), RepoError>;
+    async fn commit(self: Box<Self>) -> Result<(), RepoError>;
+    async fn rollback(self: Box<Self>);
+}
+
+enum Undo {
+    RestoreRegisterToken(RegisterToken),
+    DeleteUser(String),
+    RevokeApplication(String, String),
+}
+
+pub struct MemoryRepos {
+    pub user_repo: Arc<dyn UserRepo>,
+    pub register_token_repo: Arc<dyn RegisterTokenRepo>,
+    pub application_repo: Arc<dyn ApplicationRepo>,
+}
+
+/// The memory backend has no transactions: units of work are serialized by a lock
+/// and their changes are undone on rollback. Writers outside of a unit of work do not
+/// take the lock, the uniqueness of users is checked by the user repository itself
+pub struct UnitOfWorkMemory {
+    repos: MemoryRepos,
+    undo: Vec<Undo>,
+    _guard: OwnedMutexGuard<()>,
+}
+
+impl UnitOfWorkMemory {
+    pub fn new(repos: MemoryRepos, guard: OwnedMutexGuard<()>) -> Self {
+        Self {
+            repos,
+            undo: vec![],
+            _guard: guard,
+        }
+    }
+}
+
+#[async_trait]
+impl UnitOfWork for UnitOfWorkMemory {
+    async fn consume_register_token(&mut self, value: &str, usage: RegisterTokenUse) -> Option<RegisterToken> {
+        let token = self.repos.register_token_repo.consume(value, usage).await?;
+        self.undo.push(Undo::RestoreRegisterToken(token.clone()));
+        Some(token)
+    }
+
+    async fn add_user(&mut self, user: User) -> Result<(), RepoError> {
+        let id = user.id.clone();
+        self.repos.user_repo.insert(user).await?;
+        self.undo.push(Undo::DeleteUser(id));
+        Ok(())
+    }
+
+    async fn grant_application(&mut self, client_id: &str, user: &str) -> Result<(), RepoError> {
+        if !self.repos.application_repo.add_user(client_id, user).await {
+            return Err(RepoError::NotFound(format!("application {}", client_id)));
+        }
+        self.undo.push(Undo::RevokeApplication(client_id.to_string(), user.to_string()));
+        Ok(())
+    }
+
+    async fn commit(self: Box<Self>) -> Result<(), RepoError> {
+        Ok(())
+    }
+
+    async fn rollback(mut self: Box<Self>) {
+        while let Some(undo) = self.undo.pop() {
+            match undo {
+                Undo::RestoreRegisterToken(token) => self.repos.register_token_repo.add(token).await,
+                Undo::DeleteUser(id) => self.repos.user_repo.delete(&id).await,
+                Undo::RevokeApplication(client_id, user) => self.repos.application_repo.remove_user(&client_id, &user).await,
+            }
+        }
+    }
+}
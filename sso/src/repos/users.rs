@@ -1,45 +1,159 @@
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use async_trait::async_trait;
 use chrono::Utc;
-use crate::objects::user::User;
+use tokio::sync::RwLock;
+use crate::errors::repo::RepoError;
+use crate::objects::user::{User, UserStatus};
 
-pub trait UserRepo {
-    fn get_by_email(&self, email: &str) -> Option<User>;
-    fn get_all(&self) -> Vec<User>;
-    fn add(&self, user: User);
+#[async_trait]
+pub trait UserRepo: Send + Sync {
+    async fn get_by_id(&self, id: &str) -> Option<User>;
+    async fn get_by_email(&self, email: &str) -> Option<User>;
+    async fn get_all(&self) -> Vec<User>;
+    /// The user linked to the `subject` of an upstream provider
+    async fn get_by_federated(&self, provider: &str, subject: &str) -> Option<User>;
+    /// Insert or replace the user with the same id, fails with a conflict
+    /// if another user has the same email
+    async fn add(&self, user: User) -> Result<(), RepoError>;
+    /// Insert a new user, fails with a conflict if the id or the email is already used
+    async fn insert(&self, user: User) -> Result<(), RepoError>;
+    async fn delete(&self, id: &str);
+    async fn ping(&self) -> Result<(), RepoError>;
 }
 
+#[derive(Default)]
+struct UserTable {
+    users: HashMap<String, User>,
+    /// Email to id, unique
+    emails: HashMap<String, String>,
+}
+
+/// Id of the seeded administrator, the same on every start so that its sessions,
+/// grants and tokens stored outside of the memory keep pointing to it
+pub const ADMIN_ID: &str = "00000000-0000-4000-8000-000000000001";
+
 pub struct UserRepoMemory {
-    users: Arc<Mutex<HashMap<String, User>>>,
+    table: Arc<RwLock<UserTable>>,
 }
 impl UserRepoMemory {
     pub fn new() -> Self {
-        let obj = Self {
-            users: Arc::new(Mutex::new(HashMap::new())),
-        };
-
-        obj.add(User{
+        let admin = User{
+            id: ADMIN_ID.to_string(),
             email: "admin@example.com".to_string(),
             password: "admin".to_string(),
             name: "Admin".to_string(),
             created: Utc::now(),
             admin: true,
-        });
+            groups: HashSet::new(),
+            status: UserStatus::Active,
+            status_change: None,
+            failed_logins: 0,
+            provider: None,
+            federated: HashMap::new(),
+            avatar_url: None,
+            timezone: "UTC".to_string(),
+            locale: "en".to_string(),
+            deletion_scheduled: None,
+        };
 
-        obj
+        let table = UserTable {
+            emails: HashMap::from([(admin.email.clone(), admin.id.clone())]),
+            users: HashMap::from([(admin.id.clone(), admin)]),
+        };
+        Self {
+            table: Arc::new(RwLock::new(table)),
+        }
     }
 }
 
+#[async_trait]
 impl UserRepo for UserRepoMemory {
-    fn get_by_email(&self, email: &str) -> Option<User> {
-        self.users.lock().unwrap().get(email).cloned()
+    async fn get_by_id(&self, id: &str) -> Option<User> {
+        self.table.read().await.users.get(id).cloned()
     }
 
-    fn get_all(&self) -> Vec<User> {
-        self.users.lock().unwrap().values().cloned().collect()
+    async fn get_by_email(&self, email: &str) -> Option<User> {
+        let table = self.table.read().await;
+        table.emails.get(email).and_then(|id| table.users.get(id)).cloned()
+    }
+
+    async fn get_all(&self) -> Vec<User> {
+        self.table.read().await.users.values().cloned().collect()
+    }
+
+    async fn get_by_federated(&self, provider: &str, subject: &str) -> Option<User> {
+        self.table.read().await.users.values()
+            .find(|user| user.federated.get(provider).is_some_and(|linked| linked == subject))
+            .cloned()
+    }
+
+    async fn add(&self, user: User) -> Result<(), RepoError> {
+        let mut table = self.table.write().await;
+        if table.emails.get(&user.email).is_some_and(|id| *id != user.id) {
+            return Err(RepoError::Conflict(format!("email {} already used", user.email)));
+        }
+
+        if let Some(previous) = table.users.get(&user.id) {
+            let previous_email = previous.email.clone();
+            table.emails.remove(&previous_email);
+        }
+        table.emails.insert(user.email.clone(), user.id.clone());
+        table.users.insert(user.id.clone(), user);
+        Ok(())
     }
 
-    fn add(&self, user: User) {
-        self.users.lock().unwrap().insert(user.email.clone(), user);
+    async fn insert(&self, user: User) -> Result<(), RepoError> {
+        let mut table = self.table.write().await;
+        if table.users.contains_key(&user.id) {
+            return Err(RepoError::Conflict(format!("user {} already exists", user.id)));
+        }
+        if table.emails.contains_key(&user.email) {
+            return Err(RepoError::Conflict(format!("email {} already used", user.email)));
+        }
+        table.emails.insert(user.email.clone(), user.id.clone());
+        table.users.insert(user.id.clone(), user);
+        Ok(())
     }
-}
\ No newline at end of file
+
+    async fn delete(&self, id: &str) {
+        let mut table = self.table.write().await;
+        if let Some(user) = table.users.remove(id) {
+            table.emails.remove(&user.email);
+        }
+    }
+
+    async fn ping(&self) -> Result<(), RepoError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_email_index() {
+        let repo = UserRepoMemory::new();
+        let mut admin = repo.get_by_email("admin@example.com").await.unwrap();
+        assert_eq!(ADMIN_ID, admin.id);
+
+        let mut other = admin.clone();
+        other.id = User::new_id();
+        assert!(matches!(repo.add(other.clone()).await, Err(RepoError::Conflict(_))));
+
+        admin.email = "root@example.com".to_string();
+        assert!(repo.add(admin.clone()).await.is_ok());
+        assert!(repo.get_by_email("admin@example.com").await.is_none());
+        assert_eq!(admin.id, repo.get_by_email("root@example.com").await.unwrap().id);
+
+        // the old address is free again
+        assert!(repo.add(other.clone()).await.is_ok());
+        assert_eq!(2, repo.get_all().await.len());
+
+        // inserting never replaces
+        assert!(matches!(repo.insert(other.clone()).await, Err(RepoError::Conflict(_))));
+        other.id = User::new_id();
+        assert!(matches!(repo.insert(other).await, Err(RepoError::Conflict(_))));
+    }
+}
@@ -0,0 +1,485 @@
+use std::sync::Arc;
+use chrono::{DateTime, Days, TimeDelta, Utc};
+use serde_json::{json, Value};
+use uuid::Uuid;
+use crate::errors::account::AccountError;
+use crate::errors::repo::RepoError;
+use crate::forms::account::{DeletionForm, EmailForm, PasswordForm, ProfileForm};
+use crate::forms::validation::{Validator, EMAIL_REGEX, LOCALE_REGEX, TIMEZONE_REGEX, URL_REGEX};
+use crate::objects::audit_event::{AuditAction, AuditEvent};
+use crate::objects::config::Config;
+use crate::objects::email_change::EmailChange;
+use crate::objects::user::User;
+use crate::services::auth::AuthService;
+use crate::services::clock::Clock;
+use crate::services::factory::Repos;
+use crate::services::mailer::Mailer;
+use crate::services::password::PasswordService;
+
+/// What users can do with their own account
+#[derive(Clone)]
+pub struct AccountService {
+    config: Config,
+    repos: Repos,
+    clock: Arc<dyn Clock>,
+    mailer: Arc<dyn Mailer>,
+    passwords: PasswordService,
+}
+
+impl AccountService {
+    pub fn new(config: Config, repos: Repos, clock: Arc<dyn Clock>, mailer: Arc<dyn Mailer>) -> Self {
+        Self {
+            passwords: PasswordService::new(config.password_policy.clone()),
+            config,
+            repos,
+            clock,
+            mailer,
+        }
+    }
+
+    pub async fn update_profile(&self, user: &User, form: &ProfileForm) -> Result<User, AccountError> {
+        // the values are validated as they are stored
+        let name = form.name.trim();
+        let avatar_url = form.avatar_url.trim();
+        let mut validator = Validator::new();
+        validator.field("name", name)
+            .required()
+            .size(2, 40);
+        validator.field("avatar_url", avatar_url)
+            .optional()
+            .size(0, 2048)
+            .regex(&URL_REGEX, "https://example.com/avatar.png");
+        validator.field("timezone", &form.timezone)
+            .required()
+            .regex(&TIMEZONE_REGEX, "Europe/Paris");
+        validator.field("locale", &form.locale)
+            .required()
+            .regex(&LOCALE_REGEX, "en-US");
+        validator.finish().map_err(AccountError::Validation)?;
+
+        let mut user = user.clone();
+        user.name = name.to_string();
+        user.avatar_url = Some(avatar_url.to_string()).filter(|url| !url.is_empty());
+        user.timezone = form.timezone.clone();
+        user.locale = form.locale.clone();
+
+        self.repos.user_repo.add(user.clone()).await.map_err(|_| AccountError::Unavailable)?;
+        self.audit(&user.id, AuditAction::ProfileUpdated, &user.id).await;
+        Ok(user)
+    }
+
+    /// Change the password and close every other session of the user
+    pub async fn change_password(&self, user: &User, session_token: &str, form: &PasswordForm) -> Result<(), AccountError> {
+        if user.provider.is_some() {
+            return Err(AccountError::ManagedByProvider);
+        }
+        if !AuthService::verify_password(user, &form.current_password) {
+            return Err(AccountError::WrongPassword);
+        }
+
+        let password_errors = self.passwords.check(&form.new_password, &user.email, &user.name).await;
+        let mut validator = Validator::new();
+        validator.field("new_password", &form.new_password)
+            .required()
+            .check(|_| password_errors);
+        validator.finish().map_err(AccountError::Validation)?;
+
+        let mut user = user.clone();
+        user.password = form.new_password.clone();
+        self.repos.user_repo.add(user.clone()).await.map_err(|_| AccountError::Unavailable)?;
+
+        let closed = self.repos.login_token_repo.delete_for_user(&user.id, Some(session_token), self.clock.now()).await;
+        self.audit(&user.id, AuditAction::PasswordChanged, &user.id).await;
+        tracing::info!(email = %user.email, closed_sessions = closed, "password changed");
+        Ok(())
+    }
+
+    /// Send a verification link to the new address, the email changes once it is followed
+    pub async fn request_email_change(&self, user: &User, form: &EmailForm) -> Result<(), AccountError> {
+        if user.provider.is_some() {
+            return Err(AccountError::ManagedByProvider);
+        }
+        let mut validator = Validator::new();
+        validator.field("email", &form.email)
+            .required()
+            .regex(&EMAIL_REGEX, "abc@example.com");
+        validator.finish().map_err(AccountError::Validation)?;
+
+        if self.repos.user_repo.get_by_email(&form.email).await.is_some() {
+            return Err(AccountError::EmailAlreadyExist);
+        }
+
+        let change = EmailChange {
+            value: AuthService::generate_value(),
+            user: user.id.clone(),
+            new_email: form.email.clone(),
+            expiration: self.clock.now() + Days::new(1),
+        };
+        let link = format!("{}/account/email/verify?token={}", self.config.public_url, change.value);
+
+        self.repos.email_change_repo.add(change).await;
+        self.mailer.send(
+            &form.email,
+            "Confirm your new email",
+            &format!("Follow this link to use this address for your account: {}", link),
+        );
+        Ok(())
+    }
+
+    /// Apply a verified email change
+    pub async fn confirm_email_change(&self, value: &str) -> Result<User, AccountError> {
+        let change = self.repos.email_change_repo.get_by_value(value).await
+            .ok_or(AccountError::TokenNotExist)?;
+        if self.clock.now() > change.expiration {
+            return Err(AccountError::TokenExpired);
+        }
+
+        let mut user = self.repos.user_repo.get_by_id(&change.user).await
+            .ok_or(AccountError::UserDeleted)?;
+        let old_email = std::mem::replace(&mut user.email, change.new_email.clone());
+        self.repos.user_repo.add(user.clone()).await.map_err(|e| match e {
+            RepoError::Conflict(_) => AccountError::EmailAlreadyExist,
+            _ => AccountError::Unavailable,
+        })?;
+
+        self.repos.email_change_repo.delete(value).await;
+        self.audit(&user.id, AuditAction::EmailChanged, &user.id).await;
+        tracing::info!(%old_email, new_email = %user.email, "email changed");
+        Ok(user)
+    }
+
+    /// Schedule the deletion of the account after the grace period, returning the deletion date.
+    /// Accounts of a provider have no local password and confirm with their email instead
+    pub async fn request_deletion(&self, user: &User, form: &DeletionForm) -> Result<DateTime<Utc>, AccountError> {
+        match &user.provider {
+            Some(_) if !form.email.trim().eq_ignore_ascii_case(&user.email) => return Err(AccountError::EmailMismatch),
+            None if !AuthService::verify_password(user, &form.password) => return Err(AccountError::WrongPassword),
+            _ => {}
+        }
+
+        // The config bounds the grace period, an unusable one must never mean an immediate deletion
+        let grace_period = TimeDelta::from_std(self.config.deletion_grace_period).unwrap_or(TimeDelta::days(14));
+        let date = self.clock.now() + grace_period;
+        let mut user = user.clone();
+        user.deletion_scheduled = Some(date);
+        self.repos.user_repo.add(user.clone()).await.map_err(|_| AccountError::Unavailable)?;
+        self.audit(&user.id, AuditAction::DeletionRequested, &user.id).await;
+        tracing::info!(email = %user.email, %date, "account deletion scheduled");
+        Ok(date)
+    }
+
+    pub async fn cancel_deletion(&self, user: &User) -> Result<User, AccountError> {
+        let mut user = user.clone();
+        user.deletion_scheduled = None;
+        self.repos.user_repo.add(user.clone()).await.map_err(|_| AccountError::Unavailable)?;
+        self.audit(&user.id, AuditAction::DeletionCancelled, &user.id).await;
+        Ok(user)
+    }
+
+    /// Remove the account right away: its application grants, consents and sessions are removed
+    /// and its audit records only keep an anonymous identifier
+    pub async fn delete_account(&self, id: &str, actor: &str) -> Result<(), AccountError> {
+        self.repos.user_repo.get_by_id(id).await
+            .ok_or(AccountError::UserDeleted)?;
+
+        for application in self.repos.application_repo.get_all().await {
+            if application.users.contains(id) {
+                self.repos.application_repo.remove_user(&application.client_id, id).await;
+                self.repos.refresh_token_repo.delete_for_client(&application.client_id, id).await;
+            }
+        }
+        let sessions = self.repos.login_token_repo.delete_for_user(id, None, self.clock.now()).await;
+        self.repos.consent_repo.delete_for_user(id).await;
+        self.repos.favorite_repo.delete_for_user(id).await;
+
+        let anonymous = format!("deleted-{}", Uuid::new_v4().simple());
+        let actor = if actor == id { anonymous.as_str() } else { actor };
+        self.repos.audit_repo.replace_user(id, &anonymous).await;
+        self.repos.user_repo.delete(id).await;
+        self.audit(actor, AuditAction::AccountDeleted, &anonymous).await;
+
+        tracing::info!(deleted = %anonymous, by = %actor, closed_sessions = sessions, "account deleted");
+        Ok(())
+    }
+
+    /// Delete every account whose grace period is over, returning how many were deleted
+    pub async fn delete_scheduled(&self) -> usize {
+        let now = self.clock.now();
+        let users = self.repos.user_repo.get_all().await;
+
+        let mut deleted = 0;
+        for user in users {
+            if user.deletion_scheduled.is_some_and(|date| date <= now)
+                && self.delete_account(&user.id, &user.id).await.is_ok() {
+                deleted += 1;
+            }
+        }
+        deleted
+    }
+
+    /// Everything kept about the user, for the "download my data" archive
+    pub async fn export(&self, user: &User) -> Value {
+        let sessions: Vec<Value> = self.repos.login_token_repo.get_for_user(&user.id, self.clock.now()).await.into_iter()
+            .map(|token| json!({ "expiration": token.expiration.to_rfc3339() }))
+            .collect();
+        let favorites = self.repos.favorite_repo.get_for_user(&user.id).await;
+        let applications: Vec<Value> = self.repos.application_repo.get_all().await.into_iter()
+            .filter(|application| application.users.contains(&user.id))
+            .map(|application| json!({
+                "name": application.name,
+                "url": application.url,
+                "client_id": application.client_id,
+                "favorite": favorites.contains(&application.client_id),
+            }))
+            .collect();
+        let consents: Vec<Value> = self.repos.consent_repo.get_for_user(&user.id).await.into_iter()
+            .map(|consent| json!({
+                "client_id": consent.client_id,
+                "scopes": consent.scopes,
+                "granted": consent.granted.to_rfc3339(),
+            }))
+            .collect();
+        let events: Vec<Value> = self.repos.audit_repo.get_for_user(&user.id).await.into_iter()
+            .map(|event| json!({
+                "date": event.date.to_rfc3339(),
+                "actor": event.actor,
+                "action": event.action.as_str(),
+                "subject": event.subject,
+            }))
+            .collect();
+
+        let mut groups: Vec<&String> = user.groups.iter().collect();
+        groups.sort();
+        json!({
+            "profile": {
+                "id": user.id,
+                "name": user.name,
+                "email": user.email,
+                "admin": user.admin,
+                "groups": groups,
+                "status": format!("{:?}", user.status),
+                "avatar_url": user.avatar_url,
+                "timezone": user.timezone,
+                "locale": user.locale,
+                "created": user.created.to_rfc3339(),
+                "deletion_scheduled": user.deletion_scheduled.map(|date| date.to_rfc3339()),
+            },
+            "sessions": sessions,
+            "applications": applications,
+            "consents": consents,
+            "audit_events": events,
+        })
+    }
+
+    async fn audit(&self, actor: &str, action: AuditAction, subject: &str) {
+        self.repos.audit_repo.add(AuditEvent::new(self.clock.now(), actor, action, subject)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use std::collections::HashSet;
+    use super::*;
+    use crate::forms::auth::LoginForm;
+    use crate::objects::application::{Application, Protocol};
+    use crate::objects::login_token::LoginToken;
+    use crate::services::clock::MockClock;
+    use crate::services::metrics::Metrics;
+
+    /// Keeps the bodies of the sent emails
+    #[derive(Default)]
+    struct RecordingMailer(Mutex<Vec<String>>);
+
+    impl Mailer for RecordingMailer {
+        fn send(&self, _to: &str, _subject: &str, body: &str) {
+            self.0.lock().unwrap().push(body.to_string());
+        }
+    }
+
+    struct Setup {
+        repos: Repos,
+        account: AccountService,
+        auth: AuthService,
+        clock: Arc<MockClock>,
+        mailer: Arc<RecordingMailer>,
+    }
+
+    fn setup() -> Setup {
+        let config = Config::default();
+        let repos = Repos::new(&config, &Metrics::new());
+        let clock = Arc::new(MockClock::new(Utc::now()));
+        let mailer = Arc::new(RecordingMailer::default());
+        Setup {
+            account: AccountService::new(config.clone(), repos.clone(), clock.clone(), mailer.clone()),
+            auth: AuthService::new(config, repos.clone(), Metrics::new(), clock.clone()),
+            repos,
+            clock,
+            mailer,
+        }
+    }
+
+    fn admin_login() -> LoginForm {
+        LoginForm {
+            email: "admin@example.com".to_string(),
+            password: "admin".to_string()
+        }
+    }
+
+    fn sent_token(mailer: &RecordingMailer) -> String {
+        let body = mailer.0.lock().unwrap().last().unwrap().clone();
+        body.split("token=").nth(1).unwrap().to_string()
+    }
+
+    #[actix_web::test]
+    async fn test_update_profile() {
+        let s = setup();
+        let user = s.repos.user_repo.get_by_email("admin@example.com").await.unwrap();
+        let form = |name: &str| ProfileForm {
+            name: name.to_string(),
+            avatar_url: " ".to_string(),
+            timezone: "Europe/Paris".to_string(),
+            locale: "en-US".to_string(),
+        };
+
+        assert!(matches!(s.account.update_profile(&user, &form("  a  ")).await, Err(AccountError::Validation(_))));
+        let updated = s.account.update_profile(&user, &form("  Ada  ")).await.ok().unwrap();
+        assert_eq!("Ada", updated.name);
+        assert_eq!(None, updated.avatar_url);
+    }
+
+    #[actix_web::test]
+    async fn test_change_password_closes_other_sessions() {
+        let s = setup();
+        let current = s.auth.login(&admin_login()).await.ok().unwrap();
+        let other = s.auth.login(&admin_login()).await.ok().unwrap();
+        let user = s.auth.authenticate(&current.value).await.ok().unwrap();
+
+        let wrong = PasswordForm {
+            current_password: "wrong".to_string(),
+            new_password: "new password".to_string(),
+        };
+        assert!(matches!(s.account.change_password(&user, &current.value, &wrong).await, Err(AccountError::WrongPassword)));
+
+        let form = PasswordForm {
+            current_password: "admin".to_string(),
+            new_password: "new password".to_string(),
+        };
+        assert!(s.account.change_password(&user, &current.value, &form).await.is_ok());
+        assert!(s.auth.authenticate(&current.value).await.is_ok());
+        assert!(s.auth.authenticate(&other.value).await.is_err());
+        assert!(s.auth.login(&admin_login()).await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_email_change() {
+        let s = setup();
+        let token = s.auth.login(&admin_login()).await.ok().unwrap();
+        let user = s.auth.authenticate(&token.value).await.ok().unwrap();
+
+        let form = EmailForm { email: "root@example.com".to_string() };
+        assert!(s.account.request_email_change(&user, &form).await.is_ok());
+        // nothing changes until the link is followed
+        assert!(s.auth.authenticate(&token.value).await.is_ok());
+
+        let changed = s.account.confirm_email_change(&sent_token(&s.mailer)).await.ok().unwrap();
+        assert_eq!("root@example.com", changed.email);
+        assert_eq!(user.id, changed.id);
+        // the sessions follow the user
+        assert_eq!("root@example.com", s.auth.authenticate(&token.value).await.ok().unwrap().email);
+        assert!(s.auth.login(&admin_login()).await.is_err());
+        assert!(s.auth.login(&LoginForm {
+            email: "root@example.com".to_string(),
+            password: "admin".to_string(),
+        }).await.is_ok());
+    }
+
+    #[actix_web::test]
+    async fn test_email_change_expiry() {
+        let s = setup();
+        let token = s.auth.login(&admin_login()).await.ok().unwrap();
+        let user = s.auth.authenticate(&token.value).await.ok().unwrap();
+
+        let form = EmailForm { email: "root@example.com".to_string() };
+        assert!(s.account.request_email_change(&user, &form).await.is_ok());
+
+        s.clock.advance(TimeDelta::days(2));
+        assert!(matches!(s.account.confirm_email_change(&sent_token(&s.mailer)).await, Err(AccountError::TokenExpired)));
+    }
+
+    #[actix_web::test]
+    async fn test_deletion_after_grace_period() {
+        let s = setup();
+        let token = s.auth.login(&admin_login()).await.ok().unwrap();
+        let user = s.auth.authenticate(&token.value).await.ok().unwrap();
+        s.repos.application_repo.add(Application {
+            name: "Wiki".to_string(),
+            url: "https://wiki.example.com".to_string(),
+            icon_url: None,
+            client_id: "wiki".to_string(),
+            client_secret: "secret".to_string(),
+            protocol: Protocol::Oidc,
+            scopes: HashSet::new(),
+            users: HashSet::from([user.id.clone()]),
+            pre_consented: false,
+        }).await;
+
+        let form = DeletionForm { password: "admin".to_string(), email: String::new() };
+        s.account.request_deletion(&user, &form).await.ok().unwrap();
+
+        s.clock.advance(TimeDelta::days(13));
+        assert_eq!(0, s.account.delete_scheduled().await);
+        assert!(s.auth.authenticate(&token.value).await.is_ok());
+
+        s.clock.advance(TimeDelta::days(2));
+        assert_eq!(1, s.account.delete_scheduled().await);
+        assert!(s.repos.user_repo.get_by_email("admin@example.com").await.is_none());
+        assert!(s.auth.authenticate(&token.value).await.is_err());
+        assert!(s.repos.application_repo.get_by_client_id("wiki").await.unwrap().users.is_empty());
+        assert!(s.repos.audit_repo.get_for_user(&user.id).await.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_cancelled_deletion() {
+        let s = setup();
+        let token = s.auth.login(&admin_login()).await.ok().unwrap();
+        let user = s.auth.authenticate(&token.value).await.ok().unwrap();
+
+        let form = DeletionForm { password: "admin".to_string(), email: String::new() };
+        s.account.request_deletion(&user, &form).await.ok().unwrap();
+        let user = s.auth.authenticate(&token.value).await.ok().unwrap();
+        assert!(s.account.cancel_deletion(&user).await.is_ok());
+
+        s.clock.advance(TimeDelta::days(30));
+        assert_eq!(0, s.account.delete_scheduled().await);
+        assert!(s.repos.user_repo.get_by_email("admin@example.com").await.is_some());
+    }
+
+    #[actix_web::test]
+    async fn test_export() {
+        let s = setup();
+        let token = s.auth.login(&admin_login()).await.ok().unwrap();
+        let user = s.auth.authenticate(&token.value).await.ok().unwrap();
+        s.repos.login_token_repo.add(LoginToken { value: "other".to_string(), user: "other".to_string(), ..token.clone() }).await;
+
+        let data = s.account.export(&user).await;
+        assert_eq!("admin@example.com", data["profile"]["email"]);
+        assert_eq!(1, data["sessions"].as_array().unwrap().len());
+        assert_eq!("login", data["audit_events"][0]["action"]);
+        // the session tokens never leave the server
+        assert!(!data.to_string().contains(&token.value));
+    }
+
+    #[actix_web::test]
+    async fn test_provider_account_deletion() {
+        let s = setup();
+        let mut user = s.repos.user_repo.get_by_email("admin@example.com").await.unwrap();
+        user.provider = Some("github".to_string());
+        assert!(s.repos.user_repo.add(user.clone()).await.is_ok());
+
+        let form = DeletionForm { password: "admin".to_string(), email: String::new() };
+        assert!(matches!(s.account.request_deletion(&user, &form).await, Err(AccountError::EmailMismatch)));
+        let form = DeletionForm { password: String::new(), email: "Admin@example.com ".to_string() };
+        assert!(s.account.request_deletion(&user, &form).await.is_ok());
+    }
+}
@@ -0,0 +1,102 @@
+use std::sync::Arc;
+use crate::errors::admin::AdminError;
+use crate::objects::application::Application;
+use crate::objects::audit_event::{AuditAction, AuditEvent};
+use crate::objects::user::{StatusChange, User, UserStatus};
+use crate::services::clock::Clock;
+use crate::services::factory::Repos;
+
+pub struct AdminService {
+    repos: Repos,
+    clock: Arc<dyn Clock>,
+}
+
+impl AdminService {
+    pub fn new(repos: Repos, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            repos,
+            clock,
+        }
+    }
+
+    pub async fn list_users(&self) -> Vec<User> {
+        let mut users = self.repos.user_repo.get_all().await;
+        users.sort_by(|a, b| a.email.cmp(&b.email));
+        users
+    }
+
+    pub async fn list_applications(&self) -> Vec<Application> {
+        let mut applications = self.repos.application_repo.get_all().await;
+        applications.sort_by(|a, b| a.name.cmp(&b.name));
+        applications
+    }
+
+    /// Mark a first-party application, its users are not asked for consent anymore
+    pub async fn set_pre_consented(&self, admin: &User, client_id: &str, pre_consented: bool) -> Result<Application, AdminError> {
+        let updated = self.repos.application_repo.set_pre_consented(client_id, pre_consented).await;
+        if !updated {
+            return Err(AdminError::ApplicationNotExist);
+        }
+        tracing::info!(changed_by = %admin.email, client_id, pre_consented, "application consent changed");
+
+        self.repos.application_repo.get_by_client_id(client_id).await
+            .ok_or(AdminError::ApplicationNotExist)
+    }
+
+    /// Let a pending account log in
+    pub async fn approve_user(&self, admin: &User, id: &str) -> Result<(), AdminError> {
+        let mut user = self.repos.user_repo.get_by_id(id).await.ok_or(AdminError::UserNotExist)?;
+
+        if user.status != UserStatus::Pending {
+            return Err(AdminError::NotPending);
+        }
+        user.status = UserStatus::Active;
+
+        tracing::info!(approved_by = %admin.email, email = %user.email, "account approved");
+        self.repos.user_repo.add(user.clone()).await.map_err(|_| AdminError::Unavailable)?;
+
+        self.repos.audit_repo.add(AuditEvent::new(self.clock.now(), &admin.id, AuditAction::AccountApproved, id)).await;
+        Ok(())
+    }
+
+    /// Suspend an account and close its sessions, its data is kept
+    pub async fn disable_user(&self, admin: &User, id: &str, reason: &str) -> Result<(), AdminError> {
+        if admin.id == id {
+            return Err(AdminError::OwnAccount);
+        }
+        if reason.trim().is_empty() {
+            return Err(AdminError::ReasonRequired);
+        }
+        let mut user = self.repos.user_repo.get_by_id(id).await.ok_or(AdminError::UserNotExist)?;
+
+        user.status = UserStatus::Disabled;
+        user.status_change = Some(StatusChange {
+            by: admin.id.clone(),
+            reason: reason.trim().to_string(),
+            date: self.clock.now(),
+        });
+        self.repos.user_repo.add(user.clone()).await.map_err(|_| AdminError::Unavailable)?;
+        let sessions = self.repos.login_token_repo.delete_for_user(id, None, self.clock.now()).await;
+
+        tracing::info!(disabled_by = %admin.email, email = %user.email, reason, closed_sessions = sessions, "account disabled");
+        self.repos.audit_repo.add(AuditEvent::new(self.clock.now(), &admin.id, AuditAction::AccountDisabled, id)).await;
+        Ok(())
+    }
+
+    /// Make a disabled or locked account active again
+    pub async fn enable_user(&self, admin: &User, id: &str) -> Result<(), AdminError> {
+        let mut user = self.repos.user_repo.get_by_id(id).await.ok_or(AdminError::UserNotExist)?;
+
+        if !matches!(user.status, UserStatus::Disabled | UserStatus::Locked) {
+            return Err(AdminError::NotDisabled);
+        }
+        user.status = UserStatus::Active;
+        user.status_change = None;
+        user.failed_logins = 0;
+        self.repos.user_repo.add(user.clone()).await.map_err(|_| AdminError::Unavailable)?;
+
+        tracing::info!(enabled_by = %admin.email, email = %user.email, "account enabled");
+        self.repos.audit_repo.add(AuditEvent::new(self.clock.now(), &admin.id, AuditAction::AccountEnabled, id)).await;
+        Ok(())
+    }
+}
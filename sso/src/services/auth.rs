@@ -1,209 +1,900 @@
-use chrono::{DateTime, Days, Utc};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use chrono::{DateTime, Days, TimeDelta, Utc};
 use rand::distr::Alphanumeric;
 use rand::Rng;
-use regex::Regex;
+use crate::errors::admin::AdminError;
 use crate::errors::auth::{AuthenticateError, LoginError, RegisterError};
-use crate::errors::validation::{ValidationEnumError, ValidationError};
 use crate::forms::auth::{LoginForm, RegisterForm};
+use crate::forms::validation::{Validator, EMAIL_REGEX};
+use crate::objects::audit_event::{AuditAction, AuditEvent};
 use crate::objects::config::Config;
+use crate::objects::group::Group;
 use crate::objects::login_token::LoginToken;
-use crate::objects::user::User;
+use crate::objects::registration_token::{InviteRestriction, RegisterToken, RegisterTokenUse};
+use crate::objects::user::{StatusChange, User, UserStatus};
+use crate::services::auth_provider::{AuthProvider, ExternalIdentity};
+use crate::services::clock::Clock;
 use crate::services::factory::Repos;
+use crate::services::ldap::LdapProvider;
+use crate::services::metrics::Metrics;
+use crate::services::password::PasswordService;
 
+#[derive(Clone)]
 pub struct AuthService {
     repos: Repos,
     config: Config,
+    metrics: Metrics,
+    clock: Arc<dyn Clock>,
+    passwords: PasswordService,
+    /// Checked in order before the local accounts
+    providers: Vec<Arc<dyn AuthProvider>>,
 }
 
+pub struct NewInvite {
+    pub days_valid: u64,
+    pub max_uses: u32,
+    pub restriction: Option<InviteRestriction>,
+    pub groups: HashSet<String>,
+    pub applications: HashSet<String>,
+}
+
+/// Longest validity of an invitation
+pub const MAX_INVITE_DAYS: u64 = 365;
+
 type LoginResult = Result<LoginToken, LoginError>;
-type RegisterResult = Result<(), RegisterError>;
+type RegisterResult = Result<User, RegisterError>;
 type AuthenticateResult = Result<User, AuthenticateError>;
 
 impl AuthService {
-    pub fn new(config: Config, repos: Repos) -> Self {
+    pub fn new(config: Config, repos: Repos, metrics: Metrics, clock: Arc<dyn Clock>) -> Self {
+        let providers = config.ldap.clone()
+            .map(|ldap| Arc::new(LdapProvider::new(ldap)) as Arc<dyn AuthProvider>)
+            .into_iter()
+            .collect();
+
         Self {
+            passwords: PasswordService::new(config.password_policy.clone()),
+            providers,
             repos,
-            config
+            config,
+            metrics,
+            clock,
         }
     }
-    fn generate_value() -> String {
+    #[cfg(test)]
+    pub fn add_provider(&mut self, provider: Arc<dyn AuthProvider>) {
+        self.providers.push(provider);
+    }
+    pub fn generate_value() -> String {
         rand::rng()
             .sample_iter(&Alphanumeric)
             .take(20)
             .map(char::from)
             .collect()
     }
-    fn generate_token(user: &User) -> LoginToken {
+    fn generate_token(&self, user: &User) -> LoginToken {
         LoginToken {
-            user: user.email.clone(),
-            expiration: Utc::now() + Days::new(30),
+            user: user.id.clone(),
+            expiration: self.clock.now() + Days::new(30),
             value: Self::generate_value(),
         }
     }
-    fn date_expired(date: &DateTime<Utc>) -> bool {
-        &Utc::now() > date
+    fn date_expired(&self, date: &DateTime<Utc>) -> bool {
+        &self.clock.now() > date
     }
-    fn verify_password(user: &User, password: &str) -> bool {
+    pub fn verify_password(user: &User, password: &str) -> bool {
         user.password == password
     }
 
-    pub fn login(&self, form: &LoginForm) -> LoginResult {
-        let user = self.repos.user_repo.get_by_email(&form.email)
-            .ok_or(LoginError::EmailNotExist)?;
+    pub async fn login(&self, form: &LoginForm) -> LoginResult {
+        let result = self.try_login(form).await;
 
-        if !Self::verify_password(&user, &form.password) {
+        let outcome = match &result {
+            Ok(_) => {
+                tracing::info!(email = %form.email, "login succeeded");
+                "success"
+            }
+            Err(e) => {
+                tracing::warn!(?form, error = %e, "login failed");
+                e.kind()
+            }
+        };
+        self.metrics.logins.with_label_values(&[outcome]).inc();
+        result
+    }
+    async fn try_login(&self, form: &LoginForm) -> LoginResult {
+        let limit = self.config.login_rate_limit;
+        if limit > 0 {
+            let key = format!("login:{}", form.email.to_lowercase());
+            if self.repos.throttle_repo.hit(&key, self.clock.now(), TimeDelta::minutes(1)).await > limit {
+                return Err(LoginError::TooManyAttempts);
+            }
+        }
+
+        let (user, verified) = match self.provider_login(form).await? {
+            Some(user) => (user, true),
+            None => {
+                let user = self.repos.user_repo.get_by_email(&form.email).await.ok_or(LoginError::EmailNotExist)?;
+                (user, false)
+            }
+        };
+
+        if user.status == UserStatus::Locked {
+            return Err(LoginError::AccountLocked);
+        }
+        // the password of provisioned users is only known by their provider
+        if !verified && (user.provider.is_some() || !Self::verify_password(&user, &form.password)) {
+            self.failed_login(&user.id).await;
             return Err(LoginError::WrongPassword);
         }
+        self.open_session(user).await
+    }
+    /// Create a session for a user whose credentials were checked
+    pub async fn open_session(&self, mut user: User) -> LoginResult {
+        match user.status {
+            UserStatus::Locked => return Err(LoginError::AccountLocked),
+            UserStatus::Pending => return Err(LoginError::PendingApproval),
+            UserStatus::Disabled => return Err(LoginError::AccountDisabled),
+            _ => {}
+        }
+        if user.failed_logins > 0 {
+            user.failed_logins = 0;
+            if let Err(e) = self.repos.user_repo.add(user.clone()).await {
+                tracing::error!(error = %e, "could not reset the failed logins");
+            }
+        }
 
-        let token = Self::generate_token(&user);
+        let token = self.generate_token(&user);
 
-        self.repos.login_token_repo.add(token.clone());
+        self.repos.login_token_repo.add(token.clone()).await;
 
+        self.repos.audit_repo.add(AuditEvent::new(self.clock.now(), &user.id, AuditAction::Login, &user.id)).await;
         Ok(token)
     }
-    fn verify_register_token(&self, token: &str) -> Result<(), RegisterError> {
-        match self.repos.register_token_repo.get_by_value(token) {
-            None => Err(RegisterError::TokenNotExist),
-            Some(token) => {
-                match Self::date_expired(&token.expiration) {
-                    true => Err(RegisterError::TokenExpired),
-                    false => Ok(())
+    /// The user authenticated by the first provider accepting the credentials,
+    /// `None` to fall back to the local accounts
+    async fn provider_login(&self, form: &LoginForm) -> Result<Option<User>, LoginError> {
+        for provider in &self.providers {
+            match provider.authenticate(&form.email, &form.password).await {
+                Ok(Some(identity)) => if let Some(user) = self.provision(provider.name(), identity).await? {
+                    return Ok(Some(user));
+                },
+                Ok(None) => {}
+                Err(e) => {
+                    tracing::warn!(provider = provider.name(), error = %e, "authentication provider failed");
+                    // the password of its users can not be checked without it, and is not wrong
+                    if self.managed_by(provider.name(), &form.email).await {
+                        return Err(LoginError::Unavailable);
+                    }
                 }
             }
         }
+        Ok(None)
+    }
+    async fn managed_by(&self, provider: &str, email: &str) -> bool {
+        self.repos.user_repo.get_by_email(email).await
+            .is_some_and(|user| user.provider.as_deref() == Some(provider))
     }
-    fn validate_user(form: &RegisterForm) -> Result<(), RegisterError> {
-        if form.name.len() < 2 || form.name.len() > 40 {
-            return Err(RegisterError::Validation(ValidationError {
-                field: "name".to_string(),
-                error: ValidationEnumError::Size(2, 40)
-            }));
+    /// Create the user on its first login, or update it from its provider,
+    /// `None` when the email belongs to an account the provider does not manage
+    async fn provision(&self, provider: &str, identity: ExternalIdentity) -> Result<Option<User>, LoginError> {
+        let existing = self.repos.user_repo.get_by_email(&identity.email).await;
+
+        if let Some(mut user) = existing {
+            if user.provider.as_deref() != Some(provider) {
+                tracing::warn!(provider, email = %user.email, "provider identity matches an account it does not manage");
+                return Ok(None);
+            }
+            user.name = identity.name;
+            user.groups = identity.groups;
+            self.repos.user_repo.add(user.clone()).await.map_err(|_| LoginError::Unavailable)?;
+            self.store_groups(&user.groups).await;
+            return Ok(Some(user));
+        }
+
+        let user = User {
+            id: User::new_id(),
+            name: identity.name,
+            email: identity.email,
+            // never checked, but not empty so that it can not match an empty password
+            password: Self::generate_value(),
+            admin: false,
+            groups: identity.groups,
+            status: UserStatus::Active,
+            status_change: None,
+            failed_logins: 0,
+            provider: Some(provider.to_string()),
+            federated: HashMap::new(),
+            avatar_url: None,
+            timezone: "UTC".to_string(),
+            locale: "en".to_string(),
+            deletion_scheduled: None,
+            created: self.clock.now(),
+        };
+        let mut uow = self.repos.unit_of_work().await;
+        if uow.add_user(user.clone()).await.is_err() {
+            uow.rollback().await;
+            return Err(LoginError::Unavailable);
+        }
+        uow.commit().await.map_err(|_| LoginError::Unavailable)?;
+        self.store_groups(&user.groups).await;
+
+        tracing::info!(provider, email = %user.email, "user provisioned");
+        Ok(Some(user))
+    }
+    /// Count a wrong password, locking the account once the configured threshold is reached
+    async fn failed_login(&self, id: &str) {
+        // read again so that a status changed since the login started is kept
+        let user = self.repos.user_repo.get_by_id(id).await;
+        let Some(mut user) = user else {
+            return;
+        };
+        // only active accounts are counted, a disabled or pending one must not become locked
+        if user.status != UserStatus::Active {
+            return;
         }
-        if form.password.len() < 6 || form.password.len() > 255 {
-            return Err(RegisterError::Validation(ValidationError {
-                field: "password".to_string(),
-                error: ValidationEnumError::Size(6, 255)
-            }));
+        user.failed_logins += 1;
+
+        let threshold = self.config.lockout_threshold;
+        if threshold > 0 && user.failed_logins >= threshold {
+            user.status = UserStatus::Locked;
+            user.status_change = Some(StatusChange {
+                by: "system".to_string(),
+                reason: format!("{} failed logins", user.failed_logins),
+                date: self.clock.now(),
+            });
+            tracing::warn!(email = %user.email, failed_logins = user.failed_logins, "account locked");
+            self.repos.audit_repo.add(AuditEvent::new(self.clock.now(), "system", AuditAction::AccountLocked, &user.id)).await;
         }
-        if !Regex::new(r"^[\w\.-]+@([\w-]+\.)+[\w-]{2,4}$").unwrap().is_match(&form.email) {
-            return Err(RegisterError::Validation(ValidationError {
-                field: "email".to_string(),
-                error: ValidationEnumError::Regex("abc@example.com".to_string()),
-            }));
+
+        if let Err(e) = self.repos.user_repo.add(user).await {
+            tracing::error!(error = %e, "could not count the failed login");
         }
-        Ok(())
     }
-    pub fn register(&self, form: &RegisterForm) -> RegisterResult {
-        match (self.config.restrict_registration, &form.token) {
-            (false, _) => Ok(()),
-            (true, None) => Err(RegisterError::TokenRequired),
-            (true, Some(token)) => self.verify_register_token(token),
-        }?;
+    fn verify_register_token(&self, token: Option<RegisterToken>, email: &str) -> Result<RegisterToken, RegisterError> {
+        let token = token.ok_or(RegisterError::TokenNotExist)?;
 
-        Self::validate_user(form)?;
+        if self.date_expired(&token.expiration) {
+            return Err(RegisterError::TokenExpired);
+        }
+        if !token.allows(email) {
+            return Err(RegisterError::TokenNotAllowed);
+        }
+        Ok(token)
+    }
+    async fn validate_user(&self, form: &RegisterForm) -> Result<(), RegisterError> {
+        let password_errors = self.passwords.check(&form.password, &form.email, &form.name).await;
+        let mut validator = Validator::new();
 
-        let user = User {
+        validator.field("name", &form.name)
+            .required()
+            .size(2, 40);
+        validator.field("email", &form.email)
+            .required()
+            .regex(&EMAIL_REGEX, "abc@example.com");
+        validator.field("password", &form.password)
+            .required()
+            .check(|_| password_errors);
+
+        validator.finish().map_err(RegisterError::Validation)
+    }
+    pub async fn register(&self, form: &RegisterForm) -> RegisterResult {
+        let result = self.try_register(form).await;
+
+        let outcome = match &result {
+            Ok(_) => {
+                tracing::info!(email = %form.email, "registration succeeded");
+                "success"
+            }
+            Err(e) => {
+                tracing::warn!(?form, error = %e, "registration failed");
+                e.kind()
+            }
+        };
+        self.metrics.registrations.with_label_values(&[outcome]).inc();
+        result
+    }
+    async fn try_register(&self, form: &RegisterForm) -> RegisterResult {
+        self.validate_user(form).await?;
+
+        let policy = &self.config.registration;
+        let domain = form.email.rsplit_once('@').map(|(_, domain)| domain).unwrap_or_default();
+        if policy.is_blocked(domain) {
+            return Err(RegisterError::DomainBlocked);
+        }
+        // Anyone may register with an open domain, the invitation is not needed and not spent
+        let invitation = form.token.as_ref().filter(|_| !policy.is_open(domain));
+        if !policy.is_open(domain) && invitation.is_none() {
+            return Err(RegisterError::TokenRequired);
+        }
+        // Invitations are created by admins, so they count as an approval
+        let status = match policy.approval_required && invitation.is_none() {
+            true => UserStatus::Pending,
+            false => UserStatus::Active,
+        };
+
+        let mut user = User {
+            id: User::new_id(),
             email: form.email.clone(),
             password: form.password.clone(),
             name: form.name.clone(),
             admin: false,
-            created: Utc::now(),
+            groups: HashSet::new(),
+            status,
+            status_change: None,
+            failed_logins: 0,
+            provider: None,
+            federated: HashMap::new(),
+            avatar_url: None,
+            timezone: "UTC".to_string(),
+            locale: "en".to_string(),
+            deletion_scheduled: None,
+            created: self.clock.now(),
         };
 
-        self.repos.user_repo.add(user);
+        let mut uow = self.repos.unit_of_work().await;
 
-        if let Some(token) = &form.token {
-            self.repos.register_token_repo.delete(token)
+        // The token is used before creating the user so that its uses can not be exceeded
+        let mut applications = HashSet::new();
+        if let Some(value) = invitation {
+            let usage = RegisterTokenUse {
+                email: form.email.clone(),
+                date: self.clock.now(),
+            };
+            let token = match uow.consume_register_token(value, usage).await {
+                Some(token) => Some(token),
+                None => {
+                    // tell an invitation used up from a wrong one
+                    if self.repos.register_token_repo.get_by_value(value).await.is_some_and(|token| token.is_exhausted()) {
+                        uow.rollback().await;
+                        return Err(RegisterError::TokenUsedUp);
+                    }
+                    None
+                }
+            };
+            match self.verify_register_token(token, &form.email) {
+                Ok(token) => {
+                    user.groups = token.groups;
+                    applications = token.applications;
+                }
+                Err(e) => {
+                    uow.rollback().await;
+                    return Err(e);
+                }
+            }
+        }
+
+        if uow.add_user(user.clone()).await.is_err() {
+            uow.rollback().await;
+            return Err(RegisterError::EmailAlreadyExist);
+        }
+        for client_id in &applications {
+            if let Err(e) = uow.grant_application(client_id, &user.id).await {
+                tracing::warn!(error = %e, "invitation grants an unknown application");
+            }
         }
 
-        Ok(())
+        uow.commit().await.map_err(|_| RegisterError::Unavailable)?;
+        self.store_groups(&user.groups).await;
+        Ok(user)
     }
-    pub fn authenticate(&self, token: &str) -> AuthenticateResult {
-        let token = match self.repos.login_token_repo.get_by_value(token) {
+    /// Store the groups given to a provisioned user that do not exist yet,
+    /// the user keeps them by name when this fails
+    async fn store_groups(&self, groups: &HashSet<String>) {
+        for name in groups {
+            if self.repos.group_repo.get_by_name(name).await.is_some() {
+                continue;
+            }
+            let mut group = Group::named(name, self.clock.now());
+            // the derived id belongs to a group renamed since
+            if self.repos.group_repo.get_by_id(&group.id).await.is_some() {
+                group.id = User::new_id();
+            }
+            if let Err(e) = self.repos.group_repo.add(group).await {
+                tracing::error!(error = %e, group = name, "could not store the group");
+            }
+        }
+    }
+    /// Create an invitation on behalf of `creator`
+    pub async fn create_invite(&self, creator: &User, invite: NewInvite) -> Result<RegisterToken, AdminError> {
+        let expiration = Some(invite.days_valid)
+            .filter(|days| (1..=MAX_INVITE_DAYS).contains(days))
+            .and_then(|days| self.clock.now().checked_add_days(Days::new(days)))
+            .ok_or(AdminError::InviteValidity)?;
+        for client_id in &invite.applications {
+            if self.repos.application_repo.get_by_client_id(client_id).await.is_none() {
+                return Err(AdminError::ApplicationNotExist);
+            }
+        }
+
+        let token = RegisterToken {
+            value: Self::generate_value(),
+            expiration,
+            max_uses: invite.max_uses.max(1),
+            uses: vec![],
+            restriction: invite.restriction,
+            groups: invite.groups,
+            applications: invite.applications,
+            created_by: Some(creator.id.clone()),
+        };
+
+        tracing::info!(created_by = %creator.email, max_uses = token.max_uses, "invitation created");
+        self.repos.register_token_repo.add(token.clone()).await;
+        Ok(token)
+    }
+    pub async fn list_invites(&self) -> Vec<RegisterToken> {
+        let mut tokens = self.repos.register_token_repo.get_all().await;
+        tokens.sort_by_key(|token| token.expiration);
+        tokens
+    }
+    pub async fn authenticate(&self, token: &str) -> AuthenticateResult {
+        let result = self.try_authenticate(token).await;
+
+        if let Err(e) = &result {
+            tracing::debug!(error = %e, "authentication failed");
+            self.metrics.authentication_failures.with_label_values(&[e.kind()]).inc();
+        }
+        result
+    }
+    async fn try_authenticate(&self, token: &str) -> AuthenticateResult {
+        let token = self.repos.login_token_repo.get_by_value(token).await;
+        let token = match token {
             None => Err(AuthenticateError::TokenNotExist),
             Some(token) => {
-                match Self::date_expired(&token.expiration) {
+                match self.date_expired(&token.expiration) {
                     true => Err(AuthenticateError::TokenExpired),
                     false => Ok(token),
                 }
             }
         }?;
 
-        match self.repos.user_repo.get_by_email(&token.user) {
-            None => Err(AuthenticateError::UserDeleted),
-            Some(user) => Ok(user),
+        let user = self.repos.user_repo.get_by_id(&token.user).await
+            .ok_or(AuthenticateError::UserDeleted)?;
+        match user.status {
+            UserStatus::Disabled => Err(AuthenticateError::AccountDisabled),
+            UserStatus::Locked => Err(AuthenticateError::AccountLocked),
+            _ => Ok(user),
         }
     }
-    pub fn invalidate_token(&self, token: &str) {
-        self.repos.login_token_repo.delete(token)
+    pub async fn invalidate_token(&self, token: &str) {
+        tracing::info!("session invalidated");
+        self.repos.login_token_repo.delete(token).await
+    }
+    /// Number of login tokens that are still valid
+    pub async fn active_sessions(&self) -> usize {
+        self.repos.login_token_repo.get_all(self.clock.now()).await.len()
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use chrono::TimeDelta;
     use super::*;
+    use crate::errors::admin::AdminError;
+    use crate::errors::provider::ProviderError;
+    use crate::objects::application::{Application, Protocol};
+    use crate::services::admin::AdminService;
+    use crate::services::clock::{MockClock, SystemClock};
 
-    #[test]
-    fn test_validate_user() {
-        assert_eq!(true, AuthService::validate_user(&RegisterForm{
+    #[actix_web::test]
+    #[allow(clippy::bool_assert_comparison)]
+    async fn test_validate_user() {
+        let service = get_service();
+        assert_eq!(true, service.validate_user(&RegisterForm{
             name: "Test".to_string(),
             password: "testtest".to_string(),
             email: "test@example.com".to_string(),
             token: None,
-        }).is_ok());
-        assert_eq!(false, AuthService::validate_user(&RegisterForm{
+        }).await.is_ok());
+        assert_eq!(false, service.validate_user(&RegisterForm{
             name: "Test".to_string(),
             password: "testtest".to_string(),
             email: "test".to_string(),
             token: None,
-        }).is_ok());
-        assert_eq!(false, AuthService::validate_user(&RegisterForm{
+        }).await.is_ok());
+        assert_eq!(false, service.validate_user(&RegisterForm{
             name: "Test".to_string(),
             password: "testtest".to_string(),
             email: "test@@example.com".to_string(),
             token: None,
-        }).is_ok());
-        assert_eq!(false, AuthService::validate_user(&RegisterForm{
+        }).await.is_ok());
+        assert_eq!(false, service.validate_user(&RegisterForm{
             name: "Test".to_string(),
             password: "test".to_string(),
             email: "test@example.com".to_string(),
             token: None,
-        }).is_ok());
+        }).await.is_ok());
+
+        let result = service.validate_user(&RegisterForm{
+            name: "".to_string(),
+            password: "test".to_string(),
+            email: "test".to_string(),
+            token: None,
+        }).await;
+        let Err(RegisterError::Validation(errors)) = result else { panic!("expected validation errors") };
+        let codes: Vec<_> = errors.errors.iter().map(|e| (e.field.as_str(), e.error.code())).collect();
+        assert_eq!(vec![("name", "empty"), ("email", "regex"), ("password", "size")], codes);
     }
 
     fn get_service() -> AuthService {
         let config = Config::default();
-        AuthService{
-            repos: Repos::new(&config),
-            config,
+        AuthService::new(config.clone(), Repos::new(&config, &Metrics::new()), Metrics::new(), Arc::new(SystemClock))
+    }
+
+    fn get_mocked_service() -> (AuthService, Arc<MockClock>) {
+        let config = Config::default();
+        let clock = Arc::new(MockClock::new(Utc::now()));
+        let service = AuthService::new(config.clone(), Repos::new(&config, &Metrics::new()), Metrics::new(), clock.clone());
+        (service, clock)
+    }
+
+    fn admin_login() -> LoginForm {
+        LoginForm {
+            email: "admin@example.com".to_string(),
+            password: "admin".to_string()
         }
     }
 
-    #[test]
-    fn test_login() {
+    #[actix_web::test]
+    async fn test_session_timeout() {
+        let (service, clock) = get_mocked_service();
+        let token = service.login(&admin_login()).await.ok().unwrap();
+
+        clock.advance(TimeDelta::days(29));
+        assert!(service.authenticate(&token.value).await.is_ok());
+
+        clock.advance(TimeDelta::days(2));
+        assert!(matches!(service.authenticate(&token.value).await, Err(AuthenticateError::TokenExpired)));
+        assert_eq!(0, service.active_sessions().await);
+    }
+
+    #[actix_web::test]
+    async fn test_login_token_expiry_boundary() {
+        let (service, clock) = get_mocked_service();
+        let token = service.login(&admin_login()).await.ok().unwrap();
+
+        clock.set(token.expiration);
+        assert!(service.authenticate(&token.value).await.is_ok());
+
+        clock.advance(TimeDelta::seconds(1));
+        assert!(matches!(service.authenticate(&token.value).await, Err(AuthenticateError::TokenExpired)));
+    }
+
+    #[actix_web::test]
+    async fn test_register_token_expiry() {
+        let (service, clock) = get_mocked_service();
+
+        clock.advance(TimeDelta::days(11));
+        let result = service.register(&RegisterForm {
+            password: "testtest".to_string(),
+            email: "late@example.com".to_string(),
+            token: Some("token".to_string()),
+            name: "Late".to_string()
+        }).await;
+        assert!(matches!(result, Err(RegisterError::TokenExpired)));
+    }
+
+    #[actix_web::test]
+    #[allow(clippy::bool_assert_comparison)]
+    async fn test_login() {
         let service = get_service();
 
         assert_eq!(true, service.login(&LoginForm {
             email: "admin@example.com".to_string(),
             password: "admin".to_string()
-        }).is_ok());
+        }).await.is_ok());
         assert_eq!(false, service.login(&LoginForm {
             email: "admin@example.com".to_string(),
             password: "admi".to_string()
-        }).is_ok());
+        }).await.is_ok());
         assert_eq!(false, service.login(&LoginForm {
             email: "admi@example.com".to_string(),
             password: "admin".to_string()
-        }).is_ok())
+        }).await.is_ok())
     }
 
-    #[test]
-    fn test_register() {
+    #[actix_web::test]
+    #[allow(clippy::bool_assert_comparison)]
+    async fn test_register() {
         assert_eq!(true, get_service().register(&RegisterForm {
             password: "testtest".to_string(),
             email: "admin2@example.com".to_string(),
             token: Some("token".to_string()),
             name: "Admin".to_string()
-        }).is_ok())
+        }).await.is_ok())
+    }
+
+    #[actix_web::test]
+    async fn test_login_metrics() {
+        let service = get_service();
+
+        let _ = service.login(&LoginForm {
+            email: "admin@example.com".to_string(),
+            password: "admi".to_string()
+        }).await;
+        let _ = service.login(&LoginForm {
+            email: "admin@example.com".to_string(),
+            password: "admin".to_string()
+        }).await;
+
+        assert_eq!(1, service.metrics.logins.with_label_values(&["wrong_password"]).get());
+        assert_eq!(1, service.metrics.logins.with_label_values(&["success"]).get());
+        assert_eq!(1, service.active_sessions().await);
+        assert!(service.metrics.render().contains("sso_logins_total"));
+    }
+
+    fn register_form(email: &str) -> RegisterForm {
+        RegisterForm {
+            password: "testtest".to_string(),
+            email: email.to_string(),
+            token: Some("token".to_string()),
+            name: "Test".to_string()
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_register_token_single_use() {
+        let service = Arc::new(get_service());
+
+        let handles: Vec<_> = (0..20)
+            .map(|i| {
+                let service = service.clone();
+                actix_web::rt::spawn(async move {
+                    service.register(&register_form(&format!("user{}@example.com", i))).await.is_ok()
+                })
+            })
+            .collect();
+
+        let mut registered = 0;
+        for handle in handles {
+            if handle.await.unwrap() {
+                registered += 1;
+            }
+        }
+        assert_eq!(1, registered);
+        assert!(service.repos.register_token_repo.get_by_value("token").await.unwrap().is_exhausted());
+    }
+
+    #[actix_web::test]
+    async fn test_register_rollback() {
+        let service = get_service();
+
+        let result = service.register(&register_form("admin@example.com")).await;
+        assert!(matches!(result, Err(RegisterError::EmailAlreadyExist)));
+
+        // The invitation was not spent by the failed registration
+        assert!(!service.repos.register_token_repo.get_by_value("token").await.unwrap().is_exhausted());
+        assert!(service.register(&register_form("new@example.com")).await.is_ok());
+        assert!(matches!(
+            service.register(&register_form("other@example.com")).await,
+            Err(RegisterError::TokenUsedUp)
+        ));
+    }
+
+    #[actix_web::test]
+    async fn test_team_invite() {
+        let service = get_service();
+        let admin = service.repos.user_repo.get_by_email("admin@example.com").await.unwrap();
+        service.repos.application_repo.add(Application {
+            name: "Wiki".to_string(),
+            url: "https://wiki.example.com".to_string(),
+            icon_url: None,
+            client_id: "wiki".to_string(),
+            client_secret: "secret".to_string(),
+            protocol: Protocol::Oidc,
+            scopes: HashSet::new(),
+            users: HashSet::new(),
+            pre_consented: false,
+        }).await;
+
+        let invite = service.create_invite(&admin, NewInvite {
+            days_valid: 7,
+            max_uses: 2,
+            restriction: Some(InviteRestriction::Domain("team.com".to_string())),
+            groups: HashSet::from(["team".to_string()]),
+            applications: HashSet::from(["wiki".to_string()]),
+        }).await.ok().unwrap();
+        let form = |email: &str| RegisterForm {
+            token: Some(invite.value.clone()),
+            ..register_form(email)
+        };
+
+        assert!(matches!(service.register(&form("a@other.com")).await, Err(RegisterError::TokenNotAllowed)));
+        assert!(service.register(&form("a@team.com")).await.is_ok());
+        assert!(service.register(&form("b@team.com")).await.is_ok());
+        assert!(matches!(service.register(&form("c@team.com")).await, Err(RegisterError::TokenUsedUp)));
+
+        let a = service.repos.user_repo.get_by_email("a@team.com").await.unwrap();
+        let b = service.repos.user_repo.get_by_email("b@team.com").await.unwrap();
+        assert!(a.groups.contains("team"));
+        let wiki = service.repos.application_repo.get_by_client_id("wiki").await.unwrap();
+        assert!(wiki.users.contains(&a.id) && wiki.users.contains(&b.id));
+
+        let invite = service.repos.register_token_repo.get_by_value(&invite.value).await.unwrap();
+        assert_eq!(Some(admin.id.clone()), invite.created_by);
+        let used_by: Vec<_> = invite.uses.iter().map(|usage| usage.email.as_str()).collect();
+        assert_eq!(vec!["a@team.com", "b@team.com"], used_by);
+
+        let new_invite = |days_valid: u64, application: &str| NewInvite {
+            days_valid,
+            max_uses: 1,
+            restriction: None,
+            groups: HashSet::new(),
+            applications: HashSet::from([application.to_string()]),
+        };
+        assert!(matches!(service.create_invite(&admin, new_invite(0, "wiki")).await, Err(AdminError::InviteValidity)));
+        assert!(matches!(service.create_invite(&admin, new_invite(u64::MAX, "wiki")).await, Err(AdminError::InviteValidity)));
+        assert!(matches!(service.create_invite(&admin, new_invite(7, "unknown")).await, Err(AdminError::ApplicationNotExist)));
     }
-}
\ No newline at end of file
+
+    #[actix_web::test]
+    async fn test_registration_policy() {
+        let mut config = Config::default();
+        config.registration.open_domains = HashSet::from(["corp.com".to_string()]);
+        config.registration.blocked_domains = HashSet::from(["spam.com".to_string()]);
+        config.registration.approval_required = true;
+        let repos = Repos::new(&config, &Metrics::new());
+        let service = AuthService::new(config, repos.clone(), Metrics::new(), Arc::new(SystemClock));
+        let admin_service = AdminService::new(repos, Arc::new(SystemClock));
+        let no_token = |email: &str| RegisterForm { token: None, ..register_form(email) };
+
+        assert!(matches!(service.register(&no_token("a@other.com")).await, Err(RegisterError::TokenRequired)));
+        assert!(matches!(service.register(&register_form("a@spam.com")).await, Err(RegisterError::DomainBlocked)));
+
+        let user = service.register(&no_token("a@corp.com")).await.ok().unwrap();
+        assert_eq!(UserStatus::Pending, user.status);
+        let login = LoginForm { email: "a@corp.com".to_string(), password: "testtest".to_string() };
+        assert!(matches!(service.login(&login).await, Err(LoginError::PendingApproval)));
+
+        let admin = service.repos.user_repo.get_by_email("admin@example.com").await.unwrap();
+        assert!(admin_service.approve_user(&admin, &user.id).await.is_ok());
+        assert!(service.login(&login).await.is_ok());
+
+        // The invitation is not spent on an open domain
+        let user = service.register(&register_form("c@corp.com")).await.ok().unwrap();
+        assert_eq!(UserStatus::Pending, user.status);
+        assert!(service.repos.register_token_repo.get_by_value("token").await.unwrap().uses.is_empty());
+
+        // Invited users do not wait for an approval
+        let user = service.register(&register_form("b@other.com")).await.ok().unwrap();
+        assert_eq!(UserStatus::Active, user.status);
+    }
+
+    #[actix_web::test]
+    async fn test_disabled_account() {
+        let config = Config::default();
+        let repos = Repos::new(&config, &Metrics::new());
+        let service = AuthService::new(config, repos.clone(), Metrics::new(), Arc::new(SystemClock));
+        let admin_service = AdminService::new(repos, Arc::new(SystemClock));
+        let user = service.register(&register_form("a@example.com")).await.ok().unwrap();
+        let login = LoginForm { email: "a@example.com".to_string(), password: "testtest".to_string() };
+        let token = service.login(&login).await.ok().unwrap();
+
+        let admin = service.repos.user_repo.get_by_email("admin@example.com").await.unwrap();
+        assert!(matches!(admin_service.disable_user(&admin, &user.id, " ").await, Err(AdminError::ReasonRequired)));
+        assert!(matches!(admin_service.disable_user(&admin, &admin.id, "test").await, Err(AdminError::OwnAccount)));
+        assert!(admin_service.disable_user(&admin, &user.id, "Contract ended").await.is_ok());
+
+        assert!(matches!(service.authenticate(&token.value).await, Err(AuthenticateError::TokenNotExist)));
+        assert!(matches!(service.login(&login).await, Err(LoginError::AccountDisabled)));
+        let change = service.repos.user_repo.get_by_id(&user.id).await.unwrap().status_change.unwrap();
+        assert_eq!((admin.id.as_str(), "Contract ended"), (change.by.as_str(), change.reason.as_str()));
+
+        assert!(admin_service.enable_user(&admin, &user.id).await.is_ok());
+        assert!(service.login(&login).await.is_ok());
+        assert!(matches!(admin_service.enable_user(&admin, &user.id).await, Err(AdminError::NotDisabled)));
+    }
+
+    #[actix_web::test]
+    async fn test_lockout() {
+        let config = Config { lockout_threshold: 3, ..Config::default() };
+        let service = AuthService::new(config.clone(), Repos::new(&config, &Metrics::new()), Metrics::new(), Arc::new(SystemClock));
+        let wrong = LoginForm { email: "admin@example.com".to_string(), password: "wrong".to_string() };
+        let token = service.login(&admin_login()).await.ok().unwrap();
+
+        // a successful login starts the count again
+        assert!(service.login(&wrong).await.is_err());
+        assert!(service.login(&wrong).await.is_err());
+        assert!(service.login(&admin_login()).await.is_ok());
+
+        for _ in 0..3 {
+            assert!(matches!(service.login(&wrong).await, Err(LoginError::WrongPassword)));
+        }
+        assert!(matches!(service.login(&admin_login()).await, Err(LoginError::AccountLocked)));
+        assert!(matches!(service.authenticate(&token.value).await, Err(AuthenticateError::AccountLocked)));
+        // a disabled account stays disabled
+        let mut admin = service.repos.user_repo.get_by_email("admin@example.com").await.unwrap();
+        admin.status = UserStatus::Disabled;
+        admin.failed_logins = 0;
+        service.repos.user_repo.add(admin).await.ok().unwrap();
+        assert!(matches!(service.login(&wrong).await, Err(LoginError::WrongPassword)));
+        let admin = service.repos.user_repo.get_by_email("admin@example.com").await.unwrap();
+        assert!(admin.status == UserStatus::Disabled && admin.failed_logins == 0);
+    }
+
+    #[actix_web::test]
+    async fn test_login_rate_limit() {
+        let config = Config { login_rate_limit: 2, ..Config::default() };
+        let clock = Arc::new(MockClock::new(Utc::now()));
+        let service = AuthService::new(config.clone(), Repos::new(&config, &Metrics::new()), Metrics::new(), clock.clone());
+        let wrong = LoginForm { email: "Admin@example.com".to_string(), password: "wrong".to_string() };
+
+        assert!(service.login(&wrong).await.is_err());
+        assert!(service.login(&admin_login()).await.is_ok());
+        assert!(matches!(service.login(&admin_login()).await, Err(LoginError::TooManyAttempts)));
+        // other emails are counted apart
+        let other = LoginForm { email: "other@example.com".to_string(), ..admin_login() };
+        assert!(matches!(service.login(&other).await, Err(LoginError::EmailNotExist)));
+
+        clock.advance(TimeDelta::minutes(1));
+        assert!(service.login(&admin_login()).await.is_ok());
+    }
+
+    /// Knows a single user, whose groups can be changed between logins, and the email of the local admin
+    struct TestProvider {
+        groups: std::sync::Mutex<HashSet<String>>,
+        down: std::sync::atomic::AtomicBool,
+    }
+
+    #[async_trait::async_trait]
+    impl AuthProvider for TestProvider {
+        fn name(&self) -> &'static str {
+            "test"
+        }
+
+        async fn authenticate(&self, email: &str, password: &str) -> Result<Option<ExternalIdentity>, ProviderError> {
+            if self.down.load(std::sync::atomic::Ordering::Relaxed) {
+                return Err(ProviderError::Unavailable("down".to_string()));
+            }
+            if ["ann@corp.com", "admin@example.com"].contains(&email) && password == "directory" {
+                return Ok(Some(ExternalIdentity {
+                    name: "Ann".to_string(),
+                    email: email.to_string(),
+                    groups: self.groups.lock().unwrap().clone(),
+                }));
+            }
+            Ok(None)
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_provider_login() {
+        let mut service = get_service();
+        let provider = Arc::new(TestProvider {
+            groups: std::sync::Mutex::new(HashSet::from(["dev".to_string()])),
+            down: std::sync::atomic::AtomicBool::new(false),
+        });
+        service.add_provider(provider.clone());
+        let login = |password: &str| LoginForm { email: "ann@corp.com".to_string(), password: password.to_string() };
+
+        let token = service.login(&login("directory")).await.ok().unwrap();
+        let user = service.authenticate(&token.value).await.ok().unwrap();
+        assert_eq!(("Ann", Some("test")), (user.name.as_str(), user.provider.as_deref()));
+        assert!(user.groups.contains("dev"));
+
+        *provider.groups.lock().unwrap() = HashSet::from(["ops".to_string()]);
+        assert!(service.login(&login("directory")).await.is_ok());
+        let user = service.repos.user_repo.get_by_id(&user.id).await.unwrap();
+        assert_eq!(HashSet::from(["ops".to_string()]), user.groups);
+        assert!(service.repos.group_repo.get_by_name("ops").await.is_some());
+
+        // the local password of a provisioned user is never used
+        assert!(matches!(service.login(&login(&user.password)).await, Err(LoginError::WrongPassword)));
+        // local accounts still work
+        assert!(service.login(&admin_login()).await.is_ok());
+
+        // and are not taken over by the provider
+        let directory = LoginForm { email: "admin@example.com".to_string(), password: "directory".to_string() };
+        assert!(matches!(service.login(&directory).await, Err(LoginError::WrongPassword)));
+        let admin = service.repos.user_repo.get_by_email("admin@example.com").await.unwrap();
+        assert!(admin.provider.is_none() && admin.name != "Ann");
+
+        // an unreachable provider is not a wrong password
+        let failed_logins = service.repos.user_repo.get_by_id(&user.id).await.unwrap().failed_logins;
+        provider.down.store(true, std::sync::atomic::Ordering::Relaxed);
+        assert!(matches!(service.login(&login("directory")).await, Err(LoginError::Unavailable)));
+        assert_eq!(failed_logins, service.repos.user_repo.get_by_id(&user.id).await.unwrap().failed_logins);
+        assert!(service.login(&admin_login()).await.is_ok());
+    }
+}
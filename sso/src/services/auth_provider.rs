@@ -0,0 +1,22 @@
+use std::collections::HashSet;
+use async_trait::async_trait;
+use crate::errors::provider::ProviderError;
+
+/// A user as described by an external directory
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExternalIdentity {
+    pub name: String,
+    pub email: String,
+    pub groups: HashSet<String>,
+}
+
+/// Checks credentials against a directory other than the user repository,
+/// the users it knows are provisioned on their first login
+#[async_trait]
+pub trait AuthProvider: Send + Sync {
+    /// Stored on the provisioned users
+    fn name(&self) -> &'static str;
+    /// The identity of the user if the credentials are valid, `None` if the provider
+    /// does not know the user or the password is wrong
+    async fn authenticate(&self, email: &str, password: &str) -> Result<Option<ExternalIdentity>, ProviderError>;
+}
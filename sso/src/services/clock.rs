@@ -0,0 +1,48 @@
+#[cfg(test)]
+use std::sync::Mutex;
+use chrono::{DateTime, Utc};
+#[cfg(test)]
+use chrono::TimeDelta;
+
+/// Source of the current time, injected so that expiry can be tested without sleeping
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Clock frozen at a given time, only moving when told to
+#[cfg(test)]
+pub struct MockClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+#[cfg(test)]
+impl MockClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            now: Mutex::new(now),
+        }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, delta: TimeDelta) {
+        *self.now.lock().unwrap() += delta;
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
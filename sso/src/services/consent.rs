@@ -0,0 +1,137 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use crate::objects::application::Application;
+use crate::objects::consent::Consent;
+use crate::objects::user::User;
+use crate::services::clock::Clock;
+use crate::services::factory::Repos;
+
+/// What an application learns about the user with a scope, `None` for its own scopes
+pub fn describe_scope(scope: &str) -> Option<&'static str> {
+    match scope {
+        "openid" => Some("Your account identifier"),
+        "profile" => Some("Your name, avatar, timezone and locale"),
+        "email" => Some("Your email address"),
+        "offline_access" => Some("Access while you are not using the application"),
+        _ => None,
+    }
+}
+
+/// The scopes users agreed to share with each application
+#[derive(Clone)]
+pub struct ConsentService {
+    repos: Repos,
+    clock: Arc<dyn Clock>,
+}
+
+impl ConsentService {
+    pub fn new(repos: Repos, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            repos,
+            clock,
+        }
+    }
+
+    /// The user already agreed to share these scopes, or the application is pre-consented
+    pub async fn is_granted(&self, user: &User, application: &Application, scopes: &BTreeSet<String>) -> bool {
+        if application.pre_consented {
+            return true;
+        }
+        self.repos.consent_repo.get(&user.id, &application.client_id).await
+            .is_some_and(|consent| scopes.is_subset(&consent.scopes))
+    }
+
+    /// Remember the scopes, with the ones the user already agreed to
+    pub async fn grant(&self, user: &User, application: &Application, scopes: &BTreeSet<String>) {
+        let previous = self.repos.consent_repo.get(&user.id, &application.client_id).await;
+        let mut consent = previous.unwrap_or_else(|| Consent {
+            user: user.id.clone(),
+            client_id: application.client_id.clone(),
+            scopes: BTreeSet::new(),
+            granted: self.clock.now(),
+        });
+        consent.scopes.extend(scopes.iter().cloned());
+        consent.granted = self.clock.now();
+
+        tracing::info!(client_id = %application.client_id, scopes = ?consent.scopes, "consent granted");
+        self.repos.consent_repo.add(consent).await;
+    }
+
+    /// The applications the user consented to, by name
+    pub async fn list(&self, user: &User) -> Vec<(Application, Consent)> {
+        let consents = self.repos.consent_repo.get_for_user(&user.id).await;
+        let mut granted = Vec::new();
+        for consent in consents {
+            if let Some(application) = self.repos.application_repo.get_by_client_id(&consent.client_id).await {
+                granted.push((application, consent));
+            }
+        }
+        granted.sort_by(|(a, _), (b, _)| a.name.cmp(&b.name));
+        granted
+    }
+
+    /// Forget the consent and end the refresh tokens of the application, the user
+    /// is asked again on the next login. Returns false if there was no consent
+    pub async fn revoke(&self, user: &User, client_id: &str) -> bool {
+        let revoked = self.repos.consent_repo.delete(&user.id, client_id).await;
+        if revoked {
+            let tokens = self.repos.refresh_token_repo.delete_for_client(client_id, &user.id).await;
+            tracing::info!(client_id, refresh_tokens = tokens, "consent revoked");
+        }
+        revoked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use chrono::Utc;
+    use super::*;
+    use crate::objects::application::Protocol;
+    use crate::objects::config::Config;
+    use crate::services::clock::MockClock;
+    use crate::services::metrics::Metrics;
+
+    fn scopes(scopes: &[&str]) -> BTreeSet<String> {
+        scopes.iter().map(|scope| scope.to_string()).collect()
+    }
+
+    #[actix_web::test]
+    async fn test_consent() {
+        let repos = Repos::new(&Config::default(), &Metrics::new());
+        let service = ConsentService::new(repos.clone(), Arc::new(MockClock::new(Utc::now())));
+        let user = repos.user_repo.get_by_email("admin@example.com").await.unwrap();
+        let mut wiki = Application {
+            name: "Wiki".to_string(),
+            url: "https://wiki.example.com".to_string(),
+            icon_url: None,
+            client_id: "wiki".to_string(),
+            client_secret: "secret".to_string(),
+            protocol: Protocol::Oidc,
+            scopes: HashSet::new(),
+            users: HashSet::from([user.id.clone()]),
+            pre_consented: false,
+        };
+        repos.application_repo.add(wiki.clone()).await;
+
+        assert!(!service.is_granted(&user, &wiki, &scopes(&["openid"])).await);
+        service.grant(&user, &wiki, &scopes(&["openid", "email"])).await;
+        assert!(service.is_granted(&user, &wiki, &scopes(&["openid"])).await);
+        // Asking for more is asked again, and remembered with the previous scopes
+        assert!(!service.is_granted(&user, &wiki, &scopes(&["openid", "profile"])).await);
+        service.grant(&user, &wiki, &scopes(&["profile"])).await;
+        assert!(service.is_granted(&user, &wiki, &scopes(&["openid", "email", "profile"])).await);
+
+        let granted = service.list(&user).await;
+        assert_eq!(1, granted.len());
+        assert_eq!(scopes(&["email", "openid", "profile"]), granted[0].1.scopes);
+
+        assert!(service.revoke(&user, "wiki").await);
+        assert!(!service.revoke(&user, "wiki").await);
+        assert!(service.list(&user).await.is_empty());
+        assert!(!service.is_granted(&user, &wiki, &scopes(&["openid"])).await);
+
+        wiki.pre_consented = true;
+        assert!(service.is_granted(&user, &wiki, &scopes(&["openid", "offline_access"])).await);
+    }
+}
@@ -1,36 +1,177 @@
 use std::sync::Arc;
-use crate::objects::config::{Config, RepoType};
+use tokio::sync::Mutex;
+use crate::forms::application::read_applications;
+use crate::objects::config::{Config, RepoType, SessionStoreType};
+use crate::repos::audit::{AuditRepo, AuditRepoMemory};
+use crate::repos::authorization_codes::{AuthorizationCodeRepo, AuthorizationCodeRepoMemory};
 use crate::repos::applications::{ApplicationRepo, ApplicationRepoMemory};
+use crate::repos::consents::{ConsentRepo, ConsentRepoMemory};
+use crate::repos::email_changes::{EmailChangeRepo, EmailChangeRepoMemory};
+use crate::repos::favorites::{FavoriteRepo, FavoriteRepoMemory};
+use crate::repos::federation_states::{FederationStateRepo, FederationStateRepoMemory};
+use crate::repos::groups::{GroupRepo, GroupRepoMemory};
 use crate::repos::login_tokens::{LoginTokenRepo, LoginTokenRepoMemory};
+use crate::repos::login_tokens_redis::LoginTokenRepoRedis;
+use crate::repos::redis_connection::RedisConnection;
+use crate::repos::refresh_tokens::{RefreshTokenRepo, RefreshTokenRepoMemory};
 use crate::repos::register_tokens::{RegisterTokenRepo, RegisterTokenRepoMemory};
+use crate::repos::revoked_tokens::{RevokedTokenRepo, RevokedTokenRepoMemory};
+use crate::repos::throttles::{ThrottleRepo, ThrottleRepoMemory};
+use crate::repos::throttles_redis::ThrottleRepoRedis;
+use crate::repos::timed::Timed;
+use crate::repos::unit_of_work::{MemoryRepos, UnitOfWork, UnitOfWorkMemory};
 use crate::repos::users::{UserRepo, UserRepoMemory};
+use crate::errors::repo::RepoError;
+use crate::services::account::AccountService;
+use crate::services::admin::AdminService;
 use crate::services::auth::AuthService;
+use crate::services::clock::Clock;
+use crate::services::consent::ConsentService;
+use crate::services::federation::FederationService;
+use crate::services::health::HealthService;
+use crate::services::launcher::LauncherService;
+use crate::services::mailer::LogMailer;
+use crate::services::maintenance::MaintenanceService;
+use crate::services::metrics::Metrics;
+use crate::services::migration::MigrationService;
+use crate::services::oauth::OAuthService;
+use crate::services::saml::SamlService;
+use crate::services::scim::ScimService;
 
+#[derive(Clone)]
 pub struct Repos {
     pub user_repo: Arc<dyn UserRepo>,
     pub login_token_repo: Arc<dyn LoginTokenRepo>,
     pub register_token_repo: Arc<dyn RegisterTokenRepo>,
     pub application_repo: Arc<dyn ApplicationRepo>,
+    pub email_change_repo: Arc<dyn EmailChangeRepo>,
+    pub audit_repo: Arc<dyn AuditRepo>,
+    pub federation_state_repo: Arc<dyn FederationStateRepo>,
+    pub group_repo: Arc<dyn GroupRepo>,
+    pub revoked_token_repo: Arc<dyn RevokedTokenRepo>,
+    pub authorization_code_repo: Arc<dyn AuthorizationCodeRepo>,
+    pub refresh_token_repo: Arc<dyn RefreshTokenRepo>,
+    pub consent_repo: Arc<dyn ConsentRepo>,
+    pub favorite_repo: Arc<dyn FavoriteRepo>,
+    pub throttle_repo: Arc<dyn ThrottleRepo>,
+    transaction_lock: Arc<Mutex<()>>,
 }
 
 pub struct Services {
-    pub auth: AuthService
+    pub account: AccountService,
+    pub admin: AdminService,
+    pub auth: AuthService,
+    pub consent: ConsentService,
+    pub federation: FederationService,
+    pub health: HealthService,
+    pub launcher: LauncherService,
+    pub maintenance: MaintenanceService,
+    pub migration: MigrationService,
+    pub oauth: OAuthService,
+    pub saml: SamlService,
+    pub scim: ScimService,
+    pub metrics: Metrics,
 }
 
 impl Services {
-    pub fn new (config: &Config) -> Self {
-        let repos = Repos::new(config);
+    pub fn new (config: &Config, metrics: Metrics, clock: Arc<dyn Clock>) -> Self {
+        let repos = Repos::new(config, &metrics);
+
+        let account = AccountService::new(config.clone(), repos.clone(), clock.clone(), Arc::new(LogMailer));
+        let auth = AuthService::new(config.clone(), repos.clone(), metrics.clone(), clock.clone());
+        let consent = ConsentService::new(repos.clone(), clock.clone());
 
         Self {
-            auth: AuthService::new(config.clone(), repos)
+            scim: ScimService::new(config.clone(), repos.clone(), metrics.clone(), clock.clone(), account.clone()),
+            maintenance: MaintenanceService::new(config.clone(), repos.clone(), metrics.clone(), clock.clone(), account.clone()),
+            account,
+            admin: AdminService::new(repos.clone(), clock.clone()),
+            federation: FederationService::new(config.clone(), repos.clone(), metrics.clone(), clock.clone(), auth.clone()),
+            saml: SamlService::new(config.clone(), repos.clone(), metrics.clone(), clock.clone(), auth.clone()),
+            auth,
+            launcher: LauncherService::new(repos.clone()),
+            migration: MigrationService::new(repos.clone(), clock.clone()),
+            oauth: OAuthService::new(config.clone(), repos.clone(), metrics.clone(), clock, consent.clone()),
+            consent,
+            health: HealthService::new(repos),
+            metrics,
         }
     }
 }
 
 impl Repos {
-    pub fn new(config: &Config) -> Self {
-        match config.repo_type {
+    /// The repositories of the configured backend, each one timed in the metrics
+    pub fn new(config: &Config, metrics: &Metrics) -> Self {
+        let mut repos = match config.repo_type {
             RepoType::Memory => Self::new_memory(),
+        };
+
+        if let Some(path) = &config.applications_file {
+            match read_applications(path) {
+                Ok(applications) => repos.application_repo = Arc::new(ApplicationRepoMemory::with(applications)),
+                Err(e) => panic!("Invalid applications file: {}", e),
+            }
+        }
+
+        if let SessionStoreType::Redis(url) = &config.session_store {
+            let connection = match RedisConnection::new(url) {
+                Ok(connection) => connection,
+                Err(e) => panic!("Invalid redis url: {}", e),
+            };
+            repos.login_token_repo = Arc::new(LoginTokenRepoRedis::new(connection.clone()));
+            repos.throttle_repo = Arc::new(ThrottleRepoRedis::new(connection));
+        }
+        repos.timed(metrics)
+    }
+
+    /// Start a unit of work, waiting for the running one to finish
+    pub async fn unit_of_work(&self) -> Box<dyn UnitOfWork> {
+        let guard = self.transaction_lock.clone().lock_owned().await;
+        let repos = MemoryRepos {
+            user_repo: self.user_repo.clone(),
+            register_token_repo: self.register_token_repo.clone(),
+            application_repo: self.application_repo.clone(),
+        };
+        Box::new(UnitOfWorkMemory::new(repos, guard))
+    }
+
+    /// Probe every repository of the configured backend
+    pub async fn check(&self) -> Vec<(&'static str, Result<(), RepoError>)> {
+        vec![
+            ("users", self.user_repo.ping().await),
+            ("login_tokens", self.login_token_repo.ping().await),
+            ("register_tokens", self.register_token_repo.ping().await),
+            ("applications", self.application_repo.ping().await),
+            ("email_changes", self.email_change_repo.ping().await),
+            ("audit", self.audit_repo.ping().await),
+            ("federation_states", self.federation_state_repo.ping().await),
+            ("groups", self.group_repo.ping().await),
+            ("revoked_tokens", self.revoked_token_repo.ping().await),
+            ("authorization_codes", self.authorization_code_repo.ping().await),
+            ("refresh_tokens", self.refresh_token_repo.ping().await),
+            ("consents", self.consent_repo.ping().await),
+            ("favorites", self.favorite_repo.ping().await),
+            ("throttles", self.throttle_repo.ping().await),
+        ]
+    }
+
+    fn timed(self, metrics: &Metrics) -> Self {
+        Self {
+            user_repo: Timed::new("users", self.user_repo, metrics),
+            login_token_repo: Timed::new("login_tokens", self.login_token_repo, metrics),
+            register_token_repo: Timed::new("register_tokens", self.register_token_repo, metrics),
+            application_repo: Timed::new("applications", self.application_repo, metrics),
+            email_change_repo: Timed::new("email_changes", self.email_change_repo, metrics),
+            audit_repo: Timed::new("audit", self.audit_repo, metrics),
+            federation_state_repo: Timed::new("federation_states", self.federation_state_repo, metrics),
+            group_repo: Timed::new("groups", self.group_repo, metrics),
+            revoked_token_repo: Timed::new("revoked_tokens", self.revoked_token_repo, metrics),
+            authorization_code_repo: Timed::new("authorization_codes", self.authorization_code_repo, metrics),
+            refresh_token_repo: Timed::new("refresh_tokens", self.refresh_token_repo, metrics),
+            consent_repo: Timed::new("consents", self.consent_repo, metrics),
+            favorite_repo: Timed::new("favorites", self.favorite_repo, metrics),
+            throttle_repo: Timed::new("throttles", self.throttle_repo, metrics),
+            transaction_lock: self.transaction_lock,
         }
     }
 
@@ -40,6 +181,17 @@ impl Repos {
             register_token_repo: Arc::new(RegisterTokenRepoMemory::new()),
             user_repo: Arc::new(UserRepoMemory::new()),
             application_repo: Arc::new(ApplicationRepoMemory::new()),
+            email_change_repo: Arc::new(EmailChangeRepoMemory::new()),
+            audit_repo: Arc::new(AuditRepoMemory::new()),
+            federation_state_repo: Arc::new(FederationStateRepoMemory::new()),
+            group_repo: Arc::new(GroupRepoMemory::new()),
+            revoked_token_repo: Arc::new(RevokedTokenRepoMemory::new()),
+            authorization_code_repo: Arc::new(AuthorizationCodeRepoMemory::new()),
+            refresh_token_repo: Arc::new(RefreshTokenRepoMemory::new()),
+            consent_repo: Arc::new(ConsentRepoMemory::new()),
+            favorite_repo: Arc::new(FavoriteRepoMemory::new()),
+            throttle_repo: Arc::new(ThrottleRepoMemory::new()),
+            transaction_lock: Arc::new(Mutex::new(())),
         }
     }
 }
\ No newline at end of file
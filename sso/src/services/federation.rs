@@ -0,0 +1,371 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::TimeDelta;
+use rand::distr::Alphanumeric;
+use rand::Rng;
+use reqwest::Url;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use crate::errors::federation::FederationError;
+use crate::errors::repo::RepoError;
+use crate::objects::config::{Config, UpstreamProvider};
+use crate::objects::federation_state::FederationState;
+use crate::objects::login_token::LoginToken;
+use crate::objects::user::{User, UserStatus};
+use crate::services::auth::AuthService;
+use crate::services::clock::Clock;
+use crate::services::factory::Repos;
+use crate::services::metrics::Metrics;
+
+/// Time to connect to the provider, and to get its whole answer
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+/// The claims read from the userinfo endpoint of the provider
+#[derive(Deserialize)]
+struct UpstreamUser {
+    sub: String,
+    email: Option<String>,
+    #[serde(default)]
+    email_verified: bool,
+    name: Option<String>,
+}
+
+/// Log in with an upstream OIDC provider, using the authorization code flow with PKCE
+pub struct FederationService {
+    config: Config,
+    repos: Repos,
+    metrics: Metrics,
+    clock: Arc<dyn Clock>,
+    auth: AuthService,
+    http: reqwest::Client,
+}
+
+impl FederationService {
+    pub fn new(config: Config, repos: Repos, metrics: Metrics, clock: Arc<dyn Clock>, auth: AuthService) -> Self {
+        Self {
+            config,
+            repos,
+            metrics,
+            clock,
+            auth,
+            // a slow provider must not hold the login request forever
+            http: reqwest::Client::builder()
+                .connect_timeout(CONNECT_TIMEOUT)
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .expect("Could not create the HTTP client"),
+        }
+    }
+
+    pub fn providers(&self) -> &[UpstreamProvider] {
+        &self.config.upstream_providers
+    }
+
+    fn provider(&self, id: &str) -> Result<&UpstreamProvider, FederationError> {
+        self.providers().iter()
+            .find(|provider| provider.id == id)
+            .ok_or(FederationError::UnknownProvider)
+    }
+
+    fn redirect_uri(&self, provider: &UpstreamProvider) -> String {
+        format!("{}/auth/oidc/{}/callback", self.config.public_url, provider.id)
+    }
+
+    /// Start a login, returning the state to bind to the browser and the url to send it to
+    pub async fn start(&self, provider_id: &str) -> Result<(String, String), FederationError> {
+        let provider = self.provider(provider_id)?;
+        let state = FederationState {
+            value: AuthService::generate_value(),
+            provider: provider.id.clone(),
+            verifier: rand::rng().sample_iter(&Alphanumeric).take(64).map(char::from).collect(),
+            expiration: self.clock.now() + TimeDelta::minutes(10),
+        };
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(state.verifier.as_bytes()));
+
+        let url = Url::parse_with_params(&provider.authorization_url, &[
+            ("response_type", "code"),
+            ("client_id", provider.client_id.as_str()),
+            ("redirect_uri", self.redirect_uri(provider).as_str()),
+            ("scope", provider.scopes.as_str()),
+            ("state", state.value.as_str()),
+            ("code_challenge", challenge.as_str()),
+            ("code_challenge_method", "S256"),
+        ]).map_err(|e| FederationError::Provider(e.to_string()))?;
+
+        let value = state.value.clone();
+        self.repos.federation_state_repo.add(state).await;
+        Ok((value, url.to_string()))
+    }
+
+    /// Handle the return of the user from the provider. `browser_state` is the state
+    /// remembered by the browser that started the login
+    pub async fn finish(&self, provider_id: &str, code: &str, state: &str, browser_state: Option<&str>) -> Result<LoginToken, FederationError> {
+        let result = self.try_finish(provider_id, code, state, browser_state).await;
+
+        let outcome = match &result {
+            Ok(_) => "success",
+            Err(e) => {
+                tracing::warn!(provider = provider_id, error = %e, "federated login failed");
+                e.kind()
+            }
+        };
+        self.metrics.logins.with_label_values(&[outcome]).inc();
+        result
+    }
+
+    async fn try_finish(&self, provider_id: &str, code: &str, state: &str, browser_state: Option<&str>) -> Result<LoginToken, FederationError> {
+        let provider = self.provider(provider_id)?;
+        // the state is consumed even if the browser does not match, it is only valid once
+        let saved = self.repos.federation_state_repo.take(state).await.ok_or(FederationError::InvalidState)?;
+        if saved.provider != provider.id || saved.expiration < self.clock.now() || browser_state != Some(state) {
+            return Err(FederationError::InvalidState);
+        }
+
+        let upstream = self.fetch_user(provider, code, &saved.verifier).await?;
+        let user = self.resolve_user(provider, upstream).await?;
+        self.auth.open_session(user).await.map_err(FederationError::Login)
+    }
+
+    async fn fetch_user(&self, provider: &UpstreamProvider, code: &str, verifier: &str) -> Result<UpstreamUser, FederationError> {
+        let failed = |e: reqwest::Error| FederationError::Provider(e.to_string());
+
+        let redirect_uri = self.redirect_uri(provider);
+        let token: TokenResponse = self.http.post(&provider.token_url)
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", redirect_uri.as_str()),
+                ("client_id", provider.client_id.as_str()),
+                ("client_secret", provider.client_secret.as_str()),
+                ("code_verifier", verifier),
+            ])
+            .send().await.map_err(failed)?
+            .error_for_status().map_err(failed)?
+            .json().await.map_err(failed)?;
+
+        self.http.get(&provider.userinfo_url)
+            .bearer_auth(&token.access_token)
+            .send().await.map_err(failed)?
+            .error_for_status().map_err(failed)?
+            .json().await.map_err(failed)
+    }
+
+    /// The user already linked to the upstream account, else the local user with the same verified
+    /// email which gets linked, else a new user if the registration policy allows it
+    async fn resolve_user(&self, provider: &UpstreamProvider, upstream: UpstreamUser) -> Result<User, FederationError> {
+        let linked = self.repos.user_repo.get_by_federated(&provider.id, &upstream.sub).await;
+        if let Some(user) = linked {
+            return Ok(user);
+        }
+
+        let email = upstream.email.ok_or(FederationError::EmailMissing)?;
+        if !upstream.email_verified {
+            return Err(FederationError::EmailNotVerified);
+        }
+
+        let existing = self.repos.user_repo.get_by_email(&email).await;
+        if let Some(mut user) = existing {
+            if user.admin || user.provider.is_some() {
+                tracing::warn!(provider = %provider.id, email = %user.email, "account linking refused");
+                return Err(FederationError::LinkRefused);
+            }
+            user.federated.insert(provider.id.clone(), upstream.sub);
+            self.repos.user_repo.add(user.clone()).await.map_err(save_error)?;
+            tracing::info!(provider = %provider.id, email = %user.email, "account linked");
+            return Ok(user);
+        }
+
+        let policy = &self.config.registration;
+        let domain = email.rsplit_once('@').map(|(_, domain)| domain).unwrap_or_default();
+        if policy.is_blocked(domain) {
+            return Err(FederationError::DomainBlocked);
+        }
+        if !policy.is_open(domain) {
+            return Err(FederationError::RegistrationClosed);
+        }
+
+        let user = User {
+            id: User::new_id(),
+            name: upstream.name.unwrap_or_else(|| email.clone()),
+            email,
+            // never checked, the user logs in with the upstream provider
+            password: AuthService::generate_value(),
+            admin: false,
+            groups: HashSet::new(),
+            status: match policy.approval_required {
+                true => UserStatus::Pending,
+                false => UserStatus::Active,
+            },
+            status_change: None,
+            failed_logins: 0,
+            provider: Some(provider.id.clone()),
+            federated: HashMap::from([(provider.id.clone(), upstream.sub)]),
+            avatar_url: None,
+            timezone: "UTC".to_string(),
+            locale: "en".to_string(),
+            deletion_scheduled: None,
+            created: self.clock.now(),
+        };
+
+        let mut uow = self.repos.unit_of_work().await;
+        if let Err(e) = uow.add_user(user.clone()).await {
+            uow.rollback().await;
+            return Err(save_error(e));
+        }
+        uow.commit().await.map_err(save_error)?;
+
+        tracing::info!(provider = %provider.id, email = %user.email, "user created from upstream provider");
+        self.metrics.registrations.with_label_values(&["success"]).inc();
+        Ok(user)
+    }
+}
+
+/// A failure to save the user is ours, not the upstream provider's
+fn save_error(e: RepoError) -> FederationError {
+    match e {
+        RepoError::Conflict(_) => FederationError::Conflict,
+        _ => FederationError::Unavailable,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+    use chrono::Utc;
+    use serde_json::{json, Value};
+    use super::*;
+    use crate::errors::auth::LoginError;
+    use crate::services::clock::SystemClock;
+
+    /// Answers like an OIDC provider, the code sent to the token endpoint
+    /// is the key of the userinfo claims to return
+    async fn mock_provider(users: HashMap<String, Value>) -> String {
+        let users = web::Data::new(users);
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(users.clone())
+                .route("/token", web::post().to(|form: web::Form<HashMap<String, String>>| async move {
+                    match form.get("code_verifier") {
+                        Some(verifier) if verifier.len() >= 43 => HttpResponse::Ok()
+                            .json(json!({ "access_token": form["code"], "token_type": "Bearer" })),
+                        _ => HttpResponse::BadRequest().finish(),
+                    }
+                }))
+                .route("/userinfo", web::get().to(|req: HttpRequest, users: web::Data<HashMap<String, Value>>| async move {
+                    let token = req.headers().get("authorization")
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(|value| value.strip_prefix("Bearer "))
+                        .unwrap_or_default();
+                    match users.get(token) {
+                        Some(user) => HttpResponse::Ok().json(user),
+                        None => HttpResponse::Unauthorized().finish(),
+                    }
+                }))
+        })
+            .workers(1)
+            .disable_signals()
+            .bind(("127.0.0.1", 0))
+            .unwrap();
+        let url = format!("http://{}", server.addrs()[0]);
+        actix_web::rt::spawn(server.run());
+        url
+    }
+
+    async fn get_service(config: Config) -> FederationService {
+        let users = HashMap::from([
+            ("admin".to_string(), json!({ "sub": "kc-1", "email": "admin@example.com", "email_verified": true })),
+            ("local".to_string(), json!({ "sub": "kc-4", "email": "local@example.com", "email_verified": true })),
+            ("renamed".to_string(), json!({ "sub": "kc-4", "email": "other@example.com", "email_verified": true })),
+            ("directory".to_string(), json!({ "sub": "kc-5", "email": "ldap@example.com", "email_verified": true })),
+            ("unverified".to_string(), json!({ "sub": "kc-2", "email": "new@corp.com" })),
+            ("new".to_string(), json!({ "sub": "kc-3", "email": "new@corp.com", "email_verified": true, "name": "New" })),
+        ]);
+        let url = mock_provider(users).await;
+
+        let config = Config {
+            upstream_providers: vec![UpstreamProvider {
+                id: "keycloak".to_string(),
+                name: "Keycloak".to_string(),
+                authorization_url: format!("{}/authorize", url),
+                token_url: format!("{}/token", url),
+                userinfo_url: format!("{}/userinfo", url),
+                client_id: "sso".to_string(),
+                client_secret: "secret".to_string(),
+                scopes: "openid email".to_string(),
+            }],
+            ..config
+        };
+        let repos = Repos::new(&config, &Metrics::new());
+        let clock = Arc::new(SystemClock);
+        let auth = AuthService::new(config.clone(), repos.clone(), Metrics::new(), clock.clone());
+        FederationService::new(config, repos, Metrics::new(), clock, auth)
+    }
+
+    async fn login(service: &FederationService, code: &str) -> Result<LoginToken, FederationError> {
+        let (state, url) = service.start("keycloak").await.ok().unwrap();
+        assert!(url.contains("code_challenge_method=S256"));
+        service.finish("keycloak", code, &state, Some(&state)).await
+    }
+
+    #[actix_web::test]
+    async fn test_state_is_bound_to_the_browser() {
+        let service = get_service(Config::default()).await;
+        assert!(matches!(service.start("unknown").await, Err(FederationError::UnknownProvider)));
+
+        let (state, _) = service.start("keycloak").await.ok().unwrap();
+        assert!(matches!(service.finish("keycloak", "admin", &state, Some("other")).await, Err(FederationError::InvalidState)));
+        // a state is only valid once
+        assert!(matches!(service.finish("keycloak", "admin", &state, Some(&state)).await, Err(FederationError::InvalidState)));
+    }
+
+    #[actix_web::test]
+    async fn test_account_linking() {
+        let service = get_service(Config::default()).await;
+        let admin = service.repos.user_repo.get_by_email("admin@example.com").await.unwrap();
+        let local = User { id: User::new_id(), email: "local@example.com".to_string(), admin: false, ..admin.clone() };
+        let directory = User { id: User::new_id(), email: "ldap@example.com".to_string(), admin: false, provider: Some("directory".to_string()), ..admin.clone() };
+        for user in [&local, &directory] {
+            service.repos.user_repo.add(user.clone()).await.ok().unwrap();
+        }
+
+        let token = login(&service, "local").await.ok().unwrap();
+        assert_eq!(local.id, token.user);
+        assert!(token.expiration > Utc::now());
+
+        // once linked, the upstream email does not matter anymore
+        let token = login(&service, "renamed").await.ok().unwrap();
+        assert_eq!(local.id, token.user);
+
+        assert!(matches!(login(&service, "admin").await, Err(FederationError::LinkRefused)));
+        assert!(matches!(login(&service, "directory").await, Err(FederationError::LinkRefused)));
+        for user in [&admin, &directory] {
+            assert!(service.repos.user_repo.get_by_id(&user.id).await.unwrap().federated.is_empty());
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_jit_creation() {
+        let service = get_service(Config::default()).await;
+        assert!(matches!(login(&service, "unverified").await, Err(FederationError::EmailNotVerified)));
+        assert!(matches!(login(&service, "new").await, Err(FederationError::RegistrationClosed)));
+
+        let mut config = Config::default();
+        config.registration.open_domains = HashSet::from(["corp.com".to_string()]);
+        config.registration.approval_required = true;
+        let service = get_service(config).await;
+        assert!(matches!(login(&service, "new").await, Err(FederationError::Login(LoginError::PendingApproval))));
+
+        let user = service.repos.user_repo.get_by_email("new@corp.com").await.unwrap();
+        assert_eq!(("New", Some("keycloak")), (user.name.as_str(), user.provider.as_deref()));
+        assert_eq!(Some(&"kc-3".to_string()), user.federated.get("keycloak"));
+    }
+}
@@ -0,0 +1,82 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::services::factory::Repos;
+
+pub struct ComponentStatus {
+    pub name: &'static str,
+    pub available: bool,
+}
+
+pub struct Readiness {
+    pub migrating: bool,
+    pub components: Vec<ComponentStatus>,
+}
+
+impl Readiness {
+    pub fn is_ready(&self) -> bool {
+        !self.migrating && self.components.iter().all(|c| c.available)
+    }
+}
+
+pub struct HealthService {
+    repos: Repos,
+    migrating: AtomicBool,
+}
+
+impl HealthService {
+    /// Not ready until the migrations started at boot are done
+    pub fn new(repos: Repos) -> Self {
+        Self {
+            repos,
+            migrating: AtomicBool::new(true),
+        }
+    }
+
+    /// Mark the server as not ready while the repositories are being migrated
+    pub fn set_migrating(&self, migrating: bool) {
+        self.migrating.store(migrating, Ordering::SeqCst);
+    }
+
+    pub async fn readiness(&self) -> Readiness {
+        let components = self.repos.check().await
+            .into_iter()
+            .map(|(name, result)| {
+                // the probe is public, the reason only goes to the logs
+                if let Err(e) = &result {
+                    tracing::warn!(component = name, error = %e, "repository unavailable");
+                }
+                ComponentStatus { name, available: result.is_ok() }
+            })
+            .collect();
+
+        Readiness {
+            migrating: self.migrating.load(Ordering::SeqCst),
+            components,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::objects::config::Config;
+    use crate::services::clock::SystemClock;
+    use crate::services::metrics::Metrics;
+    use crate::services::migration::MigrationService;
+
+    #[actix_web::test]
+    async fn test_readiness() {
+        let repos = Repos::new(&Config::default(), &Metrics::new());
+        let service = HealthService::new(repos.clone());
+        assert!(!service.readiness().await.is_ready());
+
+        MigrationService::new(repos, Arc::new(SystemClock)).run(&service).await;
+        assert!(service.readiness().await.is_ready());
+
+        service.set_migrating(true);
+        assert!(!service.readiness().await.is_ready());
+
+        service.set_migrating(false);
+        assert!(service.readiness().await.is_ready());
+    }
+}
@@ -0,0 +1,125 @@
+use crate::objects::application::{Application, Protocol};
+use crate::objects::user::User;
+use crate::services::factory::Repos;
+
+/// An application on the home page of a user
+pub struct LauncherEntry {
+    pub application: Application,
+    pub favorite: bool,
+    /// Starts the login on the application
+    pub launch_url: String,
+}
+
+/// The applications users can open from the home page
+pub struct LauncherService {
+    repos: Repos,
+}
+
+impl LauncherService {
+    pub fn new(repos: Repos) -> Self {
+        Self {
+            repos,
+        }
+    }
+
+    /// The applications of the user matching `search`, favorites first then by name
+    pub async fn applications(&self, user: &User, search: Option<&str>) -> Vec<LauncherEntry> {
+        let favorites = self.repos.favorite_repo.get_for_user(&user.id).await;
+        let applications = self.repos.application_repo.get_all().await;
+        let search = search.map(str::trim).unwrap_or_default().to_lowercase();
+
+        let mut entries: Vec<LauncherEntry> = applications.into_iter()
+            .filter(|application| application.users.contains(&user.id))
+            .filter(|application| application.name.to_lowercase().contains(&search)
+                || application.url.to_lowercase().contains(&search))
+            .map(|application| LauncherEntry {
+                favorite: favorites.contains(&application.client_id),
+                launch_url: launch_url(&application),
+                application,
+            })
+            .collect();
+        entries.sort_by(|a, b| b.favorite.cmp(&a.favorite)
+            .then_with(|| a.application.name.to_lowercase().cmp(&b.application.name.to_lowercase())));
+        entries
+    }
+
+    /// Pin or unpin an application, returns false if the user can not use it
+    pub async fn set_favorite(&self, user: &User, client_id: &str, favorite: bool) -> bool {
+        let application = self.repos.application_repo.get_by_client_id(client_id).await;
+        if !application.is_some_and(|application| application.users.contains(&user.id)) {
+            return false;
+        }
+        match favorite {
+            true => {
+                self.repos.favorite_repo.add(&user.id, client_id).await
+            }
+            false => {
+                self.repos.favorite_repo.remove(&user.id, client_id).await
+            }
+        }
+        true
+    }
+}
+
+/// SAML applications are logged in from here, OIDC applications start the login themselves
+fn launch_url(application: &Application) -> String {
+    match application.protocol {
+        Protocol::Saml(_) => format!("/saml/init/{}", application.client_id),
+        Protocol::Oidc => application.url.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use super::*;
+    use crate::objects::application::SamlSettings;
+    use crate::objects::config::Config;
+    use crate::services::metrics::Metrics;
+
+    fn application(name: &str, protocol: Protocol, users: HashSet<String>) -> Application {
+        let client_id = name.to_lowercase();
+        Application {
+            name: name.to_string(),
+            url: format!("https://{}.example.com", client_id),
+            icon_url: None,
+            client_id,
+            client_secret: "secret".to_string(),
+            protocol,
+            scopes: HashSet::new(),
+            users,
+            pre_consented: false,
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_launcher() {
+        let repos = Repos::new(&Config::default(), &Metrics::new());
+        let service = LauncherService::new(repos.clone());
+        let user = repos.user_repo.get_by_email("admin@example.com").await.unwrap();
+        let users = HashSet::from([user.id.clone()]);
+        let saml = Protocol::Saml(SamlSettings {
+            entity_id: "https://payroll.example.com/saml".to_string(),
+            acs_url: "https://payroll.example.com/saml/acs".to_string(),
+            slo_url: None,
+            signing_cert: None,
+        });
+        repos.application_repo.add(application("Wiki", Protocol::Oidc, users.clone())).await;
+        repos.application_repo.add(application("Payroll", saml, users.clone())).await;
+        repos.application_repo.add(application("Billing", Protocol::Oidc, users)).await;
+        repos.application_repo.add(application("Admin", Protocol::Oidc, HashSet::new())).await;
+
+        let names = |entries: Vec<LauncherEntry>| entries.into_iter().map(|entry| entry.application.name).collect::<Vec<_>>();
+        let entries = service.applications(&user, None).await;
+        assert_eq!(entries[1].launch_url, "/saml/init/payroll");
+        assert_eq!(entries[2].launch_url, "https://wiki.example.com");
+        assert_eq!(vec!["Billing", "Payroll", "Wiki"], names(entries));
+        assert_eq!(vec!["Wiki"], names(service.applications(&user, Some(" WIK")).await));
+
+        assert!(service.set_favorite(&user, "wiki", true).await);
+        assert!(!service.set_favorite(&user, "admin", true).await);
+        assert_eq!(vec!["Wiki", "Billing", "Payroll"], names(service.applications(&user, None).await));
+        assert!(service.set_favorite(&user, "wiki", false).await);
+        assert_eq!(vec!["Billing", "Payroll", "Wiki"], names(service.applications(&user, None).await));
+    }
+}
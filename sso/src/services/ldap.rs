@@ -0,0 +1,147 @@
+use async_trait::async_trait;
+use ldap3::{ldap_escape, Ldap, LdapConnAsync, LdapConnSettings, LdapError, Scope, SearchEntry};
+use crate::errors::provider::ProviderError;
+use crate::objects::config::LdapConfig;
+use crate::services::auth_provider::{AuthProvider, ExternalIdentity};
+
+/// Result code of a bind with a wrong password
+const INVALID_CREDENTIALS: u32 = 49;
+
+/// Authenticates users with a bind on their directory entry, found by searching
+/// for their email with the service account
+pub struct LdapProvider {
+    config: LdapConfig,
+}
+
+impl LdapProvider {
+    pub fn new(config: LdapConfig) -> Self {
+        Self {
+            config,
+        }
+    }
+
+    async fn connect(&self) -> Result<Ldap, ProviderError> {
+        let settings = LdapConnSettings::new().set_conn_timeout(self.config.timeout);
+        let (conn, ldap) = LdapConnAsync::with_settings(settings, &self.config.url).await
+            .map_err(unavailable)?;
+        ldap3::drive!(conn);
+        Ok(ldap)
+    }
+
+    async fn find(&self, ldap: &mut Ldap, email: &str) -> Result<Option<SearchEntry>, ProviderError> {
+        if let Some(dn) = &self.config.bind_dn {
+            ldap.simple_bind(dn, self.config.bind_password.as_deref().unwrap_or_default()).await
+                .and_then(|result| result.success())
+                .map_err(unavailable)?;
+        }
+
+        let filter = self.config.user_filter.replace("{email}", &ldap_escape(email));
+        let attributes = [
+            self.config.name_attribute.as_str(),
+            self.config.email_attribute.as_str(),
+            self.config.group_attribute.as_str(),
+        ];
+        let (entries, _) = ldap.search(&self.config.base_dn, Scope::Subtree, &filter, attributes.to_vec()).await
+            .and_then(|result| result.success())
+            .map_err(unavailable)?;
+
+        // an ambiguous filter must not log in as the first match
+        match entries.len() {
+            1 => Ok(entries.into_iter().next().map(SearchEntry::construct)),
+            0 => Ok(None),
+            n => Err(ProviderError::InvalidEntry(format!("{} entries match {}", n, email))),
+        }
+    }
+
+    fn identity(&self, entry: &SearchEntry, login: &str) -> Result<ExternalIdentity, ProviderError> {
+        let first = |attribute: &str| entry.attrs.get(attribute).and_then(|values| values.first()).cloned();
+
+        let email = first(&self.config.email_attribute).unwrap_or_else(|| login.to_string());
+        let name = first(&self.config.name_attribute)
+            .ok_or_else(|| ProviderError::InvalidEntry(format!("{} has no {}", entry.dn, self.config.name_attribute)))?;
+        let groups = entry.attrs.get(&self.config.group_attribute)
+            .map(|values| values.iter().map(|value| group_name(value)).collect())
+            .unwrap_or_default();
+
+        Ok(ExternalIdentity {
+            name,
+            email,
+            groups,
+        })
+    }
+}
+
+/// `cn=developers,ou=groups,dc=example,dc=com` is the group `developers`
+fn group_name(value: &str) -> String {
+    let rdn = value.split(',').next().unwrap_or(value);
+    match rdn.split_once('=') {
+        Some((_, name)) => name.trim().to_string(),
+        None => value.trim().to_string(),
+    }
+}
+
+fn unavailable(e: LdapError) -> ProviderError {
+    ProviderError::Unavailable(e.to_string())
+}
+
+#[async_trait]
+impl AuthProvider for LdapProvider {
+    fn name(&self) -> &'static str {
+        "ldap"
+    }
+
+    async fn authenticate(&self, email: &str, password: &str) -> Result<Option<ExternalIdentity>, ProviderError> {
+        // an empty password is an unauthenticated bind, which most servers accept
+        if password.is_empty() {
+            return Ok(None);
+        }
+
+        let mut ldap = self.connect().await?;
+        let entry = match self.find(&mut ldap, email).await? {
+            Some(entry) => entry,
+            None => {
+                let _ = ldap.unbind().await;
+                return Ok(None);
+            }
+        };
+
+        let bind = ldap.simple_bind(&entry.dn, password).await.map_err(unavailable)?;
+        let _ = ldap.unbind().await;
+        match bind.rc {
+            0 => self.identity(&entry, email).map(Some),
+            INVALID_CREDENTIALS => Ok(None),
+            _ => Err(unavailable(LdapError::LdapResult { result: bind })),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_name() {
+        assert_eq!("developers", group_name("cn=developers,ou=groups,dc=example,dc=com"));
+        assert_eq!("admins", group_name("admins"));
+    }
+
+    /// Needs the OpenLDAP container of compose.yml, whose users have no email:
+    /// `docker compose --profile ldap up -d ldap && cargo test -- --ignored`
+    #[actix_web::test]
+    #[ignore]
+    async fn test_ldap_bind() {
+        let provider = LdapProvider::new(LdapConfig {
+            url: "ldap://localhost:1389".to_string(),
+            bind_dn: Some("cn=admin,dc=example,dc=org".to_string()),
+            bind_password: Some("adminpassword".to_string()),
+            base_dn: "ou=users,dc=example,dc=org".to_string(),
+            user_filter: "(uid={email})".to_string(),
+            ..LdapConfig::default()
+        });
+
+        let identity = provider.authenticate("user01", "password1").await.unwrap().unwrap();
+        assert!(!identity.name.is_empty());
+        assert!(provider.authenticate("user01", "wrong").await.unwrap().is_none());
+        assert!(provider.authenticate("nobody", "password1").await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,14 @@
+/// Sends emails to users
+pub trait Mailer: Send + Sync {
+    fn send(&self, to: &str, subject: &str, body: &str);
+}
+
+/// Writes the emails to the logs, used until an SMTP server is configured.
+/// The body is left out since it carries verification links
+pub struct LogMailer;
+
+impl Mailer for LogMailer {
+    fn send(&self, to: &str, subject: &str, _body: &str) {
+        tracing::info!(to, subject, "email sent");
+    }
+}
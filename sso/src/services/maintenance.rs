@@ -0,0 +1,148 @@
+use std::sync::Arc;
+use crate::objects::config::Config;
+use crate::services::account::AccountService;
+use crate::services::clock::Clock;
+use crate::services::factory::Repos;
+use crate::services::metrics::Metrics;
+
+#[derive(Default, Debug, PartialEq)]
+pub struct PurgeReport {
+    pub login_tokens: usize,
+    pub register_tokens: usize,
+    pub email_changes: usize,
+    pub federation_states: usize,
+    pub revoked_tokens: usize,
+    pub authorization_codes: usize,
+    pub refresh_tokens: usize,
+    pub throttles: usize,
+    pub accounts: usize,
+}
+
+/// Periodic cleanup of the repositories
+#[derive(Clone)]
+pub struct MaintenanceService {
+    config: Config,
+    repos: Repos,
+    metrics: Metrics,
+    clock: Arc<dyn Clock>,
+    account: AccountService,
+}
+
+impl MaintenanceService {
+    pub fn new(config: Config, repos: Repos, metrics: Metrics, clock: Arc<dyn Clock>, account: AccountService) -> Self {
+        Self {
+            config,
+            repos,
+            metrics,
+            clock,
+            account,
+        }
+    }
+
+    /// Delete every time-limited artifact that is expired
+    pub async fn purge_expired(&self) -> PurgeReport {
+        let now = self.clock.now();
+
+        let report = PurgeReport {
+            login_tokens: {
+                self.repos.login_token_repo.delete_expired(now).await
+            },
+            register_tokens: {
+                self.repos.register_token_repo.delete_expired(now).await
+            },
+            email_changes: {
+                self.repos.email_change_repo.delete_expired(now).await
+            },
+            federation_states: {
+                self.repos.federation_state_repo.delete_expired(now).await
+            },
+            revoked_tokens: {
+                self.repos.revoked_token_repo.delete_expired(now).await
+            },
+            authorization_codes: {
+                self.repos.authorization_code_repo.delete_expired(now).await
+            },
+            refresh_tokens: {
+                self.repos.refresh_token_repo.delete_expired(now).await
+            },
+            throttles: {
+                self.repos.throttle_repo.delete_expired(now).await
+            },
+            accounts: self.account.delete_scheduled().await,
+        };
+
+        self.metrics.purged.with_label_values(&["login_tokens"]).inc_by(report.login_tokens as u64);
+        self.metrics.purged.with_label_values(&["register_tokens"]).inc_by(report.register_tokens as u64);
+        self.metrics.purged.with_label_values(&["email_changes"]).inc_by(report.email_changes as u64);
+        self.metrics.purged.with_label_values(&["federation_states"]).inc_by(report.federation_states as u64);
+        self.metrics.purged.with_label_values(&["revoked_tokens"]).inc_by(report.revoked_tokens as u64);
+        self.metrics.purged.with_label_values(&["authorization_codes"]).inc_by(report.authorization_codes as u64);
+        self.metrics.purged.with_label_values(&["refresh_tokens"]).inc_by(report.refresh_tokens as u64);
+        self.metrics.purged.with_label_values(&["throttles"]).inc_by(report.throttles as u64);
+        self.metrics.purged.with_label_values(&["accounts"]).inc_by(report.accounts as u64);
+        tracing::info!(
+            login_tokens = report.login_tokens,
+            register_tokens = report.register_tokens,
+            email_changes = report.email_changes,
+            federation_states = report.federation_states,
+            revoked_tokens = report.revoked_tokens,
+            authorization_codes = report.authorization_codes,
+            refresh_tokens = report.refresh_tokens,
+            throttles = report.throttles,
+            accounts = report.accounts,
+            "purged expired tokens and accounts"
+        );
+        report
+    }
+
+    /// Run `purge_expired` every `purge_interval` on the current runtime
+    pub fn spawn(&self) {
+        let service = self.clone();
+
+        actix_web::rt::spawn(async move {
+            let mut interval = actix_web::rt::time::interval(service.config.purge_interval);
+            loop {
+                interval.tick().await;
+                service.purge_expired().await;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Days, TimeDelta, Utc};
+    use crate::objects::login_token::LoginToken;
+    use crate::services::clock::MockClock;
+    use crate::services::mailer::LogMailer;
+
+    #[actix_web::test]
+    async fn test_purge_expired() {
+        let config = Config::default();
+        let repos = Repos::new(&config, &Metrics::new());
+        let clock = Arc::new(MockClock::new(Utc::now()));
+        let account = AccountService::new(config.clone(), repos.clone(), clock.clone(), Arc::new(LogMailer));
+        let service = MaintenanceService::new(config, repos.clone(), Metrics::new(), clock.clone(), account);
+
+        repos.login_token_repo.add(LoginToken {
+            value: "old".to_string(),
+            user: "admin@example.com".to_string(),
+            expiration: Utc::now() - Days::new(1),
+        }).await;
+        repos.login_token_repo.add(LoginToken {
+            value: "new".to_string(),
+            user: "admin@example.com".to_string(),
+            expiration: Utc::now() + Days::new(1),
+        }).await;
+
+        assert_eq!(PurgeReport { login_tokens: 1, register_tokens: 0, email_changes: 0, federation_states: 0, revoked_tokens: 0, authorization_codes: 0, refresh_tokens: 0, throttles: 0, accounts: 0 }, service.purge_expired().await);
+        assert!(repos.login_token_repo.get_by_value("old").await.is_none());
+        assert!(repos.login_token_repo.get_by_value("new").await.is_some());
+        assert_eq!(1, service.metrics.purged.with_label_values(&["login_tokens"]).get());
+
+        // The seeded invitation expires after 10 days
+        clock.advance(TimeDelta::days(11));
+        assert_eq!(PurgeReport { login_tokens: 1, register_tokens: 1, email_changes: 0, federation_states: 0, revoked_tokens: 0, authorization_codes: 0, refresh_tokens: 0, throttles: 0, accounts: 0 }, service.purge_expired().await);
+    }
+}
@@ -0,0 +1,93 @@
+use prometheus::{Encoder, HistogramOpts, HistogramTimer, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder};
+
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    pub logins: IntCounterVec,
+    pub registrations: IntCounterVec,
+    pub authentication_failures: IntCounterVec,
+    pub active_sessions: IntGauge,
+    pub http_requests: HistogramVec,
+    pub repo_operations: HistogramVec,
+    pub purged: IntCounterVec,
+    pub saml_requests: IntCounterVec,
+    pub tokens: IntCounterVec,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new_custom(Some("sso".to_string()), None).unwrap();
+
+        let logins = IntCounterVec::new(
+            Opts::new("logins_total", "Login attempts by outcome"),
+            &["outcome"],
+        ).unwrap();
+        let registrations = IntCounterVec::new(
+            Opts::new("registrations_total", "Registration attempts by outcome"),
+            &["outcome"],
+        ).unwrap();
+        let authentication_failures = IntCounterVec::new(
+            Opts::new("authentication_failures_total", "Rejected session tokens by reason"),
+            &["kind"],
+        ).unwrap();
+        let active_sessions = IntGauge::new("active_sessions", "Login tokens that are not expired").unwrap();
+        let http_requests = HistogramVec::new(
+            HistogramOpts::new("http_request_duration_seconds", "HTTP request latency by route"),
+            &["method", "route", "status"],
+        ).unwrap();
+        let repo_operations = HistogramVec::new(
+            HistogramOpts::new("repo_operation_duration_seconds", "Repository operation latency")
+                .buckets(vec![0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0]),
+            &["repo", "operation"],
+        ).unwrap();
+
+        let purged = IntCounterVec::new(
+            Opts::new("purged_total", "Expired artifacts removed by the maintenance task"),
+            &["kind"],
+        ).unwrap();
+
+        let saml_requests = IntCounterVec::new(
+            Opts::new("saml_requests_total", "SAML requests by endpoint and outcome"),
+            &["endpoint", "outcome"],
+        ).unwrap();
+        let tokens = IntCounterVec::new(
+            Opts::new("token_requests_total", "OAuth token requests by grant and outcome"),
+            &["grant", "outcome"],
+        ).unwrap();
+
+        registry.register(Box::new(logins.clone())).unwrap();
+        registry.register(Box::new(registrations.clone())).unwrap();
+        registry.register(Box::new(authentication_failures.clone())).unwrap();
+        registry.register(Box::new(active_sessions.clone())).unwrap();
+        registry.register(Box::new(http_requests.clone())).unwrap();
+        registry.register(Box::new(repo_operations.clone())).unwrap();
+        registry.register(Box::new(purged.clone())).unwrap();
+        registry.register(Box::new(saml_requests.clone())).unwrap();
+        registry.register(Box::new(tokens.clone())).unwrap();
+
+        Self {
+            registry,
+            logins,
+            registrations,
+            authentication_failures,
+            active_sessions,
+            http_requests,
+            repo_operations,
+            purged,
+            saml_requests,
+            tokens,
+        }
+    }
+
+    /// Time a repository call, the duration is recorded when the timer is dropped
+    pub fn time_repo(&self, repo: &str, operation: &str) -> HistogramTimer {
+        self.repo_operations.with_label_values(&[repo, operation]).start_timer()
+    }
+
+    /// Render every metric in the Prometheus text format
+    pub fn render(&self) -> String {
+        let mut buffer = vec![];
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+}
@@ -0,0 +1,134 @@
+use std::sync::Arc;
+use crate::services::clock::Clock;
+use crate::services::factory::Repos;
+use crate::services::health::HealthService;
+
+#[derive(Default, Debug, PartialEq)]
+pub struct MigrationReport {
+    pub login_tokens: usize,
+    pub application_grants: usize,
+    pub audit_events: usize,
+    /// Sessions of users that do not exist anymore
+    pub dropped: usize,
+}
+
+/// Upgrades the data kept by persistent stores from a previous version
+pub struct MigrationService {
+    repos: Repos,
+    clock: Arc<dyn Clock>,
+}
+
+impl MigrationService {
+    pub fn new(repos: Repos, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            repos,
+            clock,
+        }
+    }
+
+    /// Run every migration, the server is not ready until they are done
+    pub async fn run(&self, health: &HealthService) {
+        let report = self.migrate_user_ids().await;
+        health.set_migrating(false);
+
+        tracing::info!(
+            login_tokens = report.login_tokens,
+            application_grants = report.application_grants,
+            audit_events = report.audit_events,
+            dropped = report.dropped,
+            "migration done"
+        );
+    }
+
+    /// Users used to be referenced by their email, replace those references by the user ids.
+    /// Ids never contain an `@`, so the migration can run on already migrated data
+    pub async fn migrate_user_ids(&self) -> MigrationReport {
+        let mut report = MigrationReport::default();
+        let users = self.repos.user_repo.get_all().await;
+        let id_of = |email: &str| users.iter()
+            .find(|user| user.email == email)
+            .map(|user| user.id.clone());
+
+        for mut token in self.repos.login_token_repo.get_all(self.clock.now()).await {
+            if !token.user.contains('@') {
+                continue;
+            }
+            match id_of(&token.user) {
+                Some(id) => {
+                    token.user = id;
+                    self.repos.login_token_repo.add(token).await;
+                    report.login_tokens += 1;
+                }
+                None => {
+                    self.repos.login_token_repo.delete(&token.value).await;
+                    report.dropped += 1;
+                }
+            }
+        }
+
+        for application in self.repos.application_repo.get_all().await {
+            for user in application.users.iter().filter(|user| user.contains('@')) {
+                self.repos.application_repo.remove_user(&application.client_id, user).await;
+                if let Some(id) = id_of(user) {
+                    self.repos.application_repo.add_user(&application.client_id, &id).await;
+                    report.application_grants += 1;
+                }
+            }
+        }
+
+        for user in &users {
+            report.audit_events += self.repos.audit_repo.replace_user(&user.email, &user.id).await;
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use chrono::{Days, Utc};
+    use super::*;
+    use crate::objects::application::{Application, Protocol};
+    use crate::objects::audit_event::{AuditAction, AuditEvent};
+    use crate::objects::config::Config;
+    use crate::objects::login_token::LoginToken;
+    use crate::services::clock::SystemClock;
+    use crate::services::metrics::Metrics;
+
+    #[actix_web::test]
+    async fn test_migrate_user_ids() {
+        let repos = Repos::new(&Config::default(), &Metrics::new());
+        let service = MigrationService::new(repos.clone(), Arc::new(SystemClock));
+        let admin = repos.user_repo.get_by_email("admin@example.com").await.unwrap();
+
+        for (value, user) in [("a", "admin@example.com"), ("b", "gone@example.com"), ("c", admin.id.as_str())] {
+            repos.login_token_repo.add(LoginToken {
+                value: value.to_string(),
+                user: user.to_string(),
+                expiration: Utc::now() + Days::new(1),
+            }).await;
+        }
+        repos.application_repo.add(Application {
+            name: "Wiki".to_string(),
+            url: "https://wiki.example.com".to_string(),
+            icon_url: None,
+            client_id: "wiki".to_string(),
+            client_secret: "secret".to_string(),
+            protocol: Protocol::Oidc,
+            scopes: HashSet::new(),
+            users: HashSet::from(["admin@example.com".to_string(), "gone@example.com".to_string()]),
+            pre_consented: false,
+        }).await;
+        repos.audit_repo.add(AuditEvent::new(Utc::now(), "admin@example.com", AuditAction::Login, "admin@example.com")).await;
+
+        let report = service.migrate_user_ids().await;
+        assert_eq!(MigrationReport { login_tokens: 1, application_grants: 1, audit_events: 1, dropped: 1 }, report);
+        assert_eq!(admin.id, repos.login_token_repo.get_by_value("a").await.unwrap().user);
+        assert!(repos.login_token_repo.get_by_value("b").await.is_none());
+        assert_eq!(HashSet::from([admin.id.clone()]), repos.application_repo.get_by_client_id("wiki").await.unwrap().users);
+        assert_eq!(1, repos.audit_repo.get_for_user(&admin.id).await.len());
+
+        // running it again changes nothing
+        assert_eq!(MigrationReport::default(), service.migrate_user_ids().await);
+    }
+}
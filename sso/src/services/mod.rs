@@ -1,2 +1,20 @@
+pub mod account;
+pub mod admin;
 pub mod auth;
-pub mod factory;
\ No newline at end of file
+pub mod auth_provider;
+pub mod clock;
+pub mod consent;
+pub mod factory;
+pub mod federation;
+pub mod health;
+pub mod launcher;
+pub mod ldap;
+pub mod mailer;
+pub mod maintenance;
+pub mod metrics;
+pub mod migration;
+pub mod oauth;
+pub mod password;
+pub mod saml;
+pub mod scim;
+pub mod scim_filter;
\ No newline at end of file
@@ -0,0 +1,660 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::TimeDelta;
+use ring::hmac;
+use ring::rand::SystemRandom;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use crate::errors::oauth::OAuthError;
+use crate::forms::oauth::{AuthorizeQuery, TokenForm};
+use crate::objects::application::{Application, Protocol};
+use crate::objects::authorization_code::AuthorizationCode;
+use crate::objects::config::Config;
+use crate::objects::refresh_token::RefreshToken;
+use crate::objects::user::{User, UserStatus};
+use crate::services::auth::AuthService;
+use crate::services::clock::Clock;
+use crate::services::consent::ConsentService;
+use crate::services::factory::Repos;
+use crate::services::metrics::Metrics;
+
+/// Claims of the access tokens, a JWT signed with HS256
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct AccessTokenClaims {
+    pub iss: String,
+    /// The client id for the client credentials grant, the user id otherwise
+    pub sub: String,
+    pub client_id: String,
+    /// Space separated scopes
+    pub scope: String,
+    pub iat: i64,
+    pub exp: i64,
+    pub jti: String,
+}
+
+/// Answer of the token endpoint
+pub struct IssuedToken {
+    pub access_token: String,
+    pub expires_in: i64,
+    pub scope: String,
+    pub refresh_token: Option<String>,
+}
+
+/// Outcome of an authorization request
+pub enum Authorization {
+    /// Sent back to the application
+    Code(String),
+    /// The user has to agree to share these scopes first
+    ConsentRequired(BTreeSet<String>),
+}
+
+/// Authorization codes must be exchanged right after the redirect
+const CODE_LIFETIME: TimeDelta = TimeDelta::seconds(60);
+
+/// Scopes any application can ask on behalf of its users, on top of its own
+const USER_SCOPES: [&str; 4] = ["openid", "profile", "email", "offline_access"];
+
+/// OAuth2 token endpoint, with the introspection and revocation resource servers call
+pub struct OAuthService {
+    config: Config,
+    repos: Repos,
+    metrics: Metrics,
+    clock: Arc<dyn Clock>,
+    consent: ConsentService,
+    key: hmac::Key,
+}
+
+impl OAuthService {
+    pub fn new(config: Config, repos: Repos, metrics: Metrics, clock: Arc<dyn Clock>, consent: ConsentService) -> Self {
+        let key = match &config.token_secret {
+            Some(secret) => hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()),
+            None => {
+                tracing::warn!("no token secret configured, access tokens will not survive a restart");
+                hmac::Key::generate(hmac::HMAC_SHA256, &SystemRandom::new()).unwrap()
+            }
+        };
+
+        Self {
+            config,
+            repos,
+            metrics,
+            clock,
+            consent,
+            key,
+        }
+    }
+
+    /// The application with this client id, if the secret matches
+    pub async fn authenticate_client(&self, client_id: &str, secret: &str) -> Result<Application, OAuthError> {
+        let application = self.repos.application_repo.get_by_client_id(client_id).await.ok_or(OAuthError::InvalidClient)?;
+
+        // Hashed so that the comparison time does not depend on the secret
+        match Sha256::digest(secret.as_bytes()) == Sha256::digest(application.client_secret.as_bytes()) {
+            true => Ok(application),
+            false => Err(OAuthError::InvalidClient),
+        }
+    }
+
+    /// The application of an authorization request, when the browser can safely be sent back to it
+    pub async fn authorization_client(&self, query: &AuthorizeQuery) -> Result<Application, OAuthError> {
+        let application = self.repos.application_repo.get_by_client_id(&query.client_id).await
+            .filter(|application| matches!(application.protocol, Protocol::Oidc))
+            .ok_or(OAuthError::InvalidClient)?;
+
+        // Anything under the url of the application, so that codes never leave it
+        let base = application.url.trim_end_matches('/');
+        let redirect = query.redirect_uri.as_str();
+        match redirect == base || redirect.strip_prefix(base).is_some_and(|path| path.starts_with(['/', '?'])) {
+            true => Ok(application),
+            false => Err(OAuthError::InvalidRequest("redirect_uri does not belong to the application".to_string())),
+        }
+    }
+
+    /// Authorize the client for the user logged in with `session`, returning the code to send back.
+    /// `consented` is set when the user just agreed on the consent page
+    pub async fn authorize(&self, user: &User, session: &str, client: &Application, query: &AuthorizeQuery, consented: bool) -> Result<Authorization, OAuthError> {
+        if query.response_type != "code" {
+            return Err(OAuthError::UnsupportedResponseType);
+        }
+        if !client.users.contains(&user.id) {
+            tracing::warn!(client_id = %client.client_id, "user without access to the application refused");
+            return Err(OAuthError::AccessDenied);
+        }
+        if query.code_challenge.is_some() && query.code_challenge_method.as_deref() != Some("S256") {
+            return Err(OAuthError::InvalidRequest("only the S256 code challenge method is supported".to_string()));
+        }
+        let scopes: BTreeSet<String> = match &query.scope {
+            Some(scope) => scope.split_whitespace().map(str::to_string).collect(),
+            None => BTreeSet::from(["openid".to_string()]),
+        };
+        if scopes.iter().any(|scope| !USER_SCOPES.contains(&scope.as_str()) && !client.scopes.contains(scope)) {
+            return Err(OAuthError::InvalidScope);
+        }
+        match consented {
+            true => self.consent.grant(user, client, &scopes).await,
+            false if !self.consent.is_granted(user, client, &scopes).await => return Ok(Authorization::ConsentRequired(scopes)),
+            false => {}
+        }
+
+        let code = AuthorizationCode {
+            value: AuthService::generate_value(),
+            client_id: client.client_id.clone(),
+            user: user.id.clone(),
+            session: session.to_string(),
+            redirect_uri: query.redirect_uri.clone(),
+            scope: scopes.into_iter().collect::<Vec<_>>().join(" "),
+            code_challenge: query.code_challenge.clone(),
+            expiration: self.clock.now() + CODE_LIFETIME,
+        };
+        let value = code.value.clone();
+        self.repos.authorization_code_repo.add(code).await;
+        tracing::info!(client_id = %client.client_id, "authorization code issued");
+        Ok(Authorization::Code(value))
+    }
+
+    pub async fn token(&self, client: &Application, form: &TokenForm) -> Result<IssuedToken, OAuthError> {
+        let result = match form.grant_type.as_str() {
+            "client_credentials" => self.client_credentials(client, form.scope.as_deref()),
+            "authorization_code" => self.authorization_code(client, form).await,
+            "refresh_token" => self.refresh(client, form).await,
+            _ => Err(OAuthError::UnsupportedGrantType),
+        };
+
+        let outcome = match &result {
+            Ok(token) => {
+                tracing::info!(client_id = %client.client_id, grant = %form.grant_type, scope = %token.scope, "access token issued");
+                "success"
+            }
+            Err(e) => {
+                tracing::warn!(?form, error = %e, "token request refused");
+                e.kind()
+            }
+        };
+        // the grant type comes from the client, keep the label values bounded
+        let grant = match form.grant_type.as_str() {
+            grant @ ("client_credentials" | "authorization_code" | "refresh_token") => grant,
+            _ => "unsupported",
+        };
+        self.metrics.tokens.with_label_values(&[grant, outcome]).inc();
+        result
+    }
+
+    /// A token for the application itself, limited to the scopes it is allowed
+    fn client_credentials(&self, client: &Application, scope: Option<&str>) -> Result<IssuedToken, OAuthError> {
+        if !matches!(client.protocol, Protocol::Oidc) {
+            return Err(OAuthError::UnauthorizedClient);
+        }
+        let scopes: BTreeSet<&str> = match scope {
+            Some(scope) => scope.split_whitespace().collect(),
+            None => client.scopes.iter().map(String::as_str).collect(),
+        };
+        if scopes.iter().any(|scope| !client.scopes.contains(*scope)) {
+            return Err(OAuthError::InvalidScope);
+        }
+        let scope = scopes.into_iter().collect::<Vec<_>>().join(" ");
+        self.issue(&client.client_id, &client.client_id, scope)
+    }
+
+    /// Exchange a code for an access token and the first refresh token of a new family
+    async fn authorization_code(&self, client: &Application, form: &TokenForm) -> Result<IssuedToken, OAuthError> {
+        let value = form.code.as_deref().ok_or(OAuthError::InvalidRequest("missing code".to_string()))?;
+        let code = self.repos.authorization_code_repo.take(value).await.ok_or(OAuthError::InvalidGrant)?;
+
+        if code.client_id != client.client_id
+            || code.expiration < self.clock.now()
+            || form.redirect_uri.as_deref() != Some(code.redirect_uri.as_str()) {
+            return Err(OAuthError::InvalidGrant);
+        }
+        if let Some(challenge) = &code.code_challenge {
+            let verifier = form.code_verifier.as_deref().ok_or(OAuthError::InvalidGrant)?;
+            if URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes())) != *challenge {
+                return Err(OAuthError::InvalidGrant);
+            }
+        }
+        if !self.has_access(client, &code.user, &code.session).await {
+            return Err(OAuthError::InvalidGrant);
+        }
+
+        let issued = self.issue(&code.user, &client.client_id, code.scope.clone())?;
+        let now = self.clock.now();
+        let family_expiration = now + TimeDelta::from_std(self.config.refresh_token_max_lifetime).unwrap_or(TimeDelta::days(30));
+        let refresh_token = self.rotate(RefreshToken {
+            value: String::new(),
+            family: AuthService::generate_value(),
+            client_id: client.client_id.clone(),
+            user: code.user.clone(),
+            session: code.session,
+            scope: code.scope.clone(),
+            used: false,
+            expiration: family_expiration,
+            family_expiration,
+        }).await;
+
+        Ok(IssuedToken {
+            refresh_token: Some(refresh_token),
+            ..issued
+        })
+    }
+
+    /// Exchange a refresh token for a new one of the same family, a token used twice
+    /// has leaked so the whole family is revoked
+    async fn refresh(&self, client: &Application, form: &TokenForm) -> Result<IssuedToken, OAuthError> {
+        let value = form.refresh_token.as_deref().ok_or(OAuthError::InvalidRequest("missing refresh_token".to_string()))?;
+        let token = self.repos.refresh_token_repo.mark_used(value).await.ok_or(OAuthError::InvalidGrant)?;
+
+        if token.used || token.client_id != client.client_id {
+            tracing::warn!(client_id = %client.client_id, owner = %token.client_id, family = %token.family, "refresh token reused, revoking its family");
+            self.revoke_family(&token.family).await;
+            return Err(OAuthError::InvalidGrant);
+        }
+        if token.expiration < self.clock.now() {
+            return Err(OAuthError::InvalidGrant);
+        }
+        if !self.has_access(client, &token.user, &token.session).await {
+            tracing::info!(client_id = %client.client_id, family = %token.family, "session or access ended, revoking the refresh tokens");
+            self.revoke_family(&token.family).await;
+            return Err(OAuthError::InvalidGrant);
+        }
+
+        // The access token can be narrowed, the refresh token keeps the scope that was authorized
+        let scope = match form.scope.as_deref() {
+            Some(scope) => {
+                let granted: BTreeSet<&str> = token.scope.split_whitespace().collect();
+                let scopes: BTreeSet<&str> = scope.split_whitespace().collect();
+                if !scopes.is_subset(&granted) {
+                    return Err(OAuthError::InvalidScope);
+                }
+                scopes.into_iter().collect::<Vec<_>>().join(" ")
+            }
+            None => token.scope.clone(),
+        };
+        let issued = self.issue(&token.user, &client.client_id, scope)?;
+        let refresh_token = self.rotate(token).await;
+
+        Ok(IssuedToken {
+            refresh_token: Some(refresh_token),
+            ..issued
+        })
+    }
+
+    /// Store the next token of the family, valid until the idle lifetime or the family cap
+    async fn rotate(&self, previous: RefreshToken) -> String {
+        let lifetime = TimeDelta::from_std(self.config.refresh_token_lifetime).unwrap_or(TimeDelta::days(7));
+        let token = RefreshToken {
+            value: AuthService::generate_value(),
+            used: false,
+            expiration: (self.clock.now() + lifetime).min(previous.family_expiration),
+            ..previous
+        };
+        let value = token.value.clone();
+        self.repos.refresh_token_repo.add(token).await;
+        value
+    }
+
+    async fn revoke_family(&self, family: &str) {
+        self.repos.refresh_token_repo.delete_family(family).await;
+    }
+
+    /// The user is still logged in with the session the application was authorized
+    /// from, is active and still allowed to use the application
+    async fn has_access(&self, client: &Application, user: &str, session: &str) -> bool {
+        let session = self.repos.login_token_repo.get_by_value(session).await;
+        if !session.is_some_and(|session| session.user == user && session.expiration > self.clock.now()) {
+            return false;
+        }
+        let application = self.repos.application_repo.get_by_client_id(&client.client_id).await;
+        if !application.is_some_and(|application| application.users.contains(user)) {
+            return false;
+        }
+        self.repos.user_repo.get_by_id(user).await
+            .is_some_and(|user| matches!(user.status, UserStatus::Active))
+    }
+
+    fn issue(&self, subject: &str, client_id: &str, scope: String) -> Result<IssuedToken, OAuthError> {
+        let now = self.clock.now();
+        let lifetime = TimeDelta::from_std(self.config.access_token_lifetime).map_err(|_| OAuthError::ServerError)?;
+        let expiration = now.checked_add_signed(lifetime).ok_or(OAuthError::ServerError)?;
+        let claims = AccessTokenClaims {
+            iss: self.config.public_url.clone(),
+            sub: subject.to_string(),
+            client_id: client_id.to_string(),
+            scope,
+            iat: now.timestamp(),
+            exp: expiration.timestamp(),
+            jti: AuthService::generate_value(),
+        };
+
+        let header = URL_SAFE_NO_PAD.encode(json!({ "alg": "HS256", "typ": "at+jwt" }).to_string());
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims).unwrap());
+        let signed = format!("{}.{}", header, payload);
+        let signature = URL_SAFE_NO_PAD.encode(hmac::sign(&self.key, signed.as_bytes()));
+
+        Ok(IssuedToken {
+            access_token: format!("{}.{}", signed, signature),
+            expires_in: lifetime.num_seconds(),
+            scope: claims.scope,
+            refresh_token: None,
+        })
+    }
+
+    /// The claims of a token signed here, expired or not
+    fn decode(&self, token: &str) -> Option<AccessTokenClaims> {
+        let (signed, signature) = token.rsplit_once('.')?;
+        let (header, payload) = signed.split_once('.')?;
+        hmac::verify(&self.key, signed.as_bytes(), &URL_SAFE_NO_PAD.decode(signature).ok()?).ok()?;
+
+        let header: serde_json::Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(header).ok()?).ok()?;
+        if header["alg"] != "HS256" {
+            return None;
+        }
+        serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()
+    }
+
+    /// The claims of an active token, `None` when it is invalid, expired or revoked
+    pub async fn introspect(&self, token: &str) -> Option<AccessTokenClaims> {
+        let claims = self.decode(token)?;
+        if claims.exp <= self.clock.now().timestamp() || claims.iss != self.config.public_url {
+            return None;
+        }
+        match self.repos.revoked_token_repo.is_revoked(&claims.jti).await {
+            true => None,
+            false => Some(claims),
+        }
+    }
+
+    /// Revoke a token issued to the client, other tokens are ignored as RFC 7009 asks.
+    /// Revoking a refresh token revokes every token of its family
+    pub async fn revoke(&self, client: &Application, token: &str) {
+        let refresh_token = self.repos.refresh_token_repo.get_by_value(token).await;
+        if let Some(refresh_token) = refresh_token {
+            if refresh_token.client_id == client.client_id {
+                self.revoke_family(&refresh_token.family).await;
+                tracing::info!(client_id = %client.client_id, "refresh token revoked");
+            }
+            return;
+        }
+
+        let Some(claims) = self.introspect(token).await else {
+            return;
+        };
+        if claims.client_id != client.client_id {
+            tracing::warn!(client_id = %client.client_id, owner = %claims.client_id, "refused to revoke the token of another client");
+            return;
+        }
+        let expiration = chrono::DateTime::from_timestamp(claims.exp, 0).unwrap_or_else(|| self.clock.now());
+        self.repos.revoked_token_repo.add(&claims.jti, expiration).await;
+        tracing::info!(client_id = %client.client_id, "access token revoked");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use chrono::Utc;
+    use super::*;
+    use crate::objects::login_token::LoginToken;
+    use crate::services::clock::MockClock;
+
+    async fn get_service() -> (OAuthService, Arc<MockClock>) {
+        let config = Config { token_secret: Some("secret".to_string()), ..Config::default() };
+        let repos = Repos::new(&config, &Metrics::new());
+        let clock = Arc::new(MockClock::new(Utc::now()));
+        for client_id in ["billing", "reports"] {
+            repos.application_repo.add(Application {
+                name: client_id.to_string(),
+                url: format!("https://{}.example.com", client_id),
+                icon_url: None,
+                client_id: client_id.to_string(),
+                client_secret: format!("{}-secret", client_id),
+                protocol: Protocol::Oidc,
+                scopes: HashSet::from(["invoices:read".to_string(), "invoices:write".to_string()]),
+                users: HashSet::new(),
+                pre_consented: false,
+            }).await;
+        }
+        let consent = ConsentService::new(repos.clone(), clock.clone());
+        (OAuthService::new(config, repos, Metrics::new(), clock.clone(), consent), clock)
+    }
+
+    fn form(scope: Option<&str>) -> TokenForm {
+        TokenForm {
+            grant_type: "client_credentials".to_string(),
+            scope: scope.map(str::to_string),
+            client_id: None,
+            client_secret: None,
+            code: None,
+            redirect_uri: None,
+            code_verifier: None,
+            refresh_token: None,
+        }
+    }
+
+    fn refresh_form(refresh_token: &str) -> TokenForm {
+        TokenForm {
+            grant_type: "refresh_token".to_string(),
+            refresh_token: Some(refresh_token.to_string()),
+            ..form(None)
+        }
+    }
+
+    fn authorize_query(redirect_uri: &str, code_challenge: Option<&str>) -> AuthorizeQuery {
+        AuthorizeQuery {
+            response_type: "code".to_string(),
+            client_id: "billing".to_string(),
+            redirect_uri: redirect_uri.to_string(),
+            scope: Some("openid invoices:read".to_string()),
+            state: None,
+            code_challenge: code_challenge.map(str::to_string),
+            code_challenge_method: code_challenge.map(|_| "S256".to_string()),
+        }
+    }
+
+    /// Log the admin in and give them access to billing, returning the session
+    async fn login(service: &OAuthService) -> (User, String) {
+        let admin = service.repos.user_repo.get_by_email("admin@example.com").await.unwrap();
+        let session = AuthService::generate_value();
+        service.repos.login_token_repo.add(LoginToken {
+            value: session.clone(),
+            user: admin.id.clone(),
+            expiration: service.clock.now() + TimeDelta::days(90),
+        }).await;
+        assert!(service.repos.application_repo.add_user("billing", &admin.id).await);
+        (admin, session)
+    }
+
+    /// Go through the authorization code flow, returning the first refresh token
+    async fn authorize(service: &OAuthService, client: &Application, user: &User, session: &str) -> String {
+        let query = authorize_query("https://billing.example.com/callback", None);
+        let Ok(Authorization::Code(code)) = service.authorize(user, session, client, &query, true).await else {
+            panic!("no authorization code");
+        };
+        let exchange = TokenForm {
+            grant_type: "authorization_code".to_string(),
+            code: Some(code),
+            redirect_uri: Some(query.redirect_uri),
+            ..form(None)
+        };
+        service.token(client, &exchange).await.ok().unwrap().refresh_token.unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_client_credentials() {
+        let (service, clock) = get_service().await;
+        assert!(matches!(service.authenticate_client("billing", "wrong").await, Err(OAuthError::InvalidClient)));
+        let client = service.authenticate_client("billing", "billing-secret").await.ok().unwrap();
+
+        let token = service.token(&client, &form(None)).await.ok().unwrap();
+        assert_eq!(token.scope, "invoices:read invoices:write");
+        assert_eq!(token.expires_in, 15 * 60);
+
+        let token = service.token(&client, &form(Some("invoices:read"))).await.ok().unwrap();
+        let claims = service.introspect(&token.access_token).await.unwrap();
+        assert_eq!((claims.sub.as_str(), claims.scope.as_str()), ("billing", "invoices:read"));
+
+        assert!(matches!(service.token(&client, &form(Some("users:read"))).await, Err(OAuthError::InvalidScope)));
+        let password = TokenForm { grant_type: "password".to_string(), ..form(None) };
+        assert!(matches!(service.token(&client, &password).await, Err(OAuthError::UnsupportedGrantType)));
+        assert_eq!(service.metrics.tokens.with_label_values(&["unsupported", "unsupported_grant_type"]).get(), 1);
+
+        // A token changed by its holder is refused
+        let (signed, _) = token.access_token.rsplit_once('.').unwrap();
+        let forged = format!("{}.{}", signed, URL_SAFE_NO_PAD.encode([0u8; 32]));
+        assert!(service.introspect(&forged).await.is_none());
+
+        clock.advance(TimeDelta::minutes(16));
+        assert!(service.introspect(&token.access_token).await.is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_lifetime_out_of_range() {
+        let (mut service, _) = get_service().await;
+        let client = service.authenticate_client("billing", "billing-secret").await.ok().unwrap();
+
+        // Too long for a TimeDelta, then too long to be added to the current date
+        for seconds in [u64::MAX, 9_000_000_000_000] {
+            service.config.access_token_lifetime = std::time::Duration::from_secs(seconds);
+            assert!(matches!(service.token(&client, &form(None)).await, Err(OAuthError::ServerError)));
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_revocation() {
+        let (service, _) = get_service().await;
+        let billing = service.authenticate_client("billing", "billing-secret").await.ok().unwrap();
+        let reports = service.authenticate_client("reports", "reports-secret").await.ok().unwrap();
+        let token = service.token(&billing, &form(None)).await.ok().unwrap().access_token;
+
+        // Only the client the token was issued to can revoke it
+        service.revoke(&reports, &token).await;
+        assert!(service.introspect(&token).await.is_some());
+
+        service.revoke(&billing, &token).await;
+        assert!(service.introspect(&token).await.is_none());
+        service.revoke(&billing, "not a token").await;
+    }
+
+    #[actix_web::test]
+    async fn test_authorization_code() {
+        let (service, _) = get_service().await;
+        let (admin, session) = login(&service).await;
+        let client = service.authenticate_client("billing", "billing-secret").await.ok().unwrap();
+
+        for redirect in ["https://billing.example.com.evil.com/callback", "https://evil.com/?https://billing.example.com"] {
+            let query = authorize_query(redirect, None);
+            assert!(matches!(service.authorization_client(&query).await, Err(OAuthError::InvalidRequest(_))));
+        }
+        let query = authorize_query("https://billing.example.com/callback", Some("wNqigVU4mRCIQCRh1klvRqZznsuuEEk-ICPKLjgtgSA"));
+        assert!(service.authorization_client(&query).await.is_ok());
+        let unknown = AuthorizeQuery { scope: Some("users:write".to_string()), ..authorize_query(&query.redirect_uri, None) };
+        assert!(matches!(service.authorize(&admin, &session, &client, &unknown, true).await, Err(OAuthError::InvalidScope)));
+
+        // The user is asked once, then the consent is remembered
+        let Ok(Authorization::ConsentRequired(scopes)) = service.authorize(&admin, &session, &client, &query, false).await else {
+            panic!("consent should be asked first");
+        };
+        assert_eq!(scopes, BTreeSet::from(["invoices:read".to_string(), "openid".to_string()]));
+        assert!(matches!(service.authorize(&admin, &session, &client, &query, true).await, Ok(Authorization::Code(_))));
+        let Ok(Authorization::Code(code)) = service.authorize(&admin, &session, &client, &query, false).await else {
+            panic!("the consent should be remembered");
+        };
+        let exchange = TokenForm {
+            grant_type: "authorization_code".to_string(),
+            code: Some(code),
+            redirect_uri: Some(query.redirect_uri.clone()),
+            code_verifier: Some("dBjftJeZ4CVP-mJ92K9TQ3q4s1mbwwTfv2W7lS-gcFM".to_string()),
+            ..form(None)
+        };
+        let token = service.token(&client, &exchange).await.ok().unwrap();
+        assert!(token.refresh_token.is_some());
+        let claims = service.introspect(&token.access_token).await.unwrap();
+        assert_eq!((claims.sub.as_str(), claims.scope.as_str()), (admin.id.as_str(), "invoices:read openid"));
+
+        // A code can only be exchanged once
+        assert!(matches!(service.token(&client, &exchange).await, Err(OAuthError::InvalidGrant)));
+
+        let Ok(Authorization::Code(code)) = service.authorize(&admin, &session, &client, &query, false).await else {
+            panic!("no authorization code");
+        };
+        let wrong_verifier = TokenForm { code: Some(code), code_verifier: Some("wrong".to_string()), ..exchange };
+        assert!(matches!(service.token(&client, &wrong_verifier).await, Err(OAuthError::InvalidGrant)));
+
+        // Only the users of the application can authorize it
+        service.repos.application_repo.remove_user("billing", &admin.id).await;
+        let client = service.authorization_client(&query).await.ok().unwrap();
+        assert!(matches!(service.authorize(&admin, &session, &client, &query, false).await, Err(OAuthError::AccessDenied)));
+    }
+
+    #[actix_web::test]
+    async fn test_refresh_rotation_and_reuse() {
+        let (service, _) = get_service().await;
+        let (admin, session) = login(&service).await;
+        let client = service.authenticate_client("billing", "billing-secret").await.ok().unwrap();
+        let reports = service.authenticate_client("reports", "reports-secret").await.ok().unwrap();
+        let first = authorize(&service, &client, &admin, &session).await;
+
+        let second = service.token(&client, &refresh_form(&first)).await.ok().unwrap();
+        assert_eq!(second.scope, "invoices:read openid");
+        let second = second.refresh_token.unwrap();
+        assert_ne!(first, second);
+        let narrowed = TokenForm { scope: Some("openid".to_string()), ..refresh_form(&second) };
+        let third = service.token(&client, &narrowed).await.ok().unwrap();
+        assert_eq!(third.scope, "openid");
+        let third = third.refresh_token.unwrap();
+
+        // The first token was stolen: its reuse revokes the token the legitimate client holds
+        assert!(matches!(service.token(&client, &refresh_form(&first)).await, Err(OAuthError::InvalidGrant)));
+        assert!(matches!(service.token(&client, &refresh_form(&third)).await, Err(OAuthError::InvalidGrant)));
+
+        // Another family is not affected, but can only be used by its client
+        let other = authorize(&service, &client, &admin, &session).await;
+        let other = service.token(&client, &refresh_form(&other)).await.ok().unwrap().refresh_token.unwrap();
+        service.revoke(&reports, &other).await;
+        let other = service.token(&client, &refresh_form(&other)).await.ok().unwrap().refresh_token.unwrap();
+        service.revoke(&client, &other).await;
+        assert!(matches!(service.token(&client, &refresh_form(&other)).await, Err(OAuthError::InvalidGrant)));
+    }
+
+    #[actix_web::test]
+    async fn test_refresh_lifetime() {
+        let (service, clock) = get_service().await;
+        let (admin, session) = login(&service).await;
+        let client = service.authenticate_client("billing", "billing-secret").await.ok().unwrap();
+
+        // Unused for longer than the idle lifetime
+        let token = authorize(&service, &client, &admin, &session).await;
+        clock.advance(TimeDelta::days(8));
+        assert!(matches!(service.token(&client, &refresh_form(&token)).await, Err(OAuthError::InvalidGrant)));
+
+        // Used regularly, but not past the absolute lifetime of the family
+        let mut token = authorize(&service, &client, &admin, &session).await;
+        for _ in 0..4 {
+            clock.advance(TimeDelta::days(6));
+            token = service.token(&client, &refresh_form(&token)).await.ok().unwrap().refresh_token.unwrap();
+        }
+        clock.advance(TimeDelta::days(6) + TimeDelta::seconds(1));
+        assert!(matches!(service.token(&client, &refresh_form(&token)).await, Err(OAuthError::InvalidGrant)));
+    }
+
+    #[actix_web::test]
+    async fn test_refresh_revoked_with_access() {
+        let (service, _) = get_service().await;
+        let (admin, session) = login(&service).await;
+        let client = service.authenticate_client("billing", "billing-secret").await.ok().unwrap();
+        let token = authorize(&service, &client, &admin, &session).await;
+
+        // Logging out ends the refresh tokens authorized from the session
+        service.repos.login_token_repo.delete(&session).await;
+        assert!(matches!(service.token(&client, &refresh_form(&token)).await, Err(OAuthError::InvalidGrant)));
+
+        let (_, session) = login(&service).await;
+        let token = authorize(&service, &client, &admin, &session).await;
+        service.repos.application_repo.remove_user("billing", &admin.id).await;
+        assert!(matches!(service.token(&client, &refresh_form(&token)).await, Err(OAuthError::InvalidGrant)));
+        // Access given back later does not revive the family
+        assert!(service.repos.application_repo.add_user("billing", &admin.id).await);
+        assert!(matches!(service.token(&client, &refresh_form(&token)).await, Err(OAuthError::InvalidGrant)));
+    }
+}
@@ -0,0 +1,154 @@
+use sha1::{Digest, Sha1};
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use crate::errors::validation::ValidationEnumError;
+use crate::objects::config::PasswordPolicy;
+
+#[derive(Clone)]
+pub struct PasswordService {
+    policy: PasswordPolicy,
+}
+
+impl PasswordService {
+    pub fn new(policy: PasswordPolicy) -> Self {
+        Self {
+            policy,
+        }
+    }
+
+    /// Check a password against the configured policy, `email` and `name`
+    /// are the ones of the account the password is for
+    pub async fn check(&self, password: &str, email: &str, name: &str) -> Vec<ValidationEnumError> {
+        let policy = &self.policy;
+        let length = password.chars().count() as i32;
+        let mut errors = vec![];
+
+        if length < policy.min_length || length > policy.max_length {
+            errors.push(ValidationEnumError::Size(policy.min_length, policy.max_length));
+        }
+        for class in &policy.required_classes {
+            if !password.chars().any(|c| class.matches(c)) {
+                errors.push(ValidationEnumError::MissingCharacter(*class));
+            }
+        }
+        if policy.disallow_personal_info && Self::contains_personal_info(password, email, name) {
+            errors.push(ValidationEnumError::PersonalInfo);
+        }
+        if policy.min_strength > 0 {
+            let local_part = email.split('@').next().unwrap_or_default();
+            let score = u8::from(zxcvbn::zxcvbn(password, &[email, local_part, name]).score());
+            if score < policy.min_strength {
+                errors.push(ValidationEnumError::TooWeak(score, policy.min_strength));
+            }
+        }
+        if self.is_breached(password).await {
+            errors.push(ValidationEnumError::Breached);
+        }
+        errors
+    }
+
+    fn contains_personal_info(password: &str, email: &str, name: &str) -> bool {
+        let password = password.to_lowercase();
+        let local_part = email.split('@').next().unwrap_or_default();
+
+        [local_part, name]
+            .iter()
+            .map(|info| info.to_lowercase())
+            // Very short names would match too many passwords
+            .filter(|info| info.chars().count() >= 3)
+            .any(|info| password.contains(&info))
+    }
+
+    /// Look the password up in the breached hash list, only reading the file of its prefix
+    async fn is_breached(&self, password: &str) -> bool {
+        let Some(dir) = &self.policy.breached_passwords else {
+            return false;
+        };
+        let hash = hex::encode_upper(Sha1::digest(password.as_bytes()));
+        let (prefix, suffix) = hash.split_at(5);
+
+        let file = match File::open(dir.join(prefix)).await {
+            Ok(file) => file,
+            Err(e) => {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    tracing::error!(error = %e, "could not read the breached password list");
+                }
+                return false;
+            }
+        };
+
+        let mut lines = BufReader::new(file).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if line.split(':').next().is_some_and(|entry| entry.trim().eq_ignore_ascii_case(suffix)) {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::objects::config::CharacterClass;
+    use super::*;
+
+    async fn check(policy: PasswordPolicy, password: &str) -> Result<(), ValidationEnumError> {
+        match PasswordService::new(policy).check(password, "jane.doe@example.com", "Jane").await.into_iter().next() {
+            None => Ok(()),
+            Some(error) => Err(error),
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_password_policy() {
+        let policy = PasswordPolicy {
+            required_classes: vec![CharacterClass::Uppercase, CharacterClass::Digit],
+            disallow_personal_info: true,
+            ..PasswordPolicy::default()
+        };
+
+        assert!(matches!(check(policy.clone(), "abc").await, Err(ValidationEnumError::Size(6, 255))));
+        assert!(matches!(check(policy.clone(), "abcdefgh1").await, Err(ValidationEnumError::MissingCharacter(CharacterClass::Uppercase))));
+        assert!(matches!(check(policy.clone(), "Abcdefghi").await, Err(ValidationEnumError::MissingCharacter(CharacterClass::Digit))));
+        assert!(matches!(check(policy.clone(), "Jane.doe1234").await, Err(ValidationEnumError::PersonalInfo)));
+        assert!(check(policy.clone(), "Abcdefgh1").await.is_ok());
+
+        // Every problem is reported at once
+        let errors = PasswordService::new(policy).check("jane", "jane.doe@example.com", "Jane").await;
+        assert_eq!(vec![
+            ValidationEnumError::Size(6, 255),
+            ValidationEnumError::MissingCharacter(CharacterClass::Uppercase),
+            ValidationEnumError::MissingCharacter(CharacterClass::Digit),
+            ValidationEnumError::PersonalInfo,
+        ], errors);
+    }
+
+    #[actix_web::test]
+    async fn test_password_strength() {
+        let policy = PasswordPolicy {
+            min_strength: 3,
+            ..PasswordPolicy::default()
+        };
+
+        assert!(matches!(check(policy.clone(), "password1").await, Err(ValidationEnumError::TooWeak(_, 3))));
+        assert!(check(policy, "correct horse battery staple").await.is_ok());
+    }
+
+    #[actix_web::test]
+    async fn test_breached_password() {
+        let dir = std::env::temp_dir().join(format!("sso-breached-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let hash = hex::encode_upper(Sha1::digest(b"hunter22"));
+        fs::write(dir.join(&hash[..5]), format!("0000000000000000000000000000000000A:3\r\n{}:42\r\n", &hash[5..])).unwrap();
+
+        let policy = PasswordPolicy {
+            breached_passwords: Some(dir.clone()),
+            ..PasswordPolicy::default()
+        };
+
+        assert!(matches!(check(policy.clone(), "hunter22").await, Err(ValidationEnumError::Breached)));
+        assert!(check(policy, "hunter23").await.is_ok());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
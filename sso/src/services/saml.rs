@@ -0,0 +1,649 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::sync::Arc;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use chrono::{DateTime, TimeDelta, Utc};
+use flate2::Compression;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use quick_xml::XmlVersion;
+use rand::distr::Alphanumeric;
+use rand::Rng;
+use ring::rand::SystemRandom;
+use ring::signature::{self, RsaKeyPair, UnparsedPublicKey};
+use sha2::{Digest, Sha256};
+use crate::errors::saml::SamlError;
+use crate::objects::application::{Application, Protocol, SamlSettings};
+use crate::objects::config::Config;
+use crate::objects::user::User;
+use crate::services::auth::AuthService;
+use crate::services::clock::Clock;
+use crate::services::factory::Repos;
+use crate::services::metrics::Metrics;
+
+const NS_ASSERTION: &str = "urn:oasis:names:tc:SAML:2.0:assertion";
+const NS_PROTOCOL: &str = "urn:oasis:names:tc:SAML:2.0:protocol";
+const NS_METADATA: &str = "urn:oasis:names:tc:SAML:2.0:metadata";
+const NS_DSIG: &str = "http://www.w3.org/2000/09/xmldsig#";
+const BINDING_REDIRECT: &str = "urn:oasis:names:tc:SAML:2.0:bindings:HTTP-Redirect";
+const NAME_ID_PERSISTENT: &str = "urn:oasis:names:tc:SAML:2.0:nameid-format:persistent";
+const STATUS_SUCCESS: &str = "urn:oasis:names:tc:SAML:2.0:status:Success";
+const EXCLUSIVE_C14N: &str = "http://www.w3.org/2001/10/xml-exc-c14n#";
+const RSA_SHA256: &str = "http://www.w3.org/2001/04/xmldsig-more#rsa-sha256";
+const SHA256: &str = "http://www.w3.org/2001/04/xmlenc#sha256";
+/// Inflated requests larger than this are refused
+const MAX_REQUEST_SIZE: u64 = 64 * 1024;
+
+/// Key pair signing the assertions and the logout responses
+struct SigningKeys {
+    key_pair: RsaKeyPair,
+    /// Base64 DER certificate, as published in the metadata
+    certificate: String,
+}
+
+/// A request received through the HTTP-Redirect binding
+struct SamlMessage {
+    id: String,
+    issuer: String,
+    acs_url: Option<String>,
+    name_id: Option<String>,
+}
+
+/// An assertion the browser has to post to the application
+pub struct SamlPost {
+    pub acs_url: String,
+    /// Base64 encoded `samlp:Response`
+    pub response: String,
+    pub relay_state: Option<String>,
+}
+
+/// SAML 2.0 identity provider, for the applications that do not speak OIDC
+pub struct SamlService {
+    config: Config,
+    repos: Repos,
+    metrics: Metrics,
+    clock: Arc<dyn Clock>,
+    auth: AuthService,
+    keys: Option<SigningKeys>,
+}
+
+impl SamlService {
+    pub fn new(config: Config, repos: Repos, metrics: Metrics, clock: Arc<dyn Clock>, auth: AuthService) -> Self {
+        let keys = config.saml.as_ref().map(|saml| {
+            let key = fs::read_to_string(&saml.key_file)
+                .unwrap_or_else(|e| panic!("Can not read the SAML key {}: {}", saml.key_file.display(), e));
+            let cert = fs::read_to_string(&saml.cert_file)
+                .unwrap_or_else(|e| panic!("Can not read the SAML certificate {}: {}", saml.cert_file.display(), e));
+            let key = pem_to_der(&key).expect("Invalid SAML key, a PEM file is expected");
+            let cert = pem_to_der(&cert).expect("Invalid SAML certificate, a PEM file is expected");
+            SigningKeys {
+                key_pair: RsaKeyPair::from_pkcs8(&key).expect("Invalid SAML key, a PKCS#8 RSA key is expected"),
+                certificate: STANDARD.encode(cert),
+            }
+        });
+
+        Self {
+            config,
+            repos,
+            metrics,
+            clock,
+            auth,
+            keys,
+        }
+    }
+
+    fn keys(&self) -> Result<&SigningKeys, SamlError> {
+        self.keys.as_ref().ok_or(SamlError::Disabled)
+    }
+
+    /// Identifier of this identity provider, the metadata is served there
+    pub fn entity_id(&self) -> String {
+        format!("{}/saml/metadata", self.config.public_url)
+    }
+
+    pub fn metadata(&self) -> Result<String, SamlError> {
+        let keys = self.keys()?;
+        let url = &self.config.public_url;
+
+        Ok(format!(
+            concat!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+                r#"<md:EntityDescriptor xmlns:md="{}" entityID="{}">"#,
+                r#"<md:IDPSSODescriptor WantAuthnRequestsSigned="false" protocolSupportEnumeration="{}">"#,
+                r#"<md:KeyDescriptor use="signing"><ds:KeyInfo xmlns:ds="{}"><ds:X509Data><ds:X509Certificate>{}</ds:X509Certificate></ds:X509Data></ds:KeyInfo></md:KeyDescriptor>"#,
+                r#"<md:SingleLogoutService Binding="{}" Location="{}/saml/slo"/>"#,
+                r#"<md:NameIDFormat>{}</md:NameIDFormat>"#,
+                r#"<md:SingleSignOnService Binding="{}" Location="{}/saml/sso"/>"#,
+                r#"</md:IDPSSODescriptor></md:EntityDescriptor>"#,
+            ),
+            NS_METADATA, escape_attribute(&self.entity_id()), NS_PROTOCOL,
+            NS_DSIG, keys.certificate,
+            BINDING_REDIRECT, escape_attribute(url),
+            NAME_ID_PERSISTENT,
+            BINDING_REDIRECT, escape_attribute(url),
+        ))
+    }
+
+    /// Answer an `AuthnRequest` sent by an application for a logged in user
+    pub async fn single_sign_on(&self, user: &User, session: &str, query: &str) -> Result<SamlPost, SamlError> {
+        let result = self.answer_request(user, session, query).await;
+        self.record("sso", &result);
+        result
+    }
+
+    async fn answer_request(&self, user: &User, session: &str, query: &str) -> Result<SamlPost, SamlError> {
+        self.keys()?;
+        let (message, application, settings, relay_state) = self.decode_redirect(query, "SAMLRequest", "AuthnRequest").await?;
+        if message.acs_url.as_ref().is_some_and(|url| *url != settings.acs_url) {
+            return Err(SamlError::AcsMismatch);
+        }
+        self.assert(user, session, &application, &settings, Some(&message.id), relay_state)
+    }
+
+    /// Log a user in an application without a request from it
+    pub async fn idp_initiated(&self, user: &User, session: &str, client_id: &str) -> Result<SamlPost, SamlError> {
+        let result = async {
+            self.keys()?;
+            let application = self.repos.application_repo.get_by_client_id(client_id).await.ok_or(SamlError::UnknownApplication)?;
+            let settings = match &application.protocol {
+                Protocol::Saml(settings) => settings.clone(),
+                Protocol::Oidc => return Err(SamlError::UnknownApplication),
+            };
+            self.assert(user, session, &application, &settings, None, None)
+        }.await;
+        self.record("idp_initiated", &result);
+        result
+    }
+
+    /// Close the session for a `LogoutRequest`, returning where to send the signed response
+    pub async fn single_logout(&self, session: Option<(&User, &str)>, query: &str) -> Result<String, SamlError> {
+        let result = self.answer_logout(session, query).await;
+        self.record("slo", &result);
+        result
+    }
+
+    async fn answer_logout(&self, session: Option<(&User, &str)>, query: &str) -> Result<String, SamlError> {
+        let keys = self.keys()?;
+        let (message, _, settings, relay_state) = self.decode_redirect(query, "SAMLRequest", "LogoutRequest").await?;
+        let slo_url = settings.slo_url.ok_or(SamlError::LogoutUnsupported)?;
+        // an unsigned logout request could be forged by any page the user visits
+        if settings.signing_cert.is_none() {
+            return Err(SamlError::SignatureRequired);
+        }
+
+        if let Some((user, token)) = session {
+            if message.name_id.as_deref() == Some(user.id.as_str()) {
+                self.auth.invalidate_token(token).await;
+                tracing::info!(email = %user.email, application = %message.issuer, "SAML single logout");
+            }
+        }
+
+        let response = format!(
+            concat!(
+                r#"<samlp:LogoutResponse xmlns:samlp="{}" xmlns:saml="{}" Destination="{}" ID="{}" InResponseTo="{}" IssueInstant="{}" Version="2.0">"#,
+                r#"<saml:Issuer>{}</saml:Issuer><samlp:Status><samlp:StatusCode Value="{}"></samlp:StatusCode></samlp:Status>"#,
+                r#"</samlp:LogoutResponse>"#,
+            ),
+            NS_PROTOCOL, NS_ASSERTION, escape_attribute(&slo_url), new_id(), escape_attribute(&message.id), instant(self.clock.now()),
+            escape_text(&self.entity_id()), STATUS_SUCCESS,
+        );
+
+        let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+        encoder.write_all(response.as_bytes())
+            .and_then(|_| encoder.finish())
+            .map(|deflated| {
+                let mut signed = vec![("SAMLResponse", STANDARD.encode(deflated))];
+                if let Some(relay_state) = relay_state {
+                    signed.push(("RelayState", relay_state));
+                }
+                signed.push(("SigAlg", RSA_SHA256.to_string()));
+                let signed = serde_urlencoded::to_string(&signed).unwrap();
+                let signature = serde_urlencoded::to_string([("Signature", STANDARD.encode(sign(keys, signed.as_bytes())))]).unwrap();
+                let separator = if slo_url.contains('?') { '&' } else { '?' };
+                format!("{}{}{}&{}", slo_url, separator, signed, signature)
+            })
+            .map_err(|e| SamlError::InvalidRequest(e.to_string()))
+    }
+
+    fn record<T>(&self, endpoint: &str, result: &Result<T, SamlError>) {
+        let outcome = match result {
+            Ok(_) => "success",
+            Err(e) => {
+                tracing::warn!(endpoint, error = %e, "SAML request refused");
+                e.kind()
+            }
+        };
+        self.metrics.saml_requests.with_label_values(&[endpoint, outcome]).inc();
+    }
+
+    /// Read a deflated message from a redirect query string, checking its signature
+    async fn decode_redirect(&self, query: &str, parameter: &str, element: &str) -> Result<(SamlMessage, Application, SamlSettings, Option<String>), SamlError> {
+        let params: HashMap<String, String> = serde_urlencoded::from_str(query)
+            .map_err(|e| SamlError::InvalidRequest(e.to_string()))?;
+        let encoded = params.get(parameter)
+            .ok_or_else(|| SamlError::InvalidRequest(format!("{} is missing", parameter)))?;
+        let deflated = STANDARD.decode(encoded.replace(['\r', '\n', ' '], ""))
+            .map_err(|_| SamlError::InvalidRequest("not base64".to_string()))?;
+        let mut xml = String::new();
+        DeflateDecoder::new(deflated.as_slice())
+            .take(MAX_REQUEST_SIZE)
+            .read_to_string(&mut xml)
+            .map_err(|_| SamlError::InvalidRequest("not deflated".to_string()))?;
+        let message = parse_message(&xml, element)?;
+
+        let application = self.repos.application_repo.get_by_entity_id(&message.issuer).await.ok_or(SamlError::UnknownApplication)?;
+        let settings = match &application.protocol {
+            Protocol::Saml(settings) => settings.clone(),
+            Protocol::Oidc => return Err(SamlError::UnknownApplication),
+        };
+
+        if let Some(cert) = &settings.signing_cert {
+            verify_redirect_signature(query, parameter, &params, cert)?;
+        }
+        let relay_state = params.get("RelayState").cloned();
+        Ok((message, application, settings, relay_state))
+    }
+
+    /// Build the signed response asserting the identity of the user to the application
+    fn assert(&self, user: &User, session: &str, application: &Application, settings: &SamlSettings, in_response_to: Option<&str>, relay_state: Option<String>) -> Result<SamlPost, SamlError> {
+        if !application.users.contains(&user.id) {
+            return Err(SamlError::AccessDenied);
+        }
+        let keys = self.keys()?;
+        let now = self.clock.now();
+        let issuer = escape_text(&self.entity_id());
+        let assertion_id = new_id();
+        let in_response_to = in_response_to
+            .map(|id| format!(r#" InResponseTo="{}""#, escape_attribute(id)))
+            .unwrap_or_default();
+
+        let mut groups: Vec<&String> = user.groups.iter().collect();
+        groups.sort();
+        let attributes = [
+            attribute("email", [&user.email]),
+            attribute("name", [&user.name]),
+            attribute("groups", groups),
+        ].concat();
+
+        // Written in its exclusive canonical form, so the digest is computed on the text as it is
+        let subject = format!(
+            concat!(
+                r#"<saml:Subject><saml:NameID Format="{}">{}</saml:NameID>"#,
+                r#"<saml:SubjectConfirmation Method="urn:oasis:names:tc:SAML:2.0:cm:bearer"><saml:SubjectConfirmationData{} NotOnOrAfter="{}" Recipient="{}"></saml:SubjectConfirmationData></saml:SubjectConfirmation></saml:Subject>"#,
+                r#"<saml:Conditions NotBefore="{}" NotOnOrAfter="{}"><saml:AudienceRestriction><saml:Audience>{}</saml:Audience></saml:AudienceRestriction></saml:Conditions>"#,
+                r#"<saml:AuthnStatement AuthnInstant="{}" SessionIndex="{}"><saml:AuthnContext><saml:AuthnContextClassRef>urn:oasis:names:tc:SAML:2.0:ac:classes:PasswordProtectedTransport</saml:AuthnContextClassRef></saml:AuthnContext></saml:AuthnStatement>"#,
+                r#"<saml:AttributeStatement>{}</saml:AttributeStatement>"#,
+            ),
+            NAME_ID_PERSISTENT, escape_text(&user.id),
+            in_response_to, instant(now + TimeDelta::minutes(5)), escape_attribute(&settings.acs_url),
+            instant(now - TimeDelta::minutes(1)), instant(now + TimeDelta::minutes(5)), escape_text(&settings.entity_id),
+            instant(now), session_index(session),
+            attributes,
+        );
+        let head = format!(
+            r#"<saml:Assertion xmlns:saml="{}" ID="{}" IssueInstant="{}" Version="2.0"><saml:Issuer>{}</saml:Issuer>"#,
+            NS_ASSERTION, assertion_id, instant(now), issuer,
+        );
+        let digest = STANDARD.encode(Sha256::digest(format!("{}{}</saml:Assertion>", head, subject)));
+
+        let signed_info = format!(
+            concat!(
+                r#"<ds:SignedInfo xmlns:ds="{}"><ds:CanonicalizationMethod Algorithm="{}"></ds:CanonicalizationMethod><ds:SignatureMethod Algorithm="{}"></ds:SignatureMethod>"#,
+                r##"<ds:Reference URI="#{}"><ds:Transforms><ds:Transform Algorithm="http://www.w3.org/2000/09/xmldsig#enveloped-signature"></ds:Transform><ds:Transform Algorithm="{}"></ds:Transform></ds:Transforms>"##,
+                r#"<ds:DigestMethod Algorithm="{}"></ds:DigestMethod><ds:DigestValue>{}</ds:DigestValue></ds:Reference></ds:SignedInfo>"#,
+            ),
+            NS_DSIG, EXCLUSIVE_C14N, RSA_SHA256,
+            assertion_id, EXCLUSIVE_C14N,
+            SHA256, digest,
+        );
+        let signature = format!(
+            r#"<ds:Signature xmlns:ds="{}">{}<ds:SignatureValue>{}</ds:SignatureValue><ds:KeyInfo><ds:X509Data><ds:X509Certificate>{}</ds:X509Certificate></ds:X509Data></ds:KeyInfo></ds:Signature>"#,
+            NS_DSIG,
+            signed_info.replacen(&format!(r#" xmlns:ds="{}""#, NS_DSIG), "", 1),
+            STANDARD.encode(sign(keys, signed_info.as_bytes())),
+            keys.certificate,
+        );
+
+        let response = format!(
+            concat!(
+                r#"<samlp:Response xmlns:samlp="{}" xmlns:saml="{}" Destination="{}" ID="{}"{} IssueInstant="{}" Version="2.0">"#,
+                r#"<saml:Issuer>{}</saml:Issuer><samlp:Status><samlp:StatusCode Value="{}"></samlp:StatusCode></samlp:Status>"#,
+                r#"{}{}{}</saml:Assertion></samlp:Response>"#,
+            ),
+            NS_PROTOCOL, NS_ASSERTION, escape_attribute(&settings.acs_url), new_id(), in_response_to, instant(now),
+            issuer, STATUS_SUCCESS,
+            head, signature, subject,
+        );
+
+        tracing::info!(email = %user.email, application = %application.client_id, "SAML assertion issued");
+        Ok(SamlPost {
+            acs_url: settings.acs_url.clone(),
+            response: STANDARD.encode(response),
+            relay_state,
+        })
+    }
+}
+
+/// The DER content of the first block of a PEM file
+fn pem_to_der(pem: &str) -> Option<Vec<u8>> {
+    let body: String = pem.lines()
+        .map(str::trim)
+        .skip_while(|line| !line.starts_with("-----BEGIN"))
+        .skip(1)
+        .take_while(|line| !line.starts_with("-----END"))
+        .collect();
+    STANDARD.decode(body).ok().filter(|der| !der.is_empty())
+}
+
+fn sign(keys: &SigningKeys, message: &[u8]) -> Vec<u8> {
+    let mut signature = vec![0; keys.key_pair.public().modulus_len()];
+    keys.key_pair.sign(&signature::RSA_PKCS1_SHA256, &SystemRandom::new(), message, &mut signature)
+        .expect("signing with a valid RSA key can not fail");
+    signature
+}
+
+/// The redirect binding signs the query string as it was sent, not the decoded values
+fn verify_redirect_signature(query: &str, parameter: &str, params: &HashMap<String, String>, cert: &str) -> Result<(), SamlError> {
+    let (Some(algorithm), Some(signature)) = (params.get("SigAlg"), params.get("Signature")) else {
+        return Err(SamlError::SignatureRequired);
+    };
+    if algorithm != RSA_SHA256 {
+        return Err(SamlError::InvalidSignature);
+    }
+
+    let raw: HashMap<&str, &str> = query.split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .collect();
+    let signed = [parameter, "RelayState", "SigAlg"].iter()
+        .filter_map(|name| raw.get(name).map(|value| format!("{}={}", name, value)))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    let signature = STANDARD.decode(signature).map_err(|_| SamlError::InvalidSignature)?;
+    let der = pem_to_der(cert).ok_or(SamlError::InvalidSignature)?;
+    let (_, certificate) = x509_parser::parse_x509_certificate(&der).map_err(|_| SamlError::InvalidSignature)?;
+    UnparsedPublicKey::new(&signature::RSA_PKCS1_2048_8192_SHA256, &certificate.public_key().subject_public_key.data)
+        .verify(signed.as_bytes(), &signature)
+        .map_err(|_| SamlError::InvalidSignature)
+}
+
+/// Read the fields used from an `AuthnRequest` or a `LogoutRequest`
+fn parse_message(xml: &str, element: &str) -> Result<SamlMessage, SamlError> {
+    let invalid = |reason: &str| SamlError::InvalidRequest(reason.to_string());
+    let mut reader = Reader::from_str(xml);
+    let mut message = SamlMessage { id: String::new(), issuer: String::new(), acs_url: None, name_id: None };
+    let mut root = None;
+    let mut text_of = None;
+
+    loop {
+        match reader.read_event().map_err(|_| invalid("malformed xml"))? {
+            Event::Start(tag) | Event::Empty(tag) => {
+                let name = tag.local_name();
+                let name = name.as_ref();
+                if root.is_none() {
+                    if name != element {
+                        return Err(invalid(&format!("{} expected", element)));
+                    }
+                    for attribute in tag.attributes() {
+                        let attribute = attribute.map_err(|_| invalid("malformed xml"))?;
+                        let value = attribute.normalized_value(XmlVersion::Implicit1_0)
+                            .map_err(|_| invalid("malformed xml"))?
+                            .into_owned();
+                        match attribute.key.local_name().as_ref() {
+                            "ID" => message.id = value,
+                            "AssertionConsumerServiceURL" => message.acs_url = Some(value),
+                            _ => {}
+                        }
+                    }
+                    root = Some(());
+                } else {
+                    text_of = match name {
+                        "Issuer" => Some("Issuer"),
+                        "NameID" => Some("NameID"),
+                        _ => None,
+                    };
+                }
+            }
+            Event::Text(text) => {
+                let text = text.xml10_content().trim().to_string();
+                match text_of.take() {
+                    Some("Issuer") if message.issuer.is_empty() => message.issuer = text,
+                    Some("NameID") if message.name_id.is_none() => message.name_id = Some(text),
+                    _ => {}
+                }
+            }
+            Event::End(_) => text_of = None,
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    if message.id.is_empty() || message.issuer.is_empty() {
+        return Err(invalid("ID and Issuer are required"));
+    }
+    Ok(message)
+}
+
+fn attribute<'a>(name: &str, values: impl IntoIterator<Item = &'a String>) -> String {
+    let values: String = values.into_iter()
+        .map(|value| format!("<saml:AttributeValue>{}</saml:AttributeValue>", escape_text(value)))
+        .collect();
+    format!(
+        r#"<saml:Attribute Name="{}" NameFormat="urn:oasis:names:tc:SAML:2.0:attrname-format:basic">{}</saml:Attribute>"#,
+        name, values,
+    )
+}
+
+/// Identifiers must be valid xml names, so they can not start with a digit
+fn new_id() -> String {
+    let value: String = rand::rng().sample_iter(&Alphanumeric).take(32).map(char::from).collect();
+    format!("_{}", value)
+}
+
+fn instant(date: DateTime<Utc>) -> String {
+    date.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+/// Ties the assertion to the session without disclosing its token
+fn session_index(session: &str) -> String {
+    Sha256::digest(session.as_bytes())[..16].iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Text escaped as exclusive canonicalization writes it
+fn escape_text(value: &str) -> String {
+    value.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\r', "&#xD;")
+}
+
+/// Attribute values escaped as exclusive canonicalization writes them
+fn escape_attribute(value: &str) -> String {
+    value.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('"', "&quot;")
+        .replace('\t', "&#x9;")
+        .replace('\n', "&#xA;")
+        .replace('\r', "&#xD;")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::path::PathBuf;
+    use super::*;
+    use crate::objects::config::SamlIdpConfig;
+    use crate::services::clock::MockClock;
+    use bergshamra_dsig::{DsigContext, VerifyResult};
+    use bergshamra_keys::KeysManager;
+
+    fn testdata(file: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("testdata").join(file)
+    }
+
+    async fn get_service() -> (SamlService, User) {
+        let config = Config {
+            saml: Some(SamlIdpConfig {
+                key_file: testdata("saml_idp_key.pem"),
+                cert_file: testdata("saml_idp_cert.pem"),
+            }),
+            ..Config::default()
+        };
+        let repos = Repos::new(&config, &Metrics::new());
+        let clock = Arc::new(MockClock::new(Utc::now()));
+        let auth = AuthService::new(config.clone(), repos.clone(), Metrics::new(), clock.clone());
+        let admin = repos.user_repo.get_by_email("admin@example.com").await.unwrap();
+
+        let cert = fs::read_to_string(testdata("saml_sp_cert.pem")).unwrap();
+        let applications = [
+            ("wiki", HashSet::from([admin.id.clone()]), Some(cert.clone())),
+            ("crm", HashSet::new(), Some(cert)),
+            ("intranet", HashSet::from([admin.id.clone()]), None),
+        ];
+        for (client_id, users, signing_cert) in applications {
+            repos.application_repo.add(Application {
+                name: client_id.to_string(),
+                url: format!("https://{}.example.com", client_id),
+                icon_url: None,
+                client_id: client_id.to_string(),
+                client_secret: "secret".to_string(),
+                protocol: Protocol::Saml(SamlSettings {
+                    entity_id: format!("https://{}.example.com/saml", client_id),
+                    acs_url: format!("https://{}.example.com/saml/acs", client_id),
+                    slo_url: Some(format!("https://{}.example.com/saml/slo", client_id)),
+                    signing_cert,
+                }),
+                scopes: HashSet::new(),
+                users,
+                pre_consented: false,
+            }).await;
+        }
+        (SamlService::new(config, repos, Metrics::new(), clock, auth), admin)
+    }
+
+    fn between<'a>(xml: &'a str, start: &str, end: &str) -> &'a str {
+        let from = xml.find(start).unwrap();
+        let to = xml[from..].find(end).unwrap() + from + end.len();
+        &xml[from..to]
+    }
+
+    /// An `AuthnRequest` signed for the redirect binding with the key of the application
+    fn signed_query(acs_url: &str, relay_state: &str) -> String {
+        let request = format!(
+            r#"<samlp:AuthnRequest xmlns:samlp="{}" xmlns:saml="{}" ID="_request" Version="2.0" AssertionConsumerServiceURL="{}"><saml:Issuer>https://wiki.example.com/saml</saml:Issuer></samlp:AuthnRequest>"#,
+            NS_PROTOCOL, NS_ASSERTION, acs_url,
+        );
+        sign_request(&request, relay_state)
+    }
+
+    /// A `LogoutRequest` of the application for the user, signed like `signed_query`
+    fn logout_query(client_id: &str, user: &str) -> String {
+        let request = format!(
+            r#"<samlp:LogoutRequest xmlns:samlp="{}" xmlns:saml="{}" ID="_logout" Version="2.0"><saml:Issuer>https://{}.example.com/saml</saml:Issuer><saml:NameID>{}</saml:NameID></samlp:LogoutRequest>"#,
+            NS_PROTOCOL, NS_ASSERTION, client_id, user,
+        );
+        sign_request(&request, "/")
+    }
+
+    fn sign_request(request: &str, relay_state: &str) -> String {
+        let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+        encoder.write_all(request.as_bytes()).unwrap();
+        let signed = serde_urlencoded::to_string([
+            ("SAMLRequest", STANDARD.encode(encoder.finish().unwrap()).as_str()),
+            ("RelayState", relay_state),
+            ("SigAlg", RSA_SHA256),
+        ]).unwrap();
+
+        let key = pem_to_der(&fs::read_to_string(testdata("saml_sp_key.pem")).unwrap()).unwrap();
+        let keys = SigningKeys { key_pair: RsaKeyPair::from_pkcs8(&key).unwrap(), certificate: String::new() };
+        let signature = serde_urlencoded::to_string([("Signature", STANDARD.encode(sign(&keys, signed.as_bytes())))]).unwrap();
+        format!("{}&{}", signed, signature)
+    }
+
+    #[actix_web::test]
+    async fn test_assertion_is_signed() {
+        let (service, admin) = get_service().await;
+
+        let post = service.idp_initiated(&admin, "token", "wiki").await.ok().unwrap();
+        assert_eq!(post.acs_url, "https://wiki.example.com/saml/acs");
+        let response = String::from_utf8(STANDARD.decode(post.response).unwrap()).unwrap();
+        assert!(response.contains(&format!(">{}</saml:NameID>", admin.id)));
+
+        // The digest covers the assertion without its signature
+        let assertion = between(&response, "<saml:Assertion ", "</saml:Assertion>");
+        let signature = between(assertion, "<ds:Signature ", "</ds:Signature>");
+        let digest = STANDARD.encode(Sha256::digest(assertion.replace(signature, "")));
+        assert!(signature.contains(&format!("<ds:DigestValue>{}</ds:DigestValue>", digest)));
+
+        let signed_info = between(signature, "<ds:SignedInfo>", "</ds:SignedInfo>")
+            .replacen("<ds:SignedInfo>", &format!(r#"<ds:SignedInfo xmlns:ds="{}">"#, NS_DSIG), 1);
+        let value = between(signature, "<ds:SignatureValue>", "</ds:SignatureValue>");
+        let value = STANDARD.decode(&value["<ds:SignatureValue>".len()..value.len() - "</ds:SignatureValue>".len()]).unwrap();
+        let cert = pem_to_der(&fs::read_to_string(testdata("saml_idp_cert.pem")).unwrap()).unwrap();
+        let (_, cert) = x509_parser::parse_x509_certificate(&cert).unwrap();
+        let key = UnparsedPublicKey::new(&signature::RSA_PKCS1_2048_8192_SHA256, &cert.public_key().subject_public_key.data);
+        assert!(key.verify(signed_info.as_bytes(), &value).is_ok());
+
+        // Checked by an XML-DSig implementation, which canonicalizes the parsed document
+        let idp_cert = fs::read(testdata("saml_idp_cert.pem")).unwrap();
+        let mut keys = KeysManager::new();
+        keys.add_key(bergshamra_keys::loader::load_x509_cert_pem(&idp_cert).unwrap());
+        let context = DsigContext::new(keys);
+        let verified = bergshamra_dsig::verify::verify(&context, &response).unwrap();
+        let VerifyResult::Valid { references, .. } = verified else {
+            panic!("invalid signature: {:?}", verified);
+        };
+        // and covers the assertion
+        let id = assertion.split(r#" ID=""#).nth(1).unwrap().split('"').next().unwrap();
+        assert_eq!(vec![format!("#{}", id)], references.iter().map(|reference| reference.uri.clone()).collect::<Vec<_>>());
+        let tampered = response.replace(&admin.email, "eve@example.com");
+        assert!(!bergshamra_dsig::verify::verify(&context, &tampered).is_ok_and(|result| result.is_valid()));
+
+        assert!(matches!(service.idp_initiated(&admin, "token", "crm").await, Err(SamlError::AccessDenied)));
+        assert!(service.metadata().ok().unwrap().contains(&service.keys.as_ref().unwrap().certificate));
+    }
+
+    #[actix_web::test]
+    async fn test_request_signature() {
+        let (service, admin) = get_service().await;
+
+        let query = signed_query("https://wiki.example.com/saml/acs", "/page");
+        let post = service.single_sign_on(&admin, "token", &query).await.ok().unwrap();
+        assert_eq!(post.relay_state.as_deref(), Some("/page"));
+        let response = String::from_utf8(STANDARD.decode(post.response).unwrap()).unwrap();
+        assert!(response.contains(r#"InResponseTo="_request""#));
+
+        let tampered = query.replace("RelayState=%2Fpage", "RelayState=%2Fother");
+        assert!(matches!(service.single_sign_on(&admin, "token", &tampered).await, Err(SamlError::InvalidSignature)));
+
+        let unsigned = query.split("&SigAlg").next().unwrap();
+        assert!(matches!(service.single_sign_on(&admin, "token", unsigned).await, Err(SamlError::SignatureRequired)));
+
+        let query = signed_query("https://evil.example.com/acs", "/page");
+        assert!(matches!(service.single_sign_on(&admin, "token", &query).await, Err(SamlError::AcsMismatch)));
+    }
+
+    #[actix_web::test]
+    async fn test_single_logout() {
+        let (service, admin) = get_service().await;
+        let session = service.auth.open_session(admin.clone()).await.ok().unwrap().value;
+
+        let url = service.single_logout(Some((&admin, &session)), &logout_query("wiki", &admin.id)).await.ok().unwrap();
+        assert!(url.starts_with("https://wiki.example.com/saml/slo?SAMLResponse="));
+        assert!(url.contains("&Signature="));
+        assert!(service.auth.authenticate(&session).await.is_err());
+
+        // Without a certificate, nothing proves the application sent the request
+        let session = service.auth.open_session(admin.clone()).await.ok().unwrap().value;
+        let unsigned = logout_query("intranet", &admin.id);
+        let unsigned = unsigned.split("&SigAlg").next().unwrap();
+        assert!(matches!(service.single_logout(Some((&admin, &session)), unsigned).await, Err(SamlError::SignatureRequired)));
+        assert!(service.auth.authenticate(&session).await.is_ok());
+    }
+}
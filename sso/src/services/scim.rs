@@ -0,0 +1,771 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use chrono::SecondsFormat;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use crate::errors::repo::RepoError;
+use crate::errors::scim::ScimError;
+use crate::forms::scim::ScimQuery;
+use crate::forms::validation::{EMAIL_REGEX, LOCALE_REGEX, TIMEZONE_REGEX};
+use crate::objects::audit_event::{AuditAction, AuditEvent};
+use crate::objects::config::Config;
+use crate::objects::group::Group;
+use crate::objects::user::{StatusChange, User, UserStatus};
+use crate::services::account::AccountService;
+use crate::services::auth::AuthService;
+use crate::services::clock::Clock;
+use crate::services::factory::Repos;
+use crate::services::metrics::Metrics;
+use crate::services::password::PasswordService;
+use crate::services::scim_filter::{field, Filter, PatchPath};
+
+pub const USER_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+pub const GROUP_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:Group";
+pub const LIST_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+pub const PATCH_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:PatchOp";
+pub const ERROR_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:Error";
+const SERVICE_PROVIDER_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:ServiceProviderConfig";
+/// Largest page returned by the list endpoints
+const MAX_RESULTS: usize = 200;
+
+/// SCIM 2.0 provisioning (RFC 7643 and 7644) of the users and their groups
+pub struct ScimService {
+    config: Config,
+    repos: Repos,
+    metrics: Metrics,
+    clock: Arc<dyn Clock>,
+    account: AccountService,
+    passwords: PasswordService,
+}
+
+impl ScimService {
+    pub fn new(config: Config, repos: Repos, metrics: Metrics, clock: Arc<dyn Clock>, account: AccountService) -> Self {
+        Self {
+            passwords: PasswordService::new(config.password_policy.clone()),
+            config,
+            repos,
+            metrics,
+            clock,
+            account,
+        }
+    }
+
+    /// The name of the provisioning client owning the bearer token
+    pub fn authenticate(&self, token: &str) -> Option<&str> {
+        // Hashed so that the comparison time does not depend on the token
+        let hash = Sha256::digest(token.as_bytes());
+        self.config.provisioning_clients.iter()
+            .find(|client| Sha256::digest(client.token.as_bytes()) == hash)
+            .map(|client| client.name.as_str())
+    }
+
+    fn location(&self, resource: &str, id: &str) -> String {
+        format!("{}/scim/v2/{}/{}", self.config.public_url, resource, id)
+    }
+
+    pub fn service_provider_config(&self) -> Value {
+        json!({
+            "schemas": [SERVICE_PROVIDER_SCHEMA],
+            "patch": { "supported": true },
+            "bulk": { "supported": false, "maxOperations": 0, "maxPayloadSize": 0 },
+            "filter": { "supported": true, "maxResults": MAX_RESULTS },
+            "changePassword": { "supported": true },
+            "sort": { "supported": false },
+            "etag": { "supported": false },
+            "authenticationSchemes": [{
+                "type": "oauthbearertoken",
+                "name": "Bearer token",
+                "description": "A token given to the provisioning client",
+            }],
+            "meta": {
+                "resourceType": "ServiceProviderConfig",
+                "location": format!("{}/scim/v2/ServiceProviderConfig", self.config.public_url),
+            },
+        })
+    }
+
+    async fn users(&self) -> Vec<User> {
+        let mut users = self.repos.user_repo.get_all().await;
+        users.sort_by(|a, b| a.created.cmp(&b.created).then_with(|| a.id.cmp(&b.id)));
+        users
+    }
+
+    async fn user(&self, id: &str) -> Result<User, ScimError> {
+        self.repos.user_repo.get_by_id(id).await.ok_or(ScimError::NotFound)
+    }
+
+    async fn save_user(&self, user: &User) -> Result<(), ScimError> {
+        self.repos.user_repo.add(user.clone()).await.map_err(|e| match e {
+            RepoError::Conflict(_) => ScimError::Uniqueness,
+            _ => ScimError::Unavailable,
+        })
+    }
+
+    /// Every group by name, including the groups only known by name on the given users.
+    /// Those are not stored by a read, they are created with their oldest member
+    async fn groups(&self, users: &[User]) -> HashMap<String, Group> {
+        let mut groups: HashMap<String, Group> = self.repos.group_repo.get_all().await
+            .into_iter()
+            .map(|group| (group.name.clone(), group))
+            .collect();
+
+        let mut named: HashMap<String, Group> = HashMap::new();
+        for user in users {
+            for name in user.groups.iter().filter(|name| !groups.contains_key(*name)) {
+                named.entry(name.clone())
+                    .and_modify(|group| group.created = group.created.min(user.created))
+                    .or_insert_with(|| Group::named(name, user.created));
+            }
+        }
+        groups.extend(named);
+        groups
+    }
+
+    fn user_resource(&self, user: &User, groups: &HashMap<String, Group>) -> Value {
+        let mut memberships: Vec<&Group> = user.groups.iter().filter_map(|name| groups.get(name)).collect();
+        memberships.sort_by(|a, b| a.name.cmp(&b.name));
+
+        json!({
+            "schemas": [USER_SCHEMA],
+            "id": user.id,
+            "userName": user.email,
+            "name": { "formatted": user.name },
+            "displayName": user.name,
+            "emails": [{ "value": user.email, "type": "work", "primary": true }],
+            "active": user.status == UserStatus::Active,
+            "locale": user.locale,
+            "timezone": user.timezone,
+            "groups": memberships.iter().map(|group| json!({
+                "value": group.id,
+                "display": group.name,
+                "$ref": self.location("Groups", &group.id),
+            })).collect::<Vec<_>>(),
+            "meta": {
+                "resourceType": "User",
+                "created": user.created.to_rfc3339_opts(SecondsFormat::Secs, true),
+                "location": self.location("Users", &user.id),
+            },
+        })
+    }
+
+    fn group_resource(&self, group: &Group, users: &[User]) -> Value {
+        json!({
+            "schemas": [GROUP_SCHEMA],
+            "id": group.id,
+            "displayName": group.name,
+            "members": users.iter().filter(|user| user.groups.contains(&group.name)).map(|user| json!({
+                "value": user.id,
+                "display": user.email,
+                "type": "User",
+                "$ref": self.location("Users", &user.id),
+            })).collect::<Vec<_>>(),
+            "meta": {
+                "resourceType": "Group",
+                "created": group.created.to_rfc3339_opts(SecondsFormat::Secs, true),
+                "location": self.location("Groups", &group.id),
+            },
+        })
+    }
+
+    /// Filter, page and project resources into a `ListResponse`
+    fn list(&self, resources: Vec<Value>, query: &ScimQuery) -> Result<Value, ScimError> {
+        let filter = query.filter.as_deref().map(Filter::parse).transpose()?;
+        let resources: Vec<Value> = resources.into_iter()
+            .filter(|resource| filter.as_ref().is_none_or(|filter| filter.matches(resource)))
+            .collect();
+
+        let start = query.start_index.unwrap_or(1).max(1);
+        let count = query.count.unwrap_or(MAX_RESULTS).min(MAX_RESULTS);
+        let page: Vec<Value> = resources.iter()
+            .skip(start - 1)
+            .take(count)
+            .map(|resource| query.project(resource.clone()))
+            .collect();
+
+        Ok(json!({
+            "schemas": [LIST_SCHEMA],
+            "totalResults": resources.len(),
+            "startIndex": start,
+            "itemsPerPage": page.len(),
+            "Resources": page,
+        }))
+    }
+
+    pub async fn list_users(&self, query: &ScimQuery) -> Result<Value, ScimError> {
+        let users = self.users().await;
+        let groups = self.groups(&users).await;
+        self.list(users.iter().map(|user| self.user_resource(user, &groups)).collect(), query)
+    }
+
+    pub async fn get_user(&self, id: &str) -> Result<Value, ScimError> {
+        let user = self.user(id).await?;
+        let groups = self.groups(&self.users().await).await;
+        Ok(self.user_resource(&user, &groups))
+    }
+
+    pub async fn create_user(&self, client: &str, resource: &Value) -> Result<Value, ScimError> {
+        let mut user = User {
+            id: User::new_id(),
+            name: String::new(),
+            email: String::new(),
+            // Replaced when the resource has a password, this one can never be entered
+            password: AuthService::generate_value(),
+            admin: false,
+            groups: HashSet::new(),
+            status: UserStatus::Active,
+            status_change: None,
+            failed_logins: 0,
+            provider: None,
+            federated: HashMap::new(),
+            avatar_url: None,
+            timezone: "UTC".to_string(),
+            locale: "en".to_string(),
+            deletion_scheduled: None,
+            created: self.clock.now(),
+        };
+        let active = self.apply_user(&mut user, resource).await?;
+        if active == Some(false) {
+            self.set_status(client, &mut user, false);
+        }
+        self.save_user(&user).await?;
+
+        tracing::info!(client, email = %user.email, "user provisioned");
+        self.metrics.registrations.with_label_values(&["scim"]).inc();
+        self.get_user(&user.id).await
+    }
+
+    pub async fn replace_user(&self, client: &str, id: &str, resource: &Value) -> Result<Value, ScimError> {
+        let mut user = self.user(id).await?;
+        self.update_user(client, &mut user, resource).await?;
+        self.get_user(id).await
+    }
+
+    pub async fn patch_user(&self, client: &str, id: &str, patch: &Value) -> Result<Value, ScimError> {
+        let mut user = self.user(id).await?;
+        let mut resource = self.get_user(id).await?;
+        apply_patch(&mut resource, patch)?;
+        self.update_user(client, &mut user, &resource).await?;
+        self.get_user(id).await
+    }
+
+    pub async fn delete_user(&self, client: &str, id: &str) -> Result<(), ScimError> {
+        self.account.delete_account(id, "system").await.map_err(|_| ScimError::NotFound)?;
+        tracing::info!(client, id, "user deprovisioned");
+        Ok(())
+    }
+
+    async fn update_user(&self, client: &str, user: &mut User, resource: &Value) -> Result<(), ScimError> {
+        let before = user.clone();
+        let active = self.apply_user(user, resource).await?;
+        let status_action = active.and_then(|active| self.set_status(client, user, active));
+        self.save_user(user).await?;
+
+        if user.status != UserStatus::Active && before.status == UserStatus::Active {
+            self.repos.login_token_repo.delete_for_user(&user.id, None, self.clock.now()).await;
+        }
+        let mut actions = vec![];
+        if user.email != before.email {
+            actions.push(AuditAction::EmailChanged);
+        }
+        if user.password != before.password {
+            actions.push(AuditAction::PasswordChanged);
+        }
+        if (&user.name, &user.locale, &user.timezone) != (&before.name, &before.locale, &before.timezone) {
+            actions.push(AuditAction::ProfileUpdated);
+        }
+        actions.extend(status_action);
+        for action in actions {
+            self.repos.audit_repo.add(AuditEvent::new(self.clock.now(), "system", action, &user.id)).await;
+        }
+
+        tracing::info!(client, email = %user.email, "user updated");
+        Ok(())
+    }
+
+    /// Read the writable attributes of a resource into the user, returning the requested `active`
+    async fn apply_user(&self, user: &mut User, resource: &Value) -> Result<Option<bool>, ScimError> {
+        let text = |name: &str| field(resource, name).and_then(Value::as_str).map(str::trim).filter(|value| !value.is_empty());
+
+        let email = text("userName").ok_or_else(|| ScimError::InvalidValue("userName is required".to_string()))?;
+        if !EMAIL_REGEX.is_match(email) {
+            return Err(ScimError::InvalidValue("userName must be an email".to_string()));
+        }
+        user.email = email.to_string();
+
+        let name = field(resource, "name");
+        let formatted = name.and_then(|name| field(name, "formatted")).and_then(Value::as_str);
+        let parts: Vec<&str> = ["givenName", "familyName"].iter()
+            .filter_map(|part| name.and_then(|name| field(name, part)).and_then(Value::as_str))
+            .collect();
+        let name = text("displayName")
+            .or(formatted.map(str::trim).filter(|name| !name.is_empty()))
+            .map(str::to_string)
+            .or_else(|| Some(parts.join(" ")).filter(|name| !name.trim().is_empty()));
+        match name {
+            Some(name) => user.name = name,
+            None if user.name.is_empty() => user.name = email.split('@').next().unwrap_or(email).to_string(),
+            None => {}
+        }
+
+        if let Some(locale) = text("locale") {
+            if !LOCALE_REGEX.is_match(locale) {
+                return Err(ScimError::InvalidValue("locale must look like en-US".to_string()));
+            }
+            user.locale = locale.to_string();
+        }
+        if let Some(timezone) = text("timezone") {
+            if !TIMEZONE_REGEX.is_match(timezone) {
+                return Err(ScimError::InvalidValue("timezone must look like Europe/Paris".to_string()));
+            }
+            user.timezone = timezone.to_string();
+        }
+        if let Some(password) = field(resource, "password").and_then(Value::as_str) {
+            if !self.passwords.check(password, &user.email, &user.name).await.is_empty() {
+                return Err(ScimError::InvalidValue("the password does not meet the policy".to_string()));
+            }
+            user.password = password.to_string();
+        }
+
+        // Some clients send booleans as strings
+        match field(resource, "active") {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::Bool(active)) => Ok(Some(*active)),
+            Some(Value::String(active)) if active.eq_ignore_ascii_case("true") => Ok(Some(true)),
+            Some(Value::String(active)) if active.eq_ignore_ascii_case("false") => Ok(Some(false)),
+            Some(_) => Err(ScimError::InvalidValue("active must be a boolean".to_string())),
+        }
+    }
+
+    /// Activate or deactivate the account, returning what to record in the audit log
+    fn set_status(&self, client: &str, user: &mut User, active: bool) -> Option<AuditAction> {
+        let action = match (active, user.status) {
+            (true, UserStatus::Active) | (false, UserStatus::Disabled) => return None,
+            (true, UserStatus::Pending) => AuditAction::AccountApproved,
+            (true, _) => AuditAction::AccountEnabled,
+            (false, _) => AuditAction::AccountDisabled,
+        };
+        if active {
+            user.status = UserStatus::Active;
+            user.status_change = None;
+            user.failed_logins = 0;
+        } else {
+            user.status = UserStatus::Disabled;
+            user.status_change = Some(StatusChange {
+                by: "system".to_string(),
+                reason: format!("Deactivated by {}", client),
+                date: self.clock.now(),
+            });
+        }
+        Some(action)
+    }
+
+    async fn group(&self, id: &str) -> Result<Group, ScimError> {
+        if let Some(group) = self.repos.group_repo.get_by_id(id).await {
+            return Ok(group);
+        }
+        self.groups(&self.users().await).await
+            .into_values()
+            .find(|group| group.id == id)
+            .ok_or(ScimError::NotFound)
+    }
+
+    pub async fn list_groups(&self, query: &ScimQuery) -> Result<Value, ScimError> {
+        let users = self.users().await;
+        let mut groups: Vec<Group> = self.groups(&users).await.into_values().collect();
+        groups.sort_by(|a, b| a.created.cmp(&b.created).then_with(|| a.id.cmp(&b.id)));
+        self.list(groups.iter().map(|group| self.group_resource(group, &users)).collect(), query)
+    }
+
+    pub async fn get_group(&self, id: &str) -> Result<Value, ScimError> {
+        let group = self.group(id).await?;
+        Ok(self.group_resource(&group, &self.users().await))
+    }
+
+    pub async fn create_group(&self, client: &str, resource: &Value) -> Result<Value, ScimError> {
+        let group = Group { id: User::new_id(), name: group_name(resource)?, created: self.clock.now() };
+        if self.repos.group_repo.get_by_name(&group.name).await.is_some() {
+            return Err(ScimError::Uniqueness);
+        }
+        let users = self.users().await;
+        let members = group_members(resource, &users)?;
+        self.save_group(&group).await?;
+        self.set_members(&group.name, &members, &users).await?;
+
+        tracing::info!(client, group = %group.name, "group provisioned");
+        self.get_group(&group.id).await
+    }
+
+    pub async fn replace_group(&self, client: &str, id: &str, resource: &Value) -> Result<Value, ScimError> {
+        let group = self.group(id).await?;
+        self.update_group(client, group, resource).await?;
+        self.get_group(id).await
+    }
+
+    pub async fn patch_group(&self, client: &str, id: &str, patch: &Value) -> Result<Value, ScimError> {
+        let group = self.group(id).await?;
+        let mut resource = self.group_resource(&group, &self.users().await);
+        apply_patch(&mut resource, patch)?;
+        self.update_group(client, group, &resource).await?;
+        self.get_group(id).await
+    }
+
+    pub async fn delete_group(&self, client: &str, id: &str) -> Result<(), ScimError> {
+        let group = self.group(id).await?;
+        self.set_members(&group.name, &HashSet::new(), &self.users().await).await?;
+        self.repos.group_repo.delete(id).await;
+        tracing::info!(client, group = %group.name, "group deleted");
+        Ok(())
+    }
+
+    async fn save_group(&self, group: &Group) -> Result<(), ScimError> {
+        self.repos.group_repo.add(group.clone()).await.map_err(|e| match e {
+            RepoError::Conflict(_) => ScimError::Uniqueness,
+            _ => ScimError::Unavailable,
+        })
+    }
+
+    async fn update_group(&self, client: &str, mut group: Group, resource: &Value) -> Result<(), ScimError> {
+        let name = group_name(resource)?;
+        let users = self.users().await;
+        let members = group_members(resource, &users)?;
+
+        // a group only known by name on its members is stored here
+        let previous = std::mem::replace(&mut group.name, name);
+        self.save_group(&group).await?;
+        if previous != group.name {
+            for mut user in users.iter().filter(|user| user.groups.contains(&previous)).cloned() {
+                user.groups.remove(&previous);
+                user.groups.insert(group.name.clone());
+                self.save_user(&user).await?;
+            }
+        }
+        self.set_members(&group.name, &members, &self.users().await).await?;
+
+        tracing::info!(client, group = %group.name, members = members.len(), "group updated");
+        Ok(())
+    }
+
+    /// Make the given users the only members of the group
+    async fn set_members(&self, name: &str, members: &HashSet<String>, users: &[User]) -> Result<(), ScimError> {
+        for user in users {
+            if members.contains(&user.id) != user.groups.contains(name) {
+                let mut user = user.clone();
+                match members.contains(&user.id) {
+                    true => user.groups.insert(name.to_string()),
+                    false => user.groups.remove(name),
+                };
+                self.save_user(&user).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn group_name(resource: &Value) -> Result<String, ScimError> {
+    field(resource, "displayName")
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .ok_or_else(|| ScimError::InvalidValue("displayName is required".to_string()))
+}
+
+/// The user ids listed in the `members` of a group resource, they must all exist
+fn group_members(resource: &Value, users: &[User]) -> Result<HashSet<String>, ScimError> {
+    let ids: HashSet<&str> = users.iter().map(|user| user.id.as_str()).collect();
+    let members = match field(resource, "members") {
+        None | Some(Value::Null) => return Ok(HashSet::new()),
+        Some(Value::Array(members)) => members,
+        Some(_) => return Err(ScimError::InvalidValue("members must be a list".to_string())),
+    };
+    members.iter()
+        .map(|member| match field(member, "value").and_then(Value::as_str) {
+            Some(id) if ids.contains(id) => Ok(id.to_string()),
+            Some(id) => Err(ScimError::InvalidValue(format!("unknown member {}", id))),
+            None => Err(ScimError::InvalidValue("members need a value".to_string())),
+        })
+        .collect()
+}
+
+/// The key of an object field, attribute names are case insensitive
+fn key(object: &Map<String, Value>, name: &str) -> String {
+    object.keys()
+        .find(|key| key.eq_ignore_ascii_case(name))
+        .cloned()
+        .unwrap_or_else(|| name.to_string())
+}
+
+/// Set a value, objects are merged and lists extended when adding
+fn set(object: &mut Map<String, Value>, name: &str, value: Value, add: bool) {
+    let name = key(object, name);
+    match (object.get_mut(&name), value) {
+        (Some(Value::Array(items)), Value::Array(values)) if add => {
+            items.extend(values.into_iter().filter(|value| !items.contains(value)).collect::<Vec<_>>())
+        }
+        (Some(Value::Array(items)), value) if add => {
+            if !items.contains(&value) {
+                items.push(value);
+            }
+        }
+        (Some(Value::Object(current)), Value::Object(values)) => {
+            for (name, value) in values {
+                set(current, &name, value, add);
+            }
+        }
+        (_, value) => {
+            object.insert(name, value);
+        }
+    }
+}
+
+/// Apply a `PatchOp` message to the JSON representation of a resource
+fn apply_patch(resource: &mut Value, patch: &Value) -> Result<(), ScimError> {
+    let schemas = field(patch, "schemas").and_then(Value::as_array);
+    if !schemas.is_some_and(|schemas| schemas.iter().any(|schema| schema == PATCH_SCHEMA)) {
+        return Err(ScimError::InvalidSyntax(format!("{} schema expected", PATCH_SCHEMA)));
+    }
+    let operations = field(patch, "Operations").and_then(Value::as_array)
+        .ok_or_else(|| ScimError::InvalidSyntax("Operations are required".to_string()))?;
+
+    for operation in operations {
+        let op = field(operation, "op").and_then(Value::as_str).unwrap_or_default().to_lowercase();
+        let path = field(operation, "path").and_then(Value::as_str);
+        let value = field(operation, "value").cloned();
+        match (op.as_str(), path) {
+            ("add" | "replace", None) => match value {
+                Some(Value::Object(values)) => {
+                    for (name, value) in values {
+                        apply_operation(resource, &op, &PatchPath::parse(&name)?, Some(value))?;
+                    }
+                }
+                _ => return Err(ScimError::InvalidValue("an object is expected without path".to_string())),
+            },
+            ("add" | "replace" | "remove", Some(path)) => apply_operation(resource, &op, &PatchPath::parse(path)?, value)?,
+            ("remove", None) => return Err(ScimError::NoTarget(String::new())),
+            (op, _) => return Err(ScimError::InvalidSyntax(format!("unknown operation {}", op))),
+        }
+    }
+    Ok(())
+}
+
+fn apply_operation(resource: &mut Value, op: &str, path: &PatchPath, value: Option<Value>) -> Result<(), ScimError> {
+    let object = resource.as_object_mut().ok_or_else(|| ScimError::InvalidSyntax("object expected".to_string()))?;
+    let name = key(object, &path.attribute);
+    let add = op == "add";
+    if op != "remove" && value.is_none() {
+        return Err(ScimError::InvalidValue(format!("{} needs a value", path.attribute)));
+    }
+
+    match (&path.filter, &path.sub_attribute) {
+        (None, None) => match (op, object.get_mut(&name), value) {
+            // Removing values from a list, as sent by some clients instead of a filter
+            ("remove", Some(Value::Array(items)), Some(removed)) => {
+                let removed: Vec<Value> = match removed {
+                    Value::Array(removed) => removed,
+                    removed => vec![removed],
+                };
+                let removed: Vec<&Value> = removed.iter().map(|value| field(value, "value").unwrap_or(value)).collect();
+                items.retain(|item| !removed.contains(&field(item, "value").unwrap_or(item)));
+            }
+            ("remove", _, _) => {
+                object.remove(&name);
+            }
+            (_, _, Some(value)) => set(object, &name, value, add),
+            (_, _, None) => {}
+        },
+        (None, Some(sub)) => {
+            let target = object.entry(name).or_insert_with(|| json!({}));
+            let targets: Vec<&mut Value> = match target {
+                Value::Array(items) => items.iter_mut().collect(),
+                target => vec![target],
+            };
+            for target in targets {
+                let target = target.as_object_mut()
+                    .ok_or_else(|| ScimError::InvalidPath(path.attribute.clone()))?;
+                match &value {
+                    Some(value) if op != "remove" => set(target, sub, value.clone(), add),
+                    _ => {
+                        target.remove(&key(target, sub));
+                    }
+                }
+            }
+        }
+        (Some(filter), sub) => {
+            let items = match object.get_mut(&name) {
+                Some(Value::Array(items)) => items,
+                _ => return Err(ScimError::NoTarget(path.attribute.clone())),
+            };
+            if op == "remove" && sub.is_none() {
+                items.retain(|item| !filter.matches(item));
+                return Ok(());
+            }
+            let mut found = false;
+            for item in items.iter_mut().filter(|item| filter.matches(item)) {
+                found = true;
+                let item = item.as_object_mut().ok_or_else(|| ScimError::InvalidPath(path.attribute.clone()))?;
+                match (sub, &value) {
+                    (Some(sub), _) if op == "remove" => {
+                        item.remove(&key(item, sub));
+                    }
+                    (Some(sub), Some(value)) => set(item, sub, value.clone(), add),
+                    (None, Some(Value::Object(values))) => {
+                        for (name, value) in values {
+                            set(item, name, value.clone(), add);
+                        }
+                    }
+                    _ => return Err(ScimError::InvalidValue(format!("{} needs an object", path.attribute))),
+                }
+            }
+            if !found {
+                return Err(ScimError::NoTarget(path.attribute.clone()));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use super::*;
+    use crate::services::clock::MockClock;
+    use crate::services::mailer::LogMailer;
+
+    fn get_service() -> ScimService {
+        let config = Config::default();
+        let repos = Repos::new(&config, &Metrics::new());
+        let clock = Arc::new(MockClock::new(Utc::now()));
+        let account = AccountService::new(config.clone(), repos.clone(), clock.clone(), Arc::new(LogMailer));
+        ScimService::new(config, repos, Metrics::new(), clock, account)
+    }
+
+    fn patch(operations: Value) -> Value {
+        json!({ "schemas": [PATCH_SCHEMA], "Operations": operations })
+    }
+
+    /// The attributes RFC 7643 requires on every resource
+    fn assert_resource(resource: &Value, schema: &str, resource_type: &str) {
+        assert_eq!(resource["schemas"], json!([schema]));
+        assert!(resource["id"].as_str().is_some_and(|id| !id.is_empty()));
+        assert_eq!(resource["meta"]["resourceType"], resource_type);
+        assert!(resource["meta"]["created"].is_string());
+        assert!(resource["meta"]["location"].as_str().unwrap().ends_with(resource["id"].as_str().unwrap()));
+    }
+
+    #[actix_web::test]
+    async fn test_user_lifecycle() {
+        let s = get_service();
+
+        let created = s.create_user("hr", &json!({
+            "schemas": [USER_SCHEMA],
+            "userName": "bjensen@example.com",
+            "name": { "givenName": "Barbara", "familyName": "Jensen" },
+            "locale": "en-US",
+        })).await.ok().unwrap();
+        assert_resource(&created, USER_SCHEMA, "User");
+        assert_eq!(created["displayName"], "Barbara Jensen");
+        assert_eq!(created["active"], true);
+        let id = created["id"].as_str().unwrap();
+
+        let duplicate = s.create_user("hr", &json!({ "userName": "bjensen@example.com" })).await;
+        assert!(matches!(duplicate, Err(ScimError::Uniqueness)));
+        assert!(matches!(s.create_user("hr", &json!({ "displayName": "x" })).await, Err(ScimError::InvalidValue(_))));
+
+        let list = s.list_users(&ScimQuery { filter: Some(r#"userName eq "BJensen@example.com""#.to_string()), ..ScimQuery::default() }).await.ok().unwrap();
+        assert_eq!(list["schemas"], json!([LIST_SCHEMA]));
+        assert_eq!((list["totalResults"].as_u64(), list["itemsPerPage"].as_u64()), (Some(1), Some(1)));
+        assert_eq!(list["Resources"][0]["id"], id);
+
+        let patched = s.patch_user("hr", id, &patch(json!([
+            { "op": "Replace", "path": "active", "value": "False" },
+            { "op": "replace", "path": "emails[type eq \"work\"].value", "value": "barbara@example.com" },
+            { "op": "replace", "value": { "userName": "barbara@example.com", "displayName": "Babs" } },
+        ]))).await.ok().unwrap();
+        assert_eq!((patched["active"].as_bool(), patched["displayName"].as_str()), (Some(false), Some("Babs")));
+        let user = s.repos.user_repo.get_by_email("barbara@example.com").await.unwrap();
+        assert_eq!(user.status, UserStatus::Disabled);
+        assert_eq!(user.status_change.unwrap().reason, "Deactivated by hr");
+
+        let replaced = s.replace_user("hr", id, &json!({
+            "schemas": [USER_SCHEMA],
+            "userName": "barbara@example.com",
+            "active": true,
+        })).await.ok().unwrap();
+        assert_eq!(replaced["active"], true);
+        let actions: Vec<&str> = s.repos.audit_repo.get_for_user(id).await.iter().map(|event| event.action.as_str()).collect();
+        assert!(actions.contains(&"account_disabled") && actions.contains(&"account_enabled"));
+
+        assert!(s.delete_user("hr", id).await.is_ok());
+        assert!(matches!(s.get_user(id).await, Err(ScimError::NotFound)));
+    }
+
+    #[actix_web::test]
+    async fn test_groups() {
+        let s = get_service();
+        let admin = s.repos.user_repo.get_by_email("admin@example.com").await.unwrap();
+        let user = s.create_user("hr", &json!({ "userName": "bjensen@example.com" })).await.ok().unwrap();
+        let user_id = user["id"].as_str().unwrap();
+
+        let group = s.create_group("hr", &json!({
+            "schemas": [GROUP_SCHEMA],
+            "displayName": "Staff",
+            "members": [{ "value": admin.id }],
+        })).await.ok().unwrap();
+        assert_resource(&group, GROUP_SCHEMA, "Group");
+        let id = group["id"].as_str().unwrap();
+        assert!(matches!(s.create_group("hr", &json!({ "displayName": "Staff" })).await, Err(ScimError::Uniqueness)));
+        assert!(matches!(s.create_group("hr", &json!({ "displayName": "Other", "members": [{ "value": "nobody" }] })).await, Err(ScimError::InvalidValue(_))));
+
+        let group = s.patch_group("hr", id, &patch(json!([
+            { "op": "add", "path": "members", "value": [{ "value": user_id }] },
+            { "op": "remove", "path": format!("members[value eq \"{}\"]", admin.id) },
+            { "op": "replace", "path": "displayName", "value": "Employees" },
+        ]))).await.ok().unwrap();
+        assert_eq!(group["members"], json!([{
+            "value": user_id,
+            "display": "bjensen@example.com",
+            "type": "User",
+            "$ref": format!("http://localhost:8080/scim/v2/Users/{}", user_id),
+        }]));
+        let user = s.get_user(user_id).await.ok().unwrap();
+        assert_eq!(user["groups"][0]["display"], "Employees");
+        assert!(s.repos.user_repo.get_by_id(&admin.id).await.unwrap().groups.is_empty());
+
+        let list = s.list_groups(&ScimQuery { filter: Some(r#"displayName eq "employees""#.to_string()), excluded_attributes: Some("members".to_string()), ..ScimQuery::default() }).await.ok().unwrap();
+        assert_eq!(list["totalResults"], 1);
+        assert!(list["Resources"][0].get("members").is_none());
+
+        assert!(s.delete_group("hr", id).await.is_ok());
+        assert!(s.repos.user_repo.get_by_id(user_id).await.unwrap().groups.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_groups_known_by_name() {
+        let s = get_service();
+        let mut admin = s.repos.user_repo.get_by_email("admin@example.com").await.unwrap();
+        admin.groups.insert("ldap-admins".to_string());
+        s.repos.user_repo.add(admin).await.unwrap();
+
+        let list = s.list_groups(&ScimQuery::default()).await.ok().unwrap();
+        let id = list["Resources"][0]["id"].as_str().unwrap();
+        assert_eq!(list["Resources"][0]["displayName"], "ldap-admins");
+        // The id stays the same on the next requests, which do not store the group
+        assert_eq!(s.get_group(id).await.ok().unwrap()["displayName"], "ldap-admins");
+        assert!(s.repos.group_repo.get_all().await.is_empty());
+
+        // It is stored with the same id once changed
+        s.patch_group("hr", id, &patch(json!([{ "op": "replace", "path": "displayName", "value": "admins" }]))).await.ok().unwrap();
+        assert_eq!("admins", s.repos.group_repo.get_by_id(id).await.unwrap().name);
+    }
+
+    #[test]
+    fn test_invalid_patch() {
+        let mut resource = json!({ "emails": [{ "value": "a@example.com", "type": "work" }] });
+
+        assert!(matches!(apply_patch(&mut resource, &json!({ "Operations": [] })), Err(ScimError::InvalidSyntax(_))));
+        let missing = patch(json!([{ "op": "replace", "path": "emails[type eq \"home\"].value", "value": "b@example.com" }]));
+        assert!(matches!(apply_patch(&mut resource, &missing), Err(ScimError::NoTarget(_))));
+        let unknown = patch(json!([{ "op": "move", "path": "emails" }]));
+        assert!(matches!(apply_patch(&mut resource, &unknown), Err(ScimError::InvalidSyntax(_))));
+    }
+}
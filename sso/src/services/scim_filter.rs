@@ -0,0 +1,395 @@
+use serde_json::Value;
+use crate::errors::scim::ScimError;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operator {
+    Eq,
+    Ne,
+    Co,
+    Sw,
+    Ew,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+/// A filter of RFC 7644 section 3.4.2.2, evaluated on the JSON representation of a resource
+#[derive(Debug, PartialEq)]
+pub enum Filter {
+    Compare(String, Operator, Value),
+    Present(String),
+    /// `emails[type eq "work"]`, matches when an element of the attribute matches
+    Complex(String, Box<Filter>),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+}
+
+/// Deepest nesting of parentheses, `not` and complex attributes accepted in a filter
+const MAX_DEPTH: usize = 16;
+/// Longest filter accepted, the `and` and `or` chains are nested as deep as they are long
+const MAX_TOKENS: usize = 256;
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    OpenBracket,
+    CloseBracket,
+    Word(String),
+    Literal(Value),
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, ScimError> {
+    let mut tokens = vec![];
+    let mut chars = input.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '[' => tokens.push(Token::OpenBracket),
+            ']' => tokens.push(Token::CloseBracket),
+            '"' => {
+                let mut escaped = false;
+                let mut end = None;
+                for (i, c) in chars.by_ref() {
+                    match (escaped, c) {
+                        (false, '\\') => escaped = true,
+                        (false, '"') => {
+                            end = Some(i);
+                            break;
+                        }
+                        _ => escaped = false,
+                    }
+                }
+                let end = end.ok_or_else(|| ScimError::InvalidFilter("unterminated string".to_string()))?;
+                let value = serde_json::from_str(&input[start..=end])
+                    .map_err(|_| ScimError::InvalidFilter("invalid string".to_string()))?;
+                tokens.push(Token::Literal(value));
+            }
+            _ => {
+                let mut end = start + c.len_utf8();
+                while let Some(&(i, c)) = chars.peek() {
+                    if c.is_whitespace() || "()[]\"".contains(c) {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                tokens.push(Token::Word(input[start..end].to_string()));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.tokens.get(self.position), Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn next(&mut self) -> Option<&Token> {
+        self.position += 1;
+        self.tokens.get(self.position - 1)
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), ScimError> {
+        match self.next() {
+            Some(next) if *next == token => Ok(()),
+            _ => Err(ScimError::InvalidFilter(format!("{:?} expected", token))),
+        }
+    }
+
+    /// Parse a nested filter, refusing the ones nested too deep to be evaluated safely
+    fn nested(&mut self) -> Result<Filter, ScimError> {
+        if self.depth == MAX_DEPTH {
+            return Err(ScimError::InvalidFilter("filter nested too deep".to_string()));
+        }
+        self.depth += 1;
+        let filter = self.or();
+        self.depth -= 1;
+        filter
+    }
+
+    fn or(&mut self) -> Result<Filter, ScimError> {
+        let mut filter = self.and()?;
+        while self.peek_keyword("or") {
+            self.position += 1;
+            filter = Filter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> Result<Filter, ScimError> {
+        let mut filter = self.unary()?;
+        while self.peek_keyword("and") {
+            self.position += 1;
+            filter = Filter::And(Box::new(filter), Box::new(self.unary()?));
+        }
+        Ok(filter)
+    }
+
+    fn unary(&mut self) -> Result<Filter, ScimError> {
+        if self.peek_keyword("not") {
+            self.position += 1;
+            self.expect(Token::Open)?;
+            let filter = self.nested()?;
+            self.expect(Token::Close)?;
+            return Ok(Filter::Not(Box::new(filter)));
+        }
+        match self.next() {
+            Some(Token::Open) => {
+                let filter = self.nested()?;
+                self.expect(Token::Close)?;
+                Ok(filter)
+            }
+            Some(Token::Word(path)) => {
+                let path = strip_schema(path).to_string();
+                if self.tokens.get(self.position) == Some(&Token::OpenBracket) {
+                    self.position += 1;
+                    let filter = self.nested()?;
+                    self.expect(Token::CloseBracket)?;
+                    return Ok(Filter::Complex(path, Box::new(filter)));
+                }
+                self.comparison(path)
+            }
+            _ => Err(ScimError::InvalidFilter("attribute expected".to_string())),
+        }
+    }
+
+    fn comparison(&mut self, path: String) -> Result<Filter, ScimError> {
+        let operator = match self.next() {
+            Some(Token::Word(word)) => word.to_lowercase(),
+            _ => return Err(ScimError::InvalidFilter(format!("operator expected after {}", path))),
+        };
+        let operator = match operator.as_str() {
+            "pr" => return Ok(Filter::Present(path)),
+            "eq" => Operator::Eq,
+            "ne" => Operator::Ne,
+            "co" => Operator::Co,
+            "sw" => Operator::Sw,
+            "ew" => Operator::Ew,
+            "gt" => Operator::Gt,
+            "ge" => Operator::Ge,
+            "lt" => Operator::Lt,
+            "le" => Operator::Le,
+            other => return Err(ScimError::InvalidFilter(format!("unknown operator {}", other))),
+        };
+        let value = match self.next() {
+            Some(Token::Literal(value)) => value.clone(),
+            Some(Token::Word(word)) => serde_json::from_str(word)
+                .map_err(|_| ScimError::InvalidFilter(format!("invalid value {}", word)))?,
+            _ => return Err(ScimError::InvalidFilter(format!("value expected after {}", path))),
+        };
+        Ok(Filter::Compare(path, operator, value))
+    }
+}
+
+/// Attributes can be prefixed by their schema, `urn:ietf:params:scim:schemas:core:2.0:User:userName`
+fn strip_schema(path: &str) -> &str {
+    match path.starts_with("urn:") {
+        true => path.rsplit_once(':').map(|(_, path)| path).unwrap_or(path),
+        false => path,
+    }
+}
+
+/// The field of an object, attribute names are case insensitive
+pub fn field<'v>(value: &'v Value, name: &str) -> Option<&'v Value> {
+    value.as_object()?
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value)
+}
+
+/// Every value found at a dotted path, multi-valued attributes are flattened
+fn values<'v>(resource: &'v Value, path: &str) -> Vec<&'v Value> {
+    let mut current = vec![resource];
+    for name in path.split('.') {
+        current = current.into_iter()
+            .flat_map(|value| match value {
+                Value::Array(items) => items.iter().collect(),
+                value => vec![value],
+            })
+            .filter_map(|value| field(value, name))
+            .collect();
+    }
+    current.into_iter()
+        .flat_map(|value| match value {
+            Value::Array(items) => items.iter().collect(),
+            value => vec![value],
+        })
+        // A complex value without sub-attribute is compared by its `value`
+        .filter_map(|value| match value {
+            Value::Object(_) => field(value, "value"),
+            value => Some(value),
+        })
+        .collect()
+}
+
+fn compare(found: &Value, operator: Operator, expected: &Value) -> bool {
+    match (found, expected) {
+        (Value::String(found), Value::String(expected)) => {
+            let (found, expected) = (found.to_lowercase(), expected.to_lowercase());
+            match operator {
+                Operator::Eq => found == expected,
+                Operator::Ne => found != expected,
+                Operator::Co => found.contains(&expected),
+                Operator::Sw => found.starts_with(&expected),
+                Operator::Ew => found.ends_with(&expected),
+                Operator::Gt => found > expected,
+                Operator::Ge => found >= expected,
+                Operator::Lt => found < expected,
+                Operator::Le => found <= expected,
+            }
+        }
+        (Value::Number(found), Value::Number(expected)) => {
+            let (found, expected) = (found.as_f64().unwrap_or_default(), expected.as_f64().unwrap_or_default());
+            match operator {
+                Operator::Eq => found == expected,
+                Operator::Ne => found != expected,
+                Operator::Gt => found > expected,
+                Operator::Ge => found >= expected,
+                Operator::Lt => found < expected,
+                Operator::Le => found <= expected,
+                _ => false,
+            }
+        }
+        (found, expected) => match operator {
+            Operator::Eq => found == expected,
+            Operator::Ne => found != expected,
+            _ => false,
+        },
+    }
+}
+
+impl Filter {
+    pub fn parse(input: &str) -> Result<Filter, ScimError> {
+        let tokens = tokenize(input)?;
+        if tokens.len() > MAX_TOKENS {
+            return Err(ScimError::InvalidFilter("filter too long".to_string()));
+        }
+        let mut parser = Parser { tokens, position: 0, depth: 0 };
+        let filter = parser.or()?;
+        match parser.position == parser.tokens.len() {
+            true => Ok(filter),
+            false => Err(ScimError::InvalidFilter("unexpected content at the end".to_string())),
+        }
+    }
+
+    pub fn matches(&self, resource: &Value) -> bool {
+        match self {
+            // An absent attribute is different from any value
+            Filter::Compare(path, Operator::Ne, expected) => !values(resource, path).iter()
+                .any(|found| compare(found, Operator::Eq, expected)),
+            Filter::Compare(path, operator, expected) => values(resource, path).iter()
+                .any(|found| compare(found, *operator, expected)),
+            Filter::Present(path) => values(resource, path).iter()
+                .any(|found| !found.is_null() && *found != "" && *found != &Value::Array(vec![])),
+            Filter::Complex(path, filter) => match field(resource, path) {
+                Some(Value::Array(items)) => items.iter().any(|item| filter.matches(item)),
+                Some(item) => filter.matches(item),
+                None => false,
+            },
+            Filter::And(left, right) => left.matches(resource) && right.matches(resource),
+            Filter::Or(left, right) => left.matches(resource) || right.matches(resource),
+            Filter::Not(filter) => !filter.matches(resource),
+        }
+    }
+}
+
+/// The target of a patch operation: `attribute[filter].subAttribute`
+pub struct PatchPath {
+    pub attribute: String,
+    pub filter: Option<Filter>,
+    pub sub_attribute: Option<String>,
+}
+
+impl PatchPath {
+    pub fn parse(path: &str) -> Result<PatchPath, ScimError> {
+        let invalid = || ScimError::InvalidPath(path.to_string());
+        let (attribute, filter, sub_attribute) = match path.split_once('[') {
+            Some((attribute, rest)) => {
+                let (filter, rest) = rest.rsplit_once(']').ok_or_else(invalid)?;
+                let sub_attribute = match rest {
+                    "" => None,
+                    rest => Some(rest.strip_prefix('.').ok_or_else(invalid)?),
+                };
+                (attribute, Some(Filter::parse(filter).map_err(|_| invalid())?), sub_attribute)
+            }
+            None => match strip_schema(path).split_once('.') {
+                Some((attribute, sub_attribute)) => (attribute, None, Some(sub_attribute)),
+                None => (strip_schema(path), None, None),
+            },
+        };
+        let attribute = strip_schema(attribute);
+        if attribute.is_empty() || sub_attribute.is_some_and(|sub| sub.is_empty() || sub.contains('.')) {
+            return Err(invalid());
+        }
+        Ok(PatchPath { attribute: attribute.to_string(), filter, sub_attribute: sub_attribute.map(str::to_string) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    #[test]
+    fn test_filter() {
+        let user = json!({
+            "userName": "Alice@Example.com",
+            "active": true,
+            "meta": { "created": "2026-01-10T00:00:00Z" },
+            "emails": [{ "value": "alice@example.com", "type": "work" }],
+            "groups": [{ "value": "g1", "display": "staff" }],
+        });
+        let matches = |filter: &str| Filter::parse(filter).ok().unwrap().matches(&user);
+
+        assert!(matches(r#"userName eq "alice@example.com""#));
+        assert!(matches(r#"urn:ietf:params:scim:schemas:core:2.0:User:userName sw "alice""#));
+        assert!(matches(r#"emails[type eq "work" and value co "@example"]"#));
+        assert!(matches(r#"emails.value ew "example.com" and active eq true"#));
+        assert!(matches(r#"groups eq "g1""#));
+        assert!(matches(r#"meta.created gt "2026-01-01T00:00:00Z""#));
+        assert!(matches(r#"title pr or not (active eq false)"#));
+        assert!(matches(r#"title ne "x""#));
+        assert!(!matches(r#"userName eq "bob@example.com" or (emails pr and active eq false)"#));
+        assert!(!matches(r#"title pr"#));
+
+        assert!(Filter::parse(r#"userName eq"#).is_err());
+        assert!(Filter::parse(r#"userName is "x""#).is_err());
+        assert!(Filter::parse(r#"userName eq "x" )"#).is_err());
+        assert!(Filter::parse(r#"userName eq "x"#).is_err());
+
+        let nested = |depth: usize| format!("{}title pr{}", "not (".repeat(depth), ")".repeat(depth));
+        assert!(Filter::parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(matches!(Filter::parse(&nested(MAX_DEPTH + 1)), Err(ScimError::InvalidFilter(_))));
+        assert!(Filter::parse(&"title pr and ".repeat(100_000)).is_err());
+    }
+
+    #[test]
+    fn test_patch_path() {
+        let path = PatchPath::parse(r#"members[value eq "2819c223"]"#).ok().unwrap();
+        assert_eq!(path.attribute, "members");
+        assert!(path.filter.is_some() && path.sub_attribute.is_none());
+
+        let path = PatchPath::parse(r#"emails[type eq "work"].value"#).ok().unwrap();
+        assert_eq!(path.sub_attribute.as_deref(), Some("value"));
+
+        let path = PatchPath::parse("urn:ietf:params:scim:schemas:core:2.0:User:name.givenName").ok().unwrap();
+        assert_eq!((path.attribute.as_str(), path.sub_attribute.as_deref()), ("name", Some("givenName")));
+
+        assert!(PatchPath::parse(r#"emails[type eq "work"]value"#).is_err());
+        assert!(PatchPath::parse("name.").is_err());
+        assert!(PatchPath::parse(&format!("emails[{}]", "(".repeat(100_000))).is_err());
+    }
+}
@@ -0,0 +1,311 @@
+use actix_web::{get, post, web, HttpResponse, Scope};
+use actix_web::http::header::{ContentDisposition, ContentType, LOCATION};
+use maud::{html, Markup};
+use crate::app::app_state::AppState;
+use crate::app::session::Session;
+use crate::errors::account::AccountError;
+use crate::forms::account::{DeletionForm, EmailForm, PasswordForm, ProfileForm, RevokeConsentForm, VerifyEmailQuery};
+use crate::objects::application::Application;
+use crate::objects::consent::Consent;
+use crate::objects::user::User;
+use crate::views::consent::scope_list;
+use crate::views::forms::field_errors;
+use crate::views::nav::get_nav;
+
+fn html_response(content: Markup) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(content)
+}
+
+fn require_login(session: Option<web::ReqData<Session>>) -> Result<Session, Box<HttpResponse>> {
+    session.map(|session| session.into_inner())
+        .ok_or_else(|| Box::new(HttpResponse::Found().insert_header((LOCATION, "/auth/login")).finish()))
+}
+
+fn error_banner(error: Option<&AccountError>) -> Markup {
+    html! {
+        @match error {
+            None | Some(AccountError::Validation(_)) => {},
+            Some(error) => div { ("Error : ") (error) },
+        }
+    }
+}
+
+fn validation_errors(error: Option<&AccountError>) -> Option<&crate::errors::validation::ValidationErrors> {
+    match error {
+        Some(AccountError::Validation(errors)) => Some(errors),
+        _ => None,
+    }
+}
+
+pub fn avatar(user: &User) -> Markup {
+    html! {
+        @match &user.avatar_url {
+            Some(url) => img src=(url) alt=(user.initials()) width="48" height="48";,
+            None => span class="avatar" { (user.initials()) },
+        }
+    }
+}
+
+fn profile_form(user: &User, error: Option<&AccountError>) -> Markup {
+    let errors = validation_errors(error);
+    html! {
+        form hx-post="/account/profile" hx-swap="outerHTML" {
+            h2 { "Profile" }
+            (error_banner(error))
+            input type="text" name="name" placeholder="Name" value=(user.name);
+            br;
+            (field_errors(errors, "name"))
+            input type="url" name="avatar_url" placeholder="Avatar url" value=[user.avatar_url.as_deref()];
+            br;
+            (field_errors(errors, "avatar_url"))
+            input type="text" name="timezone" placeholder="Europe/Paris" value=(user.timezone);
+            br;
+            (field_errors(errors, "timezone"))
+            input type="text" name="locale" placeholder="en-US" value=(user.locale);
+            br;
+            (field_errors(errors, "locale"))
+            button type="submit" { "Save" }
+        }
+    }
+}
+
+fn password_form(error: Option<&AccountError>) -> Markup {
+    let errors = validation_errors(error);
+    html! {
+        form hx-post="/account/password" hx-swap="outerHTML" {
+            h2 { "Password" }
+            (error_banner(error))
+            input type="password" name="current_password" placeholder="Current password";
+            br;
+            input type="password" name="new_password" placeholder="New password";
+            br;
+            (field_errors(errors, "new_password"))
+            button type="submit" { "Change password" }
+        }
+    }
+}
+
+fn email_form(user: &User, error: Option<&AccountError>) -> Markup {
+    let errors = validation_errors(error);
+    html! {
+        form hx-post="/account/email" hx-swap="outerHTML" {
+            h2 { "Email" }
+            (error_banner(error))
+            p { "Current email: " (user.email) }
+            input type="email" name="email" placeholder="new@example.com";
+            br;
+            (field_errors(errors, "email"))
+            button type="submit" { "Change email" }
+        }
+    }
+}
+
+fn deletion_form(user: &User, error: Option<&AccountError>) -> Markup {
+    html! {
+        div {
+            h2 { "Delete my account" }
+            (error_banner(error))
+            @match user.deletion_scheduled {
+                Some(date) => {
+                    p { "Your account will be deleted on " (date.format("%Y-%m-%d %H:%M")) " UTC" }
+                    form hx-post="/account/delete/cancel" hx-target="closest div" hx-swap="outerHTML" {
+                        button type="submit" { "Keep my account" }
+                    }
+                },
+                None => {
+                    form hx-post="/account/delete" hx-target="closest div" hx-swap="outerHTML" {
+                        @if user.provider.is_some() {
+                            input type="email" name="email" placeholder="Type your email to confirm";
+                        } @else {
+                            input type="password" name="password" placeholder="Current password";
+                        }
+                        br;
+                        button type="submit" { "Delete my account" }
+                    }
+                },
+            }
+        }
+    }
+}
+
+/// The applications the user shared data with, each can be removed
+fn consents_list(granted: &[(Application, Consent)]) -> Markup {
+    html! {
+        h2 { "Applications" }
+        @if granted.is_empty() {
+            p { "You did not give any application access to your account" }
+        }
+        @for (application, consent) in granted {
+            div {
+                a href=(application.url) { (application.name) }
+                " since " (consent.granted.format("%Y-%m-%d"))
+                (scope_list(&consent.scopes))
+                form hx-post="/account/applications/revoke" hx-target="closest div" hx-swap="outerHTML" {
+                    input type="hidden" name="client_id" value=(application.client_id);
+                    button type="submit" { "Remove access" }
+                }
+            }
+        }
+    }
+}
+
+#[get("")]
+async fn account_page(state: web::Data<AppState>, session: Option<web::ReqData<Session>>) -> HttpResponse {
+    let session = match require_login(session) {
+        Ok(session) => session,
+        Err(response) => return *response,
+    };
+    let granted = state.services.consent.list(&session.user).await;
+
+    html_response(html! {
+        (get_nav(Some(&session)))
+        h1 { (avatar(&session.user)) " " (session.user.name) }
+        (profile_form(&session.user, None))
+        (password_form(None))
+        (email_form(&session.user, None))
+        (consents_list(&granted))
+        h2 { "My data" }
+        a href="/account/export" { "Download my data" }
+        (deletion_form(&session.user, None))
+    })
+}
+
+#[post("/profile")]
+async fn update_profile(
+    state: web::Data<AppState>,
+    session: Option<web::ReqData<Session>>,
+    form: web::Form<ProfileForm>,
+) -> HttpResponse {
+    let session = match require_login(session) {
+        Ok(session) => session,
+        Err(response) => return *response,
+    };
+
+    html_response(match state.services.account.update_profile(&session.user, &form).await {
+        Ok(user) => html! { (profile_form(&user, None)) "Saved" },
+        Err(e) => profile_form(&session.user, Some(&e)),
+    })
+}
+
+#[post("/password")]
+async fn change_password(
+    state: web::Data<AppState>,
+    session: Option<web::ReqData<Session>>,
+    form: web::Form<PasswordForm>,
+) -> HttpResponse {
+    let session = match require_login(session) {
+        Ok(session) => session,
+        Err(response) => return *response,
+    };
+
+    html_response(match state.services.account.change_password(&session.user, &session.token, &form).await {
+        Ok(_) => html! { (password_form(None)) "Password changed, your other sessions have been closed" },
+        Err(e) => password_form(Some(&e)),
+    })
+}
+
+#[post("/email")]
+async fn change_email(
+    state: web::Data<AppState>,
+    session: Option<web::ReqData<Session>>,
+    form: web::Form<EmailForm>,
+) -> HttpResponse {
+    let session = match require_login(session) {
+        Ok(session) => session,
+        Err(response) => return *response,
+    };
+
+    html_response(match state.services.account.request_email_change(&session.user, &form).await {
+        Ok(_) => html! { (email_form(&session.user, None)) "A verification link has been sent to " (form.email) },
+        Err(e) => email_form(&session.user, Some(&e)),
+    })
+}
+
+#[get("/email/verify")]
+async fn verify_email(state: web::Data<AppState>, query: web::Query<VerifyEmailQuery>) -> HttpResponse {
+    html_response(match state.services.account.confirm_email_change(&query.token).await {
+        Ok(user) => html! {
+            (get_nav(None))
+            "Your email is now " (user.email) ", please log in again"
+        },
+        Err(e) => html! {
+            (get_nav(None))
+            ("Error : ") (e)
+        },
+    })
+}
+
+#[post("/delete")]
+async fn request_deletion(
+    state: web::Data<AppState>,
+    session: Option<web::ReqData<Session>>,
+    form: web::Form<DeletionForm>,
+) -> HttpResponse {
+    let session = match require_login(session) {
+        Ok(session) => session,
+        Err(response) => return *response,
+    };
+
+    html_response(match state.services.account.request_deletion(&session.user, &form).await {
+        Ok(date) => deletion_form(&User { deletion_scheduled: Some(date), ..session.user }, None),
+        Err(e) => deletion_form(&session.user, Some(&e)),
+    })
+}
+
+#[post("/delete/cancel")]
+async fn cancel_deletion(state: web::Data<AppState>, session: Option<web::ReqData<Session>>) -> HttpResponse {
+    let session = match require_login(session) {
+        Ok(session) => session,
+        Err(response) => return *response,
+    };
+
+    html_response(match state.services.account.cancel_deletion(&session.user).await {
+        Ok(user) => deletion_form(&user, None),
+        Err(e) => deletion_form(&session.user, Some(&e)),
+    })
+}
+
+/// The application asks for consent again on the next login
+#[post("/applications/revoke")]
+async fn revoke_consent(
+    state: web::Data<AppState>,
+    session: Option<web::ReqData<Session>>,
+    form: web::Form<RevokeConsentForm>,
+) -> HttpResponse {
+    let session = match require_login(session) {
+        Ok(session) => session,
+        Err(response) => return *response,
+    };
+
+    state.services.consent.revoke(&session.user, &form.client_id).await;
+    html_response(html! { div { "Access removed" } })
+}
+
+#[get("/export")]
+async fn export(state: web::Data<AppState>, session: Option<web::ReqData<Session>>) -> HttpResponse {
+    let session = match require_login(session) {
+        Ok(session) => session,
+        Err(response) => return *response,
+    };
+
+    let data = state.services.account.export(&session.user).await;
+    HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .insert_header(ContentDisposition::attachment("my-data.json"))
+        .body(serde_json::to_string_pretty(&data).unwrap())
+}
+
+pub fn get_scope() -> Scope {
+    web::scope("/account")
+        .service(account_page)
+        .service(update_profile)
+        .service(change_password)
+        .service(change_email)
+        .service(verify_email)
+        .service(request_deletion)
+        .service(cancel_deletion)
+        .service(revoke_consent)
+        .service(export)
+}
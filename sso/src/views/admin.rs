@@ -0,0 +1,370 @@
+use std::collections::{HashMap, HashSet};
+use actix_web::{get, post, web, HttpResponse, Scope};
+use actix_web::http::header::ContentType;
+use maud::{html, Markup};
+use crate::app::app_state::AppState;
+use crate::app::session::Session;
+use crate::errors::admin::AdminError;
+use crate::forms::admin::{DisableForm, InviteForm, PreConsentForm, UserActionForm};
+use crate::objects::application::{Application, Protocol};
+use crate::objects::registration_token::{InviteRestriction, RegisterToken};
+use crate::objects::user::{User, UserStatus};
+use crate::services::auth::{NewInvite, MAX_INVITE_DAYS};
+use crate::views::nav::get_nav;
+
+/// The session of an admin, or the response to send to anyone else
+fn require_admin(session: Option<web::ReqData<Session>>) -> Result<Session, Box<HttpResponse>> {
+    match session {
+        Some(session) if session.user.admin => Ok(session.into_inner()),
+        _ => Err(Box::new(HttpResponse::Forbidden().body("Admin access required"))),
+    }
+}
+
+fn html_response(content: Markup) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(content)
+}
+
+fn split_list(value: &str) -> HashSet<String> {
+    value.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Email of every user by id, to show who did something
+fn emails_by_id(users: &[User]) -> HashMap<&str, &str> {
+    users.iter()
+        .map(|user| (user.id.as_str(), user.email.as_str()))
+        .collect()
+}
+
+/// The id itself for `system` or when the user does not exist anymore
+fn email_of<'a>(emails: &HashMap<&str, &'a str>, id: &'a str) -> &'a str {
+    emails.get(id).copied().unwrap_or(id)
+}
+
+fn invite_row(token: &RegisterToken, emails: &HashMap<&str, &str>) -> Markup {
+    html! {
+        tr {
+            td { a href={"/auth/register?token=" (token.value)} { (token.value) } }
+            td { (token.expiration.format("%Y-%m-%d %H:%M")) }
+            td { (token.uses.len()) " / " (token.max_uses) }
+            td {
+                @match &token.restriction {
+                    None => "anyone",
+                    Some(InviteRestriction::Email(email)) => (email),
+                    Some(InviteRestriction::Domain(domain)) => { "@" (domain) },
+                }
+            }
+            td { (token.groups.iter().cloned().collect::<Vec<_>>().join(", ")) }
+            td { (token.applications.iter().cloned().collect::<Vec<_>>().join(", ")) }
+            td { (token.created_by.as_deref().map(|id| email_of(emails, id)).unwrap_or("")) }
+            td {
+                @for usage in &token.uses {
+                    (usage.email) " (" (usage.date.format("%Y-%m-%d")) ")" br;
+                }
+            }
+        }
+    }
+}
+
+fn user_row(user: &User, emails: &HashMap<&str, &str>) -> Markup {
+    html! {
+        tr {
+            td { (user.email) }
+            td { (user.name) }
+            td {
+                (format!("{:?}", user.status))
+                @if let Some(change) = &user.status_change {
+                    br;
+                    "by " (email_of(emails, &change.by)) " on " (change.date.format("%Y-%m-%d")) ": " (change.reason)
+                }
+            }
+            td { (user.groups.iter().cloned().collect::<Vec<_>>().join(", ")) }
+            td { (user.created.format("%Y-%m-%d")) }
+            td {
+                @if let Some(date) = user.deletion_scheduled {
+                    "Deleted on " (date.format("%Y-%m-%d"))
+                }
+            }
+            td {
+                @if user.status == UserStatus::Pending {
+                    form hx-post="/admin/users/approve" hx-target="closest td" {
+                        input type="hidden" name="id" value=(user.id);
+                        button type="submit" { "Approve" }
+                    }
+                }
+                @match user.status {
+                    UserStatus::Disabled | UserStatus::Locked => {
+                        form hx-post="/admin/users/enable" hx-target="closest td" {
+                            input type="hidden" name="id" value=(user.id);
+                            button type="submit" { "Enable" }
+                        }
+                    },
+                    _ => {
+                        form hx-post="/admin/users/disable" hx-target="closest td" {
+                            input type="hidden" name="id" value=(user.id);
+                            input type="text" name="reason" placeholder="Reason";
+                            button type="submit" { "Disable" }
+                        }
+                    },
+                }
+                form hx-post="/admin/users/delete" hx-target="closest td" hx-confirm={"Delete " (user.email) " now?"} {
+                    input type="hidden" name="id" value=(user.id);
+                    button type="submit" { "Delete" }
+                }
+            }
+        }
+    }
+}
+
+#[get("/users")]
+async fn users_page(state: web::Data<AppState>, session: Option<web::ReqData<Session>>) -> HttpResponse {
+    let session = match require_admin(session) {
+        Ok(session) => session,
+        Err(response) => return *response,
+    };
+    let users = state.services.admin.list_users().await;
+    let emails = emails_by_id(&users);
+
+    html_response(html! {
+        (get_nav(Some(&session)))
+        h1 { "Users" }
+        a href="/admin/invites" { "Invitations" }
+        " "
+        a href="/admin/applications" { "Applications" }
+        table {
+            tr {
+                th { "Email" } th { "Name" } th { "Status" } th { "Groups" } th { "Created" } th { "Deletion" } th {}
+            }
+            @for user in &users {
+                (user_row(user, &emails))
+            }
+        }
+    })
+}
+
+#[post("/users/approve")]
+async fn approve_user(
+    state: web::Data<AppState>,
+    session: Option<web::ReqData<Session>>,
+    form: web::Form<UserActionForm>,
+) -> HttpResponse {
+    let session = match require_admin(session) {
+        Ok(session) => session,
+        Err(response) => return *response,
+    };
+
+    match state.services.admin.approve_user(&session.user, &form.id).await {
+        Ok(_) => html_response(html! { "Approved" }),
+        Err(e) => html_response(html! { ("Error : ") (e) }),
+    }
+}
+
+#[post("/users/disable")]
+async fn disable_user(
+    state: web::Data<AppState>,
+    session: Option<web::ReqData<Session>>,
+    form: web::Form<DisableForm>,
+) -> HttpResponse {
+    let session = match require_admin(session) {
+        Ok(session) => session,
+        Err(response) => return *response,
+    };
+
+    match state.services.admin.disable_user(&session.user, &form.id, &form.reason).await {
+        Ok(_) => html_response(html! { "Disabled" }),
+        Err(e) => html_response(html! { ("Error : ") (e) }),
+    }
+}
+
+#[post("/users/enable")]
+async fn enable_user(
+    state: web::Data<AppState>,
+    session: Option<web::ReqData<Session>>,
+    form: web::Form<UserActionForm>,
+) -> HttpResponse {
+    let session = match require_admin(session) {
+        Ok(session) => session,
+        Err(response) => return *response,
+    };
+
+    match state.services.admin.enable_user(&session.user, &form.id).await {
+        Ok(_) => html_response(html! { "Enabled" }),
+        Err(e) => html_response(html! { ("Error : ") (e) }),
+    }
+}
+
+/// Delete an account right away, without waiting for the grace period
+#[post("/users/delete")]
+async fn delete_user(
+    state: web::Data<AppState>,
+    session: Option<web::ReqData<Session>>,
+    form: web::Form<UserActionForm>,
+) -> HttpResponse {
+    let session = match require_admin(session) {
+        Ok(session) => session,
+        Err(response) => return *response,
+    };
+
+    if form.id == session.user.id {
+        return html_response(html! { ("Error : ") (AdminError::OwnAccount) });
+    }
+    match state.services.account.delete_account(&form.id, &session.user.id).await {
+        Ok(_) => html_response(html! { "Deleted" }),
+        Err(e) => html_response(html! { ("Error : ") (e) }),
+    }
+}
+
+#[get("/invites")]
+async fn invites_page(state: web::Data<AppState>, session: Option<web::ReqData<Session>>) -> HttpResponse {
+    let session = match require_admin(session) {
+        Ok(session) => session,
+        Err(response) => return *response,
+    };
+    let invites = state.services.auth.list_invites().await;
+    let users = state.services.admin.list_users().await;
+    let emails = emails_by_id(&users);
+
+    html_response(html! {
+        (get_nav(Some(&session)))
+        h1 { "Invitations" }
+        table {
+            tr {
+                th { "Link" } th { "Expires" } th { "Uses" } th { "Restricted to" }
+                th { "Groups" } th { "Applications" } th { "Created by" } th { "Used by" }
+            }
+            @for token in &invites {
+                (invite_row(token, &emails))
+            }
+        }
+        h2 { "New invitation" }
+        div {}
+        form hx-post="/admin/invites" hx-target="previous" {
+            input type="number" name="days_valid" value="7" min="1" max=(MAX_INVITE_DAYS);
+            input type="number" name="max_uses" value="1" min="1";
+            input type="text" name="restriction" placeholder="user@example.com or @example.com";
+            input type="text" name="groups" placeholder="Groups, comma separated";
+            input type="text" name="applications" placeholder="Client ids, comma separated";
+            button type="submit" { "Create" }
+        }
+    })
+}
+
+#[post("/invites")]
+async fn create_invite(
+    state: web::Data<AppState>,
+    session: Option<web::ReqData<Session>>,
+    form: web::Form<InviteForm>,
+) -> HttpResponse {
+    let session = match require_admin(session) {
+        Ok(session) => session,
+        Err(response) => return *response,
+    };
+
+    let restriction = match form.restriction.trim() {
+        "" => None,
+        domain if domain.starts_with('@') => Some(InviteRestriction::Domain(domain[1..].to_string())),
+        email => Some(InviteRestriction::Email(email.to_string())),
+    };
+    let invite = NewInvite {
+        days_valid: form.days_valid,
+        max_uses: form.max_uses,
+        restriction,
+        groups: split_list(&form.groups),
+        applications: split_list(&form.applications),
+    };
+    let token = match state.services.auth.create_invite(&session.user, invite).await {
+        Ok(token) => token,
+        Err(e) => return html_response(html! { ("Error : ") (e) }),
+    };
+
+    html_response(html! {
+        "Invitation created: "
+        a href={"/auth/register?token=" (token.value)} { "/auth/register?token=" (token.value) }
+    })
+}
+
+fn pre_consent_form(application: &Application) -> Markup {
+    html! {
+        form hx-post="/admin/applications/consent" hx-swap="outerHTML" {
+            input type="hidden" name="client_id" value=(application.client_id);
+            @if application.pre_consented {
+                "Pre-consented "
+                input type="hidden" name="pre_consented" value="false";
+                button type="submit" { "Ask users" }
+            } @else {
+                "Users are asked "
+                input type="hidden" name="pre_consented" value="true";
+                button type="submit" { "Pre-consent" }
+            }
+        }
+    }
+}
+
+#[get("/applications")]
+async fn applications_page(state: web::Data<AppState>, session: Option<web::ReqData<Session>>) -> HttpResponse {
+    let session = match require_admin(session) {
+        Ok(session) => session,
+        Err(response) => return *response,
+    };
+    let applications = state.services.admin.list_applications().await;
+
+    html_response(html! {
+        (get_nav(Some(&session)))
+        h1 { "Applications" }
+        p { "Users of pre-consented first-party applications are not asked to share their data" }
+        table {
+            tr {
+                th { "Name" } th { "Client id" } th { "Protocol" } th { "Users" } th { "Consent" }
+            }
+            @for application in &applications {
+                tr {
+                    td { a href=(application.url) { (application.name) } }
+                    td { (application.client_id) }
+                    td {
+                        @match application.protocol {
+                            Protocol::Oidc => "OIDC",
+                            Protocol::Saml(_) => "SAML",
+                        }
+                    }
+                    td { (application.users.len()) }
+                    td { (pre_consent_form(application)) }
+                }
+            }
+        }
+    })
+}
+
+#[post("/applications/consent")]
+async fn set_pre_consented(
+    state: web::Data<AppState>,
+    session: Option<web::ReqData<Session>>,
+    form: web::Form<PreConsentForm>,
+) -> HttpResponse {
+    let session = match require_admin(session) {
+        Ok(session) => session,
+        Err(response) => return *response,
+    };
+
+    match state.services.admin.set_pre_consented(&session.user, &form.client_id, form.pre_consented).await {
+        Ok(application) => html_response(pre_consent_form(&application)),
+        Err(e) => html_response(html! { ("Error : ") (e) }),
+    }
+}
+
+pub fn get_scope() -> Scope {
+    web::scope("/admin")
+        .service(users_page)
+        .service(approve_user)
+        .service(disable_user)
+        .service(enable_user)
+        .service(delete_user)
+        .service(invites_page)
+        .service(create_invite)
+        .service(applications_page)
+        .service(set_pre_consented)
+}
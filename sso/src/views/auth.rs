@@ -1,14 +1,19 @@
-use actix_web::{get, post, web, Error, HttpResponse, Scope};
+use actix_web::{get, post, web, Error, HttpMessage, HttpRequest, HttpResponse, Scope};
 use actix_web::body::BoxBody;
-use actix_web::cookie::{Cookie, Expiration};
+use actix_web::cookie::{Cookie, Expiration, SameSite};
 use actix_web::cookie::time::{OffsetDateTime, UtcDateTime};
 use actix_web::dev::{ServiceRequest, ServiceResponse};
-use actix_web::http::header::ContentType;
+use actix_web::http::header::{ContentType, LOCATION};
 use actix_web::http::StatusCode;
 use actix_web::middleware::Next;
 use maud::{html, Markup};
 use crate::app::app_state::AppState;
-use crate::forms::auth::LoginForm;
+use crate::app::session::Session;
+use crate::errors::auth::RegisterError;
+use crate::objects::user::UserStatus;
+use crate::forms::auth::{FederationCallbackQuery, LoginForm, LoginQuery, RegisterForm, RegisterQuery};
+use crate::objects::login_token::LoginToken;
+use crate::views::forms::field_errors;
 use crate::views::nav::get_nav;
 
 pub async fn auth_middleware(
@@ -18,21 +23,40 @@ pub async fn auth_middleware(
     let excluded_paths = [
         "/auth/login",
         "/auth/register",
-        "/"
+        "/api/auth/register",
+        "/account/email/verify",
+        "/",
+        "/metrics",
+        "/healthz",
+        "/readyz",
     ];
 
-    let excluded = excluded_paths.contains(&req.path());
+    // SAML endpoints and the OAuth authorization endpoint send users without a valid session
+    // to the login page themselves, and SCIM and OAuth clients authenticate themselves
+    // instead of using the cookie
+    let excluded = excluded_paths.contains(&req.path())
+        || req.path().starts_with("/auth/oidc/")
+        || req.path().starts_with("/saml/")
+        || req.path().starts_with("/scim/")
+        || req.path().starts_with("/oauth/");
     let cookie = req.cookie("token");
 
     if let Some(cookie) = cookie {
         let value = cookie.value();
 
         let state = req.app_data::<web::Data<AppState>>().unwrap();
-        let user = state.services.auth.authenticate(value);
+        let user = state.services.auth.authenticate(value).await;
 
         match (excluded, user) {
-            (_, Ok(user)) => { *state.user.lock().unwrap() = Some((value.to_string(), user)); }
+            (_, Ok(user)) => {
+                tracing::Span::current().record("user", user.email.as_str());
+                req.extensions_mut().insert(Session {
+                    token: value.to_string(),
+                    user,
+                });
+            }
             (false, Err(e)) => {
+                tracing::info!(error = %e, "rejected unauthenticated request");
                 let content = html! {
                     script {"window.location.replace('/auth/login')"}
                     div { (e) }
@@ -50,9 +74,9 @@ pub async fn auth_middleware(
 }
 
 #[get("logout")]
-async fn logout(state: web::Data<AppState>) -> HttpResponse {
-    if let Some(token) = state.user.lock().unwrap().as_ref() {
-        state.services.auth.invalidate_token(&token.0);
+async fn logout(state: web::Data<AppState>, session: Option<web::ReqData<Session>>) -> HttpResponse {
+    if let Some(session) = session {
+        state.services.auth.invalidate_token(&session.token).await;
     }
     HttpResponse::build(StatusCode::OK)
         .content_type(ContentType::html())
@@ -62,57 +86,173 @@ async fn logout(state: web::Data<AppState>) -> HttpResponse {
         })
 }
 
-#[post("/login")]
-async fn login(state: web::Data<AppState>, form: web::Form<LoginForm>) -> HttpResponse {
-    let response = state.services.auth.login(&form);
-
-    let (cookie, body) = match response {
-        Ok(token) => {
-            let content = html! {
-                "You are connected, redirecting..."
-                script {"window.location.replace('/')"}
-            };
-            let cookie = Cookie::build("token", token.value)
-                .path("/")
-                .expires(
-                    Expiration::DateTime(
-                        OffsetDateTime::from(
-                            UtcDateTime::from_unix_timestamp(token.expiration.timestamp()).unwrap()
-                        )
-                    )
+/// Remembers which browser started a federated login
+const FEDERATION_COOKIE: &str = "oidc_state";
+
+fn session_cookie(token: &LoginToken) -> Cookie<'static> {
+    Cookie::build("token", token.value.clone())
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .expires(
+            Expiration::DateTime(
+                OffsetDateTime::from(
+                    UtcDateTime::from_unix_timestamp(token.expiration.timestamp()).unwrap()
                 )
-                .finish();
-            (Some(cookie), content)
-        },
-        Err(e) => {
-            let content = html! {
-                ("Error : ") (e)
-            };
+            )
+        )
+        .finish()
+}
 
-            (None, content)
-        }
-    };
+/// Send the user to the login page, coming back to the same request afterwards
+pub fn login_redirect(req: &HttpRequest) -> HttpResponse {
+    let query = LoginQuery { next: Some(req.uri().to_string()) };
+    let location = format!("/auth/login?{}", serde_urlencoded::to_string(&query).unwrap_or_default());
+    HttpResponse::Found().insert_header((LOCATION, location)).finish()
+}
 
-    let mut builder = HttpResponse::build(StatusCode::OK)
-        .body(body);
-    if let Some(cookie) = cookie {
-        let _ = builder.add_cookie(&cookie);
+#[post("/login")]
+async fn login(state: web::Data<AppState>, form: web::Form<LoginForm>, query: web::Query<LoginQuery>) -> HttpResponse {
+    match state.services.auth.login(&form).await {
+        Ok(token) => HttpResponse::Ok()
+            .insert_header(("HX-Redirect", query.target()))
+            .cookie(session_cookie(&token))
+            .body(html! { "You are connected, redirecting..." }),
+        Err(e) => HttpResponse::Ok()
+            .body(html! { ("Error : ") (e) }),
     }
-    builder
 }
 
 #[get("/login")]
-async fn login_page(state: web::Data<AppState>) -> Markup {
+async fn login_page(state: web::Data<AppState>, session: Option<web::ReqData<Session>>, query: web::Query<LoginQuery>) -> Markup {
+    let providers = state.services.federation.providers();
+    let action = match serde_urlencoded::to_string(&*query) {
+        Ok(params) if !params.is_empty() => format!("/auth/login?{}", params),
+        _ => "/auth/login".to_string(),
+    };
+
     html! {
-        (get_nav(&state))
+        (get_nav(session.as_deref()))
         div {}
-        form hx-post="/auth/login" hx-target="previous" {
+        form hx-post=(action) hx-target="previous" {
             input type="email" name="email" placeholder="user@example.com";
             br;
             input type="password" name="password" placeholder="Password";
             br;
             button type="submit" {"Login"}
         }
+        @for provider in providers {
+            a href={"/auth/oidc/" (provider.id)} { "Log in with " (provider.name) }
+            br;
+        }
+    }
+}
+
+/// Send the browser to the upstream provider
+#[get("/oidc/{provider}")]
+async fn federation_start(state: web::Data<AppState>, provider: web::Path<String>) -> HttpResponse {
+    match state.services.federation.start(&provider).await {
+        Ok((value, url)) => {
+            let cookie = Cookie::build(FEDERATION_COOKIE, value)
+                .path("/auth/oidc")
+                .http_only(true)
+                .same_site(SameSite::Lax)
+                .max_age(actix_web::cookie::time::Duration::minutes(10))
+                .finish();
+            HttpResponse::Found()
+                .insert_header((LOCATION, url))
+                .cookie(cookie)
+                .finish()
+        }
+        Err(e) => HttpResponse::NotFound()
+            .content_type(ContentType::html())
+            .body(html! { (get_nav(None)) ("Error : ") (e) }),
+    }
+}
+
+#[get("/oidc/{provider}/callback")]
+async fn federation_callback(
+    state: web::Data<AppState>,
+    req: actix_web::HttpRequest,
+    provider: web::Path<String>,
+    query: web::Query<FederationCallbackQuery>,
+) -> HttpResponse {
+    let browser_state = req.cookie(FEDERATION_COOKIE);
+    let result = match (&query.code, &query.error) {
+        (Some(code), None) => state.services.federation
+            .finish(&provider, code, &query.state, browser_state.as_ref().map(|cookie| cookie.value()))
+            .await
+            .map_err(|e| e.to_string()),
+        (_, error) => Err(format!("The identity provider refused the login: {}", error.as_deref().unwrap_or("no code"))),
+    };
+
+    let mut removal = Cookie::build(FEDERATION_COOKIE, "").path("/auth/oidc").finish();
+    removal.make_removal();
+    match result {
+        Ok(token) => HttpResponse::Found()
+            .insert_header((LOCATION, "/"))
+            .cookie(session_cookie(&token))
+            .cookie(removal)
+            .finish(),
+        Err(e) => HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .cookie(removal)
+            .body(html! {
+                (get_nav(None))
+                ("Error : ") (e)
+                br;
+                a href="/auth/login" { "Back to the login page" }
+            }),
+    }
+}
+
+/// The register form, filled with the previous values and their errors when there are some
+fn register_form(form: Option<&RegisterForm>, token: Option<&str>, error: Option<&RegisterError>) -> Markup {
+    let errors = match error {
+        Some(RegisterError::Validation(errors)) => Some(errors),
+        _ => None,
+    };
+
+    html! {
+        form hx-post="/auth/register" hx-swap="outerHTML" {
+            @if let Some(error) = error.filter(|_| errors.is_none()) {
+                div { ("Error : ") (error) }
+            }
+            input type="text" name="name" placeholder="Name" value=[form.map(|f| f.name.as_str())];
+            br;
+            (field_errors(errors, "name"))
+            input type="email" name="email" placeholder="user@example.com" value=[form.map(|f| f.email.as_str())];
+            br;
+            (field_errors(errors, "email"))
+            input type="password" name="password" placeholder="Password";
+            br;
+            (field_errors(errors, "password"))
+            input type="text" name="token" placeholder="Invitation" value=[token];
+            br;
+            button type="submit" {"Register"}
+        }
+    }
+}
+
+#[post("/register")]
+async fn register(state: web::Data<AppState>, form: web::Form<RegisterForm>) -> Markup {
+    match state.services.auth.register(&form).await {
+        Ok(user) if user.status == UserStatus::Pending => html! {
+            "Your account has been created, an administrator has to approve it before you can log in"
+        },
+        Ok(_) => html! {
+            "Your account has been created, redirecting..."
+            script {"window.location.replace('/auth/login')"}
+        },
+        Err(e) => register_form(Some(&form), form.token.as_deref(), Some(&e)),
+    }
+}
+
+#[get("/register")]
+async fn register_page(session: Option<web::ReqData<Session>>, query: web::Query<RegisterQuery>) -> Markup {
+    html! {
+        (get_nav(session.as_deref()))
+        (register_form(None, query.token.as_deref(), None))
     }
 }
 
@@ -121,4 +261,8 @@ pub fn get_scope() -> Scope {
         .service(login_page)
         .service(login)
         .service(logout)
+        .service(federation_start)
+        .service(federation_callback)
+        .service(register_page)
+        .service(register)
 }
\ No newline at end of file
@@ -0,0 +1,41 @@
+use std::collections::BTreeSet;
+use maud::{html, Markup};
+use crate::app::session::Session;
+use crate::objects::application::Application;
+use crate::services::consent::describe_scope;
+use crate::views::nav::get_nav;
+
+/// The scopes and what each of them shares
+pub fn scope_list(scopes: &BTreeSet<String>) -> Markup {
+    html! {
+        ul {
+            @for scope in scopes {
+                li {
+                    @match describe_scope(scope) {
+                        Some(description) => { (description) " (" code { (scope) } ")" },
+                        None => { "Access to " code { (scope) } },
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Asks the user to share the scopes, the form is posted back to the authorization request
+pub fn consent_page(session: &Session, application: &Application, scopes: &BTreeSet<String>) -> Markup {
+    html! {
+        (get_nav(Some(session)))
+        h1 { (application.name) }
+        p {
+            a href=(application.url) { (application.url) }
+            " would like to access your account " (session.user.email) ":"
+        }
+        (scope_list(scopes))
+        form method="post" {
+            input type="hidden" name="csrf_token" value=(session.csrf_token());
+            button type="submit" name="decision" value="allow" { "Allow" }
+            button type="submit" name="decision" value="deny" { "Deny" }
+        }
+        p { "You can remove this access later from your " a href="/account" { "account" } "." }
+    }
+}
@@ -0,0 +1,14 @@
+use maud::{html, Markup};
+use crate::errors::validation::{EnglishMessages, Messages, ValidationErrors};
+
+/// The errors of one field, shown under its input
+pub fn field_errors(errors: Option<&ValidationErrors>, field: &str) -> Markup {
+    html! {
+        @if let Some(errors) = errors {
+            @for error in errors.for_field(field) {
+                small class="error" { (EnglishMessages.message(error)) }
+                br;
+            }
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use actix_web::{get, post, web, HttpResponse, Scope};
+use actix_web::http::header::ContentType;
+use maud::{html, Markup};
+use crate::app::app_state::AppState;
+use crate::app::session::Session;
+use crate::forms::launcher::{FavoriteForm, LauncherQuery};
+use crate::services::launcher::LauncherEntry;
+use crate::views::nav::get_nav;
+
+fn html_response(content: Markup) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(content)
+}
+
+fn icon(entry: &LauncherEntry) -> Markup {
+    let application = &entry.application;
+    html! {
+        @match &application.icon_url {
+            Some(url) => img src=(url) alt=(application.initials()) width="48" height="48";,
+            None => span class="avatar" { (application.initials()) },
+        }
+    }
+}
+
+/// The applications, replaced as the user searches or changes a favorite
+fn application_list(entries: &[LauncherEntry], search: Option<&str>) -> Markup {
+    html! {
+        div id="applications" {
+            ul {
+                @for entry in entries {
+                    li {
+                        a href=(entry.launch_url) {
+                            (icon(entry)) " " (entry.application.name)
+                        }
+                        form hx-post="/launcher/favorite" hx-target="#applications" hx-swap="outerHTML" hx-include="[name='search']" {
+                            input type="hidden" name="client_id" value=(entry.application.client_id);
+                            @if entry.favorite {
+                                input type="hidden" name="favorite" value="false";
+                                button type="submit" title="Remove from favorites" { "★" }
+                            } @else {
+                                input type="hidden" name="favorite" value="true";
+                                button type="submit" title="Add to favorites" { "☆" }
+                            }
+                        }
+                    }
+                }
+            }
+            @if entries.is_empty() {
+                @match search {
+                    Some(search) => p { "No application matches \"" (search) "\"" },
+                    None => p { "You do not have access to any application yet, ask an administrator" },
+                }
+            }
+        }
+    }
+}
+
+#[get("/")]
+pub async fn home(state: web::Data<AppState>, session: Option<web::ReqData<Session>>) -> HttpResponse {
+    let Some(session) = session else {
+        return html_response(html! {
+            (get_nav(None))
+            p { "Log in to see your applications" }
+        });
+    };
+    let entries = state.services.launcher.applications(&session.user, None).await;
+
+    html_response(html! {
+        (get_nav(Some(&session)))
+        h1 { "Applications" }
+        input type="search" name="search" placeholder="Search"
+            hx-get="/launcher/applications" hx-trigger="input changed delay:200ms, search" hx-target="#applications" hx-swap="outerHTML";
+        (application_list(&entries, None))
+    })
+}
+
+#[get("/applications")]
+async fn search_applications(state: web::Data<AppState>, session: Option<web::ReqData<Session>>, query: web::Query<LauncherQuery>) -> HttpResponse {
+    let Some(session) = session else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let entries = state.services.launcher.applications(&session.user, query.search.as_deref()).await;
+    html_response(application_list(&entries, query.search.as_deref()))
+}
+
+#[post("/favorite")]
+async fn set_favorite(state: web::Data<AppState>, session: Option<web::ReqData<Session>>, form: web::Form<FavoriteForm>) -> HttpResponse {
+    let Some(session) = session else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let launcher = &state.services.launcher;
+    if !launcher.set_favorite(&session.user, &form.client_id, form.favorite).await {
+        return html_response(html! { ("Error : ") "You do not have access to this application" });
+    }
+    let entries = launcher.applications(&session.user, form.search.as_deref()).await;
+    html_response(application_list(&entries, form.search.as_deref()))
+}
+
+pub fn get_scope() -> Scope {
+    web::scope("/launcher")
+        .service(search_applications)
+        .service(set_favorite)
+}
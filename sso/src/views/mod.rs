@@ -1,2 +1,8 @@
 pub mod nav;
-pub mod auth;
\ No newline at end of file
+pub mod account;
+pub mod admin;
+pub mod auth;
+pub mod forms;
+pub mod saml;
+pub mod consent;
+pub mod home;
\ No newline at end of file
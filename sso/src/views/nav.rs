@@ -1,18 +1,22 @@
 use maud::{html, Markup};
-use crate::app::app_state::AppState;
+use crate::app::session::Session;
 
-pub fn get_nav(data: &AppState) -> Markup {
+pub fn get_nav(session: Option<&Session>) -> Markup {
     html! {
         script src="https://unpkg.com/htmx.org@2.0.4" integrity="sha384-HGfztofotfshcF7+8n44JQL2oJmowVChPTg48S+jvZoztPfvwD79OC/LTtG6dMp+" crossorigin="anonymous" {}
         nav {
             a href="/" { "home" }
-            @if data.is_connected() {
+            @if let Some(session) = session {
+                @if session.user.admin {
+                    a href="/admin/users" {"admin"}
+                }
+                a href="/account" {"account"}
                 a href="/auth/logout" {"logout"}
             } @else {
                 a href="/auth/login" {"login"}
-                a {"register"}
+                a href="/auth/register" {"register"}
             }
 
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,88 @@
+use actix_web::{get, web, HttpRequest, HttpResponse, Scope};
+use actix_web::http::header::{ContentType, LOCATION};
+use maud::html;
+use crate::app::app_state::AppState;
+use crate::app::session::Session;
+use crate::errors::saml::SamlError;
+use crate::services::saml::SamlPost;
+use crate::views::auth::login_redirect;
+use crate::views::nav::get_nav;
+
+fn error_response(session: Option<&Session>, error: SamlError) -> HttpResponse {
+    let mut builder = match error {
+        SamlError::Disabled | SamlError::UnknownApplication => HttpResponse::NotFound(),
+        SamlError::AccessDenied => HttpResponse::Forbidden(),
+        _ => HttpResponse::BadRequest(),
+    };
+    builder.content_type(ContentType::html())
+        .body(html! {
+            (get_nav(session))
+            ("Error : ") (error)
+        })
+}
+
+/// The HTTP-POST binding, the browser submits the assertion to the application
+fn post_response(post: SamlPost) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html! {
+            form method="post" action=(post.acs_url) {
+                input type="hidden" name="SAMLResponse" value=(post.response);
+                @if let Some(relay_state) = &post.relay_state {
+                    input type="hidden" name="RelayState" value=(relay_state);
+                }
+                noscript { button type="submit" { "Continue" } }
+            }
+            script { "document.forms[0].submit()" }
+        })
+}
+
+#[get("/metadata")]
+async fn metadata(state: web::Data<AppState>) -> HttpResponse {
+    match state.services.saml.metadata() {
+        Ok(metadata) => HttpResponse::Ok()
+            .content_type("application/samlmetadata+xml")
+            .body(metadata),
+        Err(e) => error_response(None, e),
+    }
+}
+
+#[get("/sso")]
+async fn single_sign_on(state: web::Data<AppState>, req: HttpRequest, session: Option<web::ReqData<Session>>) -> HttpResponse {
+    let Some(session) = session else {
+        return login_redirect(&req);
+    };
+    match state.services.saml.single_sign_on(&session.user, &session.token, req.query_string()).await {
+        Ok(post) => post_response(post),
+        Err(e) => error_response(Some(&session), e),
+    }
+}
+
+/// Log in an application from this server, without a request from the application
+#[get("/init/{client_id}")]
+async fn idp_initiated(state: web::Data<AppState>, req: HttpRequest, session: Option<web::ReqData<Session>>, client_id: web::Path<String>) -> HttpResponse {
+    let Some(session) = session else {
+        return login_redirect(&req);
+    };
+    match state.services.saml.idp_initiated(&session.user, &session.token, &client_id).await {
+        Ok(post) => post_response(post),
+        Err(e) => error_response(Some(&session), e),
+    }
+}
+
+#[get("/slo")]
+async fn single_logout(state: web::Data<AppState>, req: HttpRequest, session: Option<web::ReqData<Session>>) -> HttpResponse {
+    let current = session.as_ref().map(|session| (&session.user, session.token.as_str()));
+    match state.services.saml.single_logout(current, req.query_string()).await {
+        Ok(url) => HttpResponse::Found().insert_header((LOCATION, url)).finish(),
+        Err(e) => error_response(session.as_deref(), e),
+    }
+}
+
+pub fn get_scope() -> Scope {
+    web::scope("/saml")
+        .service(metadata)
+        .service(single_sign_on)
+        .service(idp_initiated)
+        .service(single_logout)
+}